@@ -0,0 +1,7 @@
+-- Add up migration script here
+CREATE TABLE credentials (
+    user_id VARCHAR PRIMARY KEY NOT NULL,
+    password_hash VARCHAR NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX refresh_tokens_family_id;
+DROP TABLE refresh_tokens;
@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE refresh_tokens (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    family_id VARCHAR NOT NULL,
+    user_id VARCHAR NOT NULL,
+    tenant_id VARCHAR NOT NULL,
+    token_hash VARCHAR NOT NULL,
+    created_at DATETIME NOT NULL,
+    expires_at DATETIME NOT NULL,
+    used_at DATETIME,
+    revoked_at DATETIME,
+    UNIQUE(token_hash),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX refresh_tokens_family_id ON refresh_tokens(family_id);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE identity_authorizations (
+    state VARCHAR PRIMARY KEY NOT NULL,
+    nonce VARCHAR NOT NULL,
+    code_verifier VARCHAR NOT NULL,
+    tenant_id VARCHAR NOT NULL,
+    created_at DATETIME NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE memberships (
+    user_id VARCHAR NOT NULL,
+    tenant_id VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    joined_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, tenant_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(tenant_id) REFERENCES tenants(id) ON DELETE CASCADE
+);
+
+CREATE INDEX memberships_tenant_id_idx ON memberships (tenant_id);
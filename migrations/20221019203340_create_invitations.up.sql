@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE invitations (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    tenant_id VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    invited_by VARCHAR,
+    created_at DATETIME NOT NULL,
+    expires_at DATETIME NOT NULL,
+    accepted_at DATETIME,
+    revoked_at DATETIME,
+    FOREIGN KEY(tenant_id) REFERENCES tenants(id) ON DELETE CASCADE,
+    FOREIGN KEY(invited_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX invitations_tenant_id_email_idx ON invitations (tenant_id, email);
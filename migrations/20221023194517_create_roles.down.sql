@@ -0,0 +1,9 @@
+-- Add down migration script here
+INSERT OR IGNORE INTO permissions (user_id, action, resource_id, resource_kind)
+SELECT role_assignments.user_id, role_permissions.action, role_assignments.resource_id, role_assignments.resource_kind
+    FROM role_assignments
+    JOIN role_permissions ON role_permissions.role_id = role_assignments.role_id;
+
+DROP TABLE role_assignments;
+DROP TABLE role_permissions;
+DROP TABLE roles;
@@ -0,0 +1,57 @@
+-- Add up migration script here
+CREATE TABLE roles (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    tenant_id VARCHAR,
+    name VARCHAR NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY(tenant_id) REFERENCES tenants(id) ON DELETE CASCADE
+);
+
+-- global roles have no tenant, and NULLs are distinct in a regular unique index
+CREATE UNIQUE INDEX roles_tenant_id_name_idx ON roles (COALESCE(tenant_id, ''), name);
+
+CREATE TABLE role_permissions (
+    role_id VARCHAR NOT NULL,
+    action VARCHAR NOT NULL,
+    PRIMARY KEY (role_id, action),
+    FOREIGN KEY(role_id) REFERENCES roles(id) ON DELETE CASCADE
+);
+
+CREATE TABLE role_assignments (
+    role_id VARCHAR NOT NULL,
+    user_id VARCHAR NOT NULL,
+    resource_id VARCHAR NOT NULL,
+    resource_kind VARCHAR NOT NULL,
+    PRIMARY KEY (role_id, user_id, resource_id, resource_kind),
+    FOREIGN KEY(role_id) REFERENCES roles(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX role_assignments_user_id_idx ON role_assignments (user_id, resource_id, resource_kind);
+
+-- the built-in membership roles, which replace the grants previously inserted for each member
+INSERT INTO roles (id, tenant_id, name, created_at) VALUES
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', NULL, 'owner', datetime('now')),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', NULL, 'admin', datetime('now')),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a03', NULL, 'member', datetime('now'));
+
+INSERT INTO role_permissions (role_id, action) VALUES
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'read-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'write-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'execute-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', 'read-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', 'write-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a03', 'read-tenant');
+
+INSERT INTO role_assignments (role_id, user_id, resource_id, resource_kind)
+SELECT roles.id, memberships.user_id, memberships.tenant_id, 'tenant'
+    FROM memberships
+    JOIN roles ON roles.tenant_id IS NULL AND roles.name = memberships.role;
+
+DELETE FROM permissions
+    WHERE resource_kind = 'tenant'
+    AND EXISTS (
+        SELECT 1 FROM memberships
+            WHERE memberships.user_id = permissions.user_id
+            AND memberships.tenant_id = permissions.resource_id
+    );
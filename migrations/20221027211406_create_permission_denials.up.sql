@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE permission_denials (
+    user_id VARCHAR NOT NULL,
+    action VARCHAR NOT NULL,
+    resource_id VARCHAR NOT NULL,
+    resource_kind VARCHAR NOT NULL,
+    reason VARCHAR,
+    denied_by VARCHAR,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, action, resource_id, resource_kind),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(denied_by) REFERENCES users(id) ON DELETE SET NULL
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE permissions ADD COLUMN condition TEXT;
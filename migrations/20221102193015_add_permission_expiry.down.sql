@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX permissions_expires_at_idx;
+ALTER TABLE permissions DROP COLUMN expires_at;
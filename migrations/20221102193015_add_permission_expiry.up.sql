@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE permissions ADD COLUMN expires_at DATETIME;
+CREATE INDEX permissions_expires_at_idx ON permissions (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DELETE FROM permissions WHERE action = 'read-permissions';
+
+DELETE FROM role_permissions
+    WHERE action = 'read-permissions'
+    AND role_id IN ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', '6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02');
@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- owners and admins can see what the members of their tenant can do
+INSERT INTO role_permissions (role_id, action) VALUES
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'read-permissions'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', 'read-permissions');
+
+-- and every user can see what they can do themselves
+INSERT OR IGNORE INTO permissions (user_id, action, resource_id, resource_kind)
+SELECT id, 'read-permissions', id, 'user' FROM users;
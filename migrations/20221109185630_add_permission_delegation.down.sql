@@ -0,0 +1,6 @@
+-- Add down migration script here
+DELETE FROM role_permissions WHERE action LIKE 'execute-grant-%';
+
+DROP INDEX permissions_granted_by_idx;
+ALTER TABLE permissions DROP COLUMN granted_by;
+ALTER TABLE permissions DROP COLUMN delegable;
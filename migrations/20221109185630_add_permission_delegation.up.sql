@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE permissions ADD COLUMN delegable BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE permissions ADD COLUMN granted_by VARCHAR REFERENCES users(id) ON DELETE SET NULL;
+CREATE INDEX permissions_granted_by_idx ON permissions (granted_by) WHERE granted_by IS NOT NULL;
+
+-- only owners can delegate what they hold within their tenant to others
+INSERT INTO role_permissions (role_id, action) VALUES
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'execute-grant-tenant'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'execute-grant-user'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'execute-grant-permissions');
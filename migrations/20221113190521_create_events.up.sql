@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE events (
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    event TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE event_cursors (
+    handler VARCHAR PRIMARY KEY NOT NULL,
+    sequence INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL
+);
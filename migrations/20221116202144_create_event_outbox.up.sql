@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE event_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    delivered_at DATETIME
+);
+CREATE INDEX event_outbox_undelivered_idx ON event_outbox (id) WHERE delivered_at IS NULL;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE event_dead_letters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    handler VARCHAR NOT NULL,
+    sequence INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at DATETIME NOT NULL,
+    redriven_at DATETIME,
+    FOREIGN KEY(sequence) REFERENCES events(sequence)
+);
+CREATE INDEX event_dead_letters_pending_idx ON event_dead_letters (handler, id) WHERE redriven_at IS NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DELETE FROM role_permissions WHERE action IN ('read-webhooks', 'write-webhooks');
+DROP TABLE webhook_deliveries;
+DROP TABLE webhooks;
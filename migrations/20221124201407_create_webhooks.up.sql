@@ -0,0 +1,39 @@
+-- Add up migration script here
+CREATE TABLE webhooks (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    tenant_id VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    event_types TEXT NOT NULL,
+    created_by VARCHAR,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY(tenant_id) REFERENCES tenants(id) ON DELETE CASCADE,
+    FOREIGN KEY(created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX webhooks_tenant_id_idx ON webhooks (tenant_id);
+
+CREATE TABLE webhook_deliveries (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    webhook_id VARCHAR NOT NULL,
+    event_id VARCHAR NOT NULL,
+    event_type VARCHAR NOT NULL,
+    payload TEXT NOT NULL,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER,
+    error TEXT,
+    succeeded BOOLEAN NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    attempted_at DATETIME NOT NULL,
+    FOREIGN KEY(webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, attempted_at);
+CREATE INDEX webhook_deliveries_event_id_idx ON webhook_deliveries (webhook_id, event_id);
+
+-- owners and admins manage the webhooks of their tenant
+INSERT INTO role_permissions (role_id, action) VALUES
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'read-webhooks'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a01', 'write-webhooks'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', 'read-webhooks'),
+    ('6f0b5f8e-5c1a-4c39-9a0e-3f1d2b7c8a02', 'write-webhooks');
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE webhook_deliveries ADD COLUMN next_attempt_at DATETIME;
+
+CREATE INDEX webhook_deliveries_next_attempt_at_idx ON webhook_deliveries (next_attempt_at);
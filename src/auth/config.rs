@@ -0,0 +1,89 @@
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use std::{env, fs};
+
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub secret: Vec<u8>,
+    pub ttl: chrono::Duration,
+    /// Whether the session cookie is only sent over https, which may only be turned off for local
+    /// development over plain http.
+    pub secure_cookie: bool,
+}
+
+impl SessionConfig {
+    pub fn from_env() -> Self {
+        let secret = env::var("SESSION_SECRET")
+            .unwrap_or_else(|_| panic!("SESSION_SECRET env var must be defined"));
+
+        Self {
+            secret: secret.into_bytes(),
+            ttl: chrono::Duration::seconds(env_i64("SESSION_TTL_SECONDS", 1_209_600)),
+            secure_cookie: env_bool("SESSION_COOKIE_SECURE", true),
+        }
+    }
+}
+
+/// Keys and lifetimes used to issue stateless access tokens and their refresh tokens.
+#[derive(Clone)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    pub issuer: String,
+    pub access_ttl: chrono::Duration,
+    pub refresh_ttl: chrono::Duration,
+}
+
+impl JwtConfig {
+    pub fn from_env() -> Self {
+        let algorithm = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+        let (algorithm, encoding_key, decoding_key) = match algorithm.as_str() {
+            "HS256" => {
+                let secret = env::var("JWT_SECRET")
+                    .unwrap_or_else(|_| panic!("JWT_SECRET env var must be defined for HS256"));
+                (
+                    Algorithm::HS256,
+                    EncodingKey::from_secret(secret.as_bytes()),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                )
+            }
+            "EdDSA" => {
+                let private_key = read_key("JWT_PRIVATE_KEY_PATH");
+                let public_key = read_key("JWT_PUBLIC_KEY_PATH");
+                (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(&private_key).unwrap_or_else(|e| panic!("{}", e)),
+                    DecodingKey::from_ed_pem(&public_key).unwrap_or_else(|e| panic!("{}", e)),
+                )
+            }
+            alg => panic!("unsupported JWT_ALGORITHM `{alg}`; expected HS256 or EdDSA"),
+        };
+
+        Self {
+            algorithm,
+            encoding_key,
+            decoding_key,
+            issuer: env::var("JWT_ISSUER")
+                .unwrap_or_else(|_| "rust-rocket-webapp-boilerplate".to_string()),
+            access_ttl: chrono::Duration::seconds(env_i64("JWT_ACCESS_TTL_SECONDS", 900)),
+            refresh_ttl: chrono::Duration::seconds(env_i64("JWT_REFRESH_TTL_SECONDS", 2_592_000)),
+        }
+    }
+}
+
+fn read_key(var: &str) -> Vec<u8> {
+    let path = env::var(var).unwrap_or_else(|_| panic!("{var} env var must be defined for EdDSA"));
+    fs::read(&path).unwrap_or_else(|_| panic!("to read key file @ {path}"))
+}
+
+fn env_i64(var: &str, default: i64) -> i64 {
+    env::var(var).map_or(default, |v| {
+        v.parse::<i64>().unwrap_or_else(|e| panic!("{}", e))
+    })
+}
+
+fn env_bool(var: &str, default: bool) -> bool {
+    env::var(var).map_or(default, |v| {
+        v.parse::<bool>().unwrap_or_else(|e| panic!("{}", e))
+    })
+}
@@ -0,0 +1,120 @@
+use crate::auth::{
+    self,
+    config::{JwtConfig, SessionConfig},
+    jwt, token,
+    types::SessionId,
+};
+use crate::tenants::types::TenantId;
+use crate::users::types::UserId;
+
+use std::convert::TryFrom;
+
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// The user making the current request, as identified by their session or access token.
+///
+/// Tokens are read from an `Authorization: Bearer <token>` header first, falling back to the
+/// session cookie set on sign in. Bearer tokens may either be session tokens or JWT access
+/// tokens; `session_id` is only present for the former and `tenant_id` only for the latter.
+/// Any route taking this guard responds with `401 Unauthorized` when no valid, unexpired
+/// token is presented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedUser {
+    pub user_id: UserId,
+    pub session_id: Option<SessionId>,
+    pub tenant_id: Option<TenantId>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum AuthenticationError {
+    #[error("missing session token")]
+    Missing,
+
+    #[error("invalid or expired session token")]
+    Invalid,
+
+    #[error("authentication state is not managed by rocket")]
+    Unconfigured,
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+fn bearer_token(request: &Request<'_>) -> Option<String> {
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = AuthenticationError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (Some(config), Some(jwt_config), Some(pool)) = (
+            request.rocket().state::<SessionConfig>(),
+            request.rocket().state::<JwtConfig>(),
+            request.rocket().state::<SqlitePool>(),
+        ) else {
+            return Outcome::Failure((
+                Status::InternalServerError,
+                AuthenticationError::Unconfigured,
+            ));
+        };
+
+        let Some(token) = bearer_token(request).or_else(|| {
+            request
+                .cookies()
+                .get(SESSION_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        }) else {
+            return Outcome::Failure((Status::Unauthorized, AuthenticationError::Missing));
+        };
+
+        if jwt::is_jwt(&token) {
+            return match jwt::decode(jwt_config, &token) {
+                Ok(claims) => match (
+                    UserId::try_from(claims.sub.as_str()),
+                    TenantId::try_from(claims.tid.as_str()),
+                ) {
+                    (Ok(user_id), Ok(tenant_id)) => Outcome::Success(Self {
+                        user_id,
+                        session_id: None,
+                        tenant_id: Some(tenant_id),
+                        permissions: claims.perms,
+                    }),
+                    _ => Outcome::Failure((Status::Unauthorized, AuthenticationError::Invalid)),
+                },
+                Err(_) => Outcome::Failure((Status::Unauthorized, AuthenticationError::Invalid)),
+            };
+        }
+
+        let Some(token_hash) = token::verify(&config.secret, &token) else {
+            return Outcome::Failure((Status::Unauthorized, AuthenticationError::Invalid));
+        };
+
+        match auth::sqlite::find_active_session(pool, &token_hash).await {
+            Ok(Some(session)) => Outcome::Success(Self {
+                user_id: session.user_id,
+                session_id: Some(session.id),
+                tenant_id: None,
+                permissions: vec![],
+            }),
+            Ok(None) => Outcome::Failure((Status::Unauthorized, AuthenticationError::Invalid)),
+            Err(err) => {
+                Outcome::Failure((Status::InternalServerError, AuthenticationError::Sqlx(err)))
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::auth::identity::{Identity, IdentityError, IdentityProvider, PendingAuthorization};
+
+use color_eyre::eyre;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+pub const LOCAL_ISSUER: &str = "local";
+
+#[derive(Debug, Clone, Deserialize)]
+struct LocalIdentity {
+    subject: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+/// A fake identity provider backed by a JSON file, for local development and tests.
+///
+/// There is no login page: the authorization url redirects straight back to the callback with
+/// the matching identity's subject as the code, so signing in as someone is as simple as passing
+/// their email as the login hint.
+pub struct LocalIdentityProvider {
+    path: PathBuf,
+    redirect_url: String,
+}
+
+impl LocalIdentityProvider {
+    pub fn new(path: &str, redirect_url: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            redirect_url: redirect_url.to_string(),
+        }
+    }
+
+    async fn identities(&self) -> eyre::Result<Vec<LocalIdentity>, IdentityError> {
+        // read on every request so the file can be edited without restarting the server
+        let contents = tokio::fs::read(&self.path).await?;
+
+        Ok(serde_json::from_slice(&contents)?)
+    }
+}
+
+#[rocket::async_trait]
+impl IdentityProvider for LocalIdentityProvider {
+    fn name(&self) -> &str {
+        LOCAL_ISSUER
+    }
+
+    async fn authorization_url(
+        &self,
+        pending: &PendingAuthorization,
+        login_hint: Option<&str>,
+    ) -> eyre::Result<String, IdentityError> {
+        let identities = self.identities().await?;
+        let identity = login_hint
+            .map_or_else(
+                || identities.first(),
+                |hint| {
+                    identities
+                        .iter()
+                        .find(|i| i.email.as_deref() == Some(hint) || i.subject == hint)
+                },
+            )
+            .ok_or_else(|| {
+                IdentityError::UnknownIdentity(login_hint.unwrap_or_default().to_string())
+            })?;
+
+        let mut url = reqwest::Url::parse(&self.redirect_url)
+            .map_err(|e| IdentityError::Config(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("code", &identity.subject)
+            .append_pair("state", &pending.state);
+
+        Ok(url.to_string())
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        _pending: &PendingAuthorization,
+    ) -> eyre::Result<Identity, IdentityError> {
+        let identity = self
+            .identities()
+            .await?
+            .into_iter()
+            .find(|i| i.subject == code)
+            .ok_or_else(|| IdentityError::UnknownIdentity(code.to_string()))?;
+
+        Ok(Identity {
+            issuer: LOCAL_ISSUER.to_string(),
+            subject: identity.subject,
+            email: identity.email,
+            email_verified: identity.email_verified,
+        })
+    }
+}
@@ -0,0 +1,161 @@
+mod local;
+mod oidc;
+
+pub use local::LocalIdentityProvider;
+pub use oidc::OpenIdConnectProvider;
+
+use color_eyre::eyre;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Issuer recorded in `auth_id` for users who sign up with an email and password rather than
+/// through an external identity provider.
+pub const PASSWORD_ISSUER: &str = "password";
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("identity provider is misconfigured: {0}")]
+    Config(String),
+
+    #[error("identity provider rejected the request: {0}")]
+    Rejected(String),
+
+    #[error("id token failed validation")]
+    InvalidIdToken(#[from] jsonwebtoken::errors::Error),
+
+    #[error("identity `{0}` is unknown to the provider")]
+    UnknownIdentity(String),
+
+    #[error("failed to communicate with the identity provider")]
+    Http(#[from] reqwest::Error),
+
+    #[error("failed to read local identities")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse local identities")]
+    Json(#[from] serde_json::Error),
+}
+
+/// An identity asserted by a provider once a user has authenticated with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+impl Identity {
+    /// The stable identifier stored as `users.auth_id`.
+    ///
+    /// Subjects are only unique per issuer, so both are included.
+    pub fn auth_id(&self) -> String {
+        format!("{}|{}", self.issuer, self.subject)
+    }
+
+    pub fn password() -> Self {
+        Self {
+            issuer: PASSWORD_ISSUER.to_string(),
+            subject: uuid::Uuid::new_v4().to_string(),
+            email: None,
+            email_verified: false,
+        }
+    }
+}
+
+/// Values generated when a sign in starts which must be presented again to complete it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingAuthorization {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl PendingAuthorization {
+    pub fn new() -> Self {
+        Self {
+            state: random_string(),
+            nonce: random_string(),
+            code_verifier: random_string(),
+        }
+    }
+
+    /// The S256 PKCE challenge derived from the verifier.
+    pub fn code_challenge(&self) -> String {
+        base64::encode_config(
+            Sha256::digest(self.code_verifier.as_bytes()),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+}
+
+impl Default for PendingAuthorization {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn random_string() -> String {
+    let mut bytes = [0_u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// An external service which authenticates users on our behalf, such as an OIDC provider.
+///
+/// Sign in is a two step authorization-code flow: users are redirected to the
+/// `authorization_url`, and the provider redirects them back with a code which is exchanged
+/// for their `Identity`.
+#[rocket::async_trait]
+pub trait IdentityProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn authorization_url(
+        &self,
+        pending: &PendingAuthorization,
+        login_hint: Option<&str>,
+    ) -> eyre::Result<String, IdentityError>;
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pending: &PendingAuthorization,
+    ) -> eyre::Result<Identity, IdentityError>;
+}
+
+/// Builds the identity provider selected by the `IDENTITY_PROVIDER` env var.
+///
+/// There's no default, as the local provider signs in without a password and must only ever be
+/// chosen deliberately.
+pub fn from_env() -> Arc<dyn IdentityProvider> {
+    let redirect_url = env::var("IDENTITY_REDIRECT_URL")
+        .unwrap_or_else(|_| "http://127.0.0.1:8000/api/auth/identity/callback".to_string());
+
+    match env::var("IDENTITY_PROVIDER")
+        .unwrap_or_else(|_| panic!("IDENTITY_PROVIDER env var must be defined"))
+        .as_str()
+    {
+        "oidc" => Arc::new(OpenIdConnectProvider::new(
+            &env::var("OIDC_ISSUER_URL")
+                .unwrap_or_else(|_| panic!("OIDC_ISSUER_URL env var must be defined")),
+            &env::var("OIDC_CLIENT_ID")
+                .unwrap_or_else(|_| panic!("OIDC_CLIENT_ID env var must be defined")),
+            env::var("OIDC_CLIENT_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .as_deref(),
+            &redirect_url,
+        )),
+        "local" => Arc::new(LocalIdentityProvider::new(
+            &env::var("LOCAL_IDENTITY_FILE")
+                .unwrap_or_else(|_| "identities.example.json".to_string()),
+            &redirect_url,
+        )),
+        provider => panic!("unsupported IDENTITY_PROVIDER `{provider}`; expected oidc or local"),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::auth::identity::{Identity, IdentityError, IdentityProvider, PendingAuthorization};
+
+use color_eyre::eyre;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+/// Algorithms an id token may be signed with; symmetric algorithms are refused since the
+/// client secret would then be enough to forge tokens.
+const ALLOWED_ALGORITHMS: [Algorithm; 7] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+/// An OIDC provider using the authorization-code flow with PKCE.
+///
+/// The provider's discovery document and signing keys are fetched lazily and cached; the keys
+/// are refetched whenever an id token references a key id we haven't seen, which handles
+/// provider key rotation.
+pub struct OpenIdConnectProvider {
+    issuer_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    http: reqwest::Client,
+    discovery: RwLock<Option<Discovery>>,
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+impl OpenIdConnectProvider {
+    pub fn new(
+        issuer_url: &str,
+        client_id: &str,
+        client_secret: Option<&str>,
+        redirect_url: &str,
+    ) -> Self {
+        Self {
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.map(ToString::to_string),
+            redirect_url: redirect_url.to_string(),
+            http: reqwest::Client::new(),
+            discovery: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    async fn discovery(&self) -> eyre::Result<Discovery, IdentityError> {
+        if let Some(discovery) = self.discovery.read().await.as_ref() {
+            return Ok(discovery.clone());
+        }
+
+        let discovery: Discovery = self
+            .http
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                self.issuer_url
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if discovery.issuer.trim_end_matches('/') != self.issuer_url {
+            return Err(IdentityError::Config(format!(
+                "discovery document issuer `{}` does not match `{}`",
+                discovery.issuer, self.issuer_url
+            )));
+        }
+
+        *self.discovery.write().await = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    async fn decoding_key(
+        &self,
+        discovery: &Discovery,
+        kid: &str,
+    ) -> eyre::Result<DecodingKey, IdentityError> {
+        if let Some(jwk) = self
+            .jwks
+            .read()
+            .await
+            .as_ref()
+            .and_then(|jwks| jwks.find(kid))
+        {
+            return Ok(DecodingKey::from_jwk(jwk)?);
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let key = jwks
+            .find(kid)
+            .ok_or_else(|| IdentityError::Rejected(format!("unknown signing key `{kid}`")))
+            .and_then(|jwk| Ok(DecodingKey::from_jwk(jwk)?));
+
+        *self.jwks.write().await = Some(jwks);
+        key
+    }
+
+    async fn validate_id_token(
+        &self,
+        discovery: &Discovery,
+        id_token: &str,
+        pending: &PendingAuthorization,
+    ) -> eyre::Result<IdTokenClaims, IdentityError> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(IdentityError::Rejected(format!(
+                "id token signed with disallowed algorithm {:?}",
+                header.alg
+            )));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| IdentityError::Rejected("id token is missing a key id".to_string()))?;
+        let key = self.decoding_key(discovery, &kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)?.claims;
+
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err(IdentityError::Rejected(
+                "id token nonce mismatch".to_string(),
+            ));
+        }
+
+        Ok(claims)
+    }
+}
+
+#[rocket::async_trait]
+impl IdentityProvider for OpenIdConnectProvider {
+    fn name(&self) -> &str {
+        &self.issuer_url
+    }
+
+    async fn authorization_url(
+        &self,
+        pending: &PendingAuthorization,
+        login_hint: Option<&str>,
+    ) -> eyre::Result<String, IdentityError> {
+        let discovery = self.discovery().await?;
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)
+            .map_err(|e| IdentityError::Config(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", "openid email profile")
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &pending.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+        if let Some(hint) = login_hint {
+            url.query_pairs_mut().append_pair("login_hint", hint);
+        }
+
+        Ok(url.to_string())
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pending: &PendingAuthorization,
+    ) -> eyre::Result<Identity, IdentityError> {
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_url.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self
+            .http
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(IdentityError::Rejected(format!(
+                "token endpoint responded with {status}: {body}"
+            )));
+        }
+        let tokens: TokenResponse = response.json().await?;
+
+        let claims = self
+            .validate_id_token(&discovery, &tokens.id_token, pending)
+            .await?;
+
+        Ok(Identity {
+            issuer: discovery.issuer,
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use crate::auth::config::JwtConfig;
+use crate::tenants::types::TenantId;
+use crate::types::uuid::Uuid;
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use jsonwebtoken::{Header, Validation};
+use serde::{Deserialize, Serialize};
+
+/// Claims carried by an access token.
+///
+/// `perms` is a snapshot of the user's grants at the time the token was issued, formatted as
+/// `action:resource_id:resource_kind`; it is only as fresh as the token's lifetime allows.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Claims {
+    pub sub: String,
+    pub tid: String,
+    pub perms: Vec<String>,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+impl Claims {
+    pub fn new(
+        config: &JwtConfig,
+        user_id: &UserId,
+        tenant_id: &TenantId,
+        perms: Vec<String>,
+    ) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            sub: user_id.to_string(),
+            tid: tenant_id.to_string(),
+            perms,
+            iss: config.issuer.clone(),
+            iat: now.timestamp(),
+            exp: (now + config.access_ttl).timestamp(),
+            jti: Uuid::new().to_string(),
+        }
+    }
+}
+
+pub fn encode(
+    config: &JwtConfig,
+    claims: &Claims,
+) -> eyre::Result<String, jsonwebtoken::errors::Error> {
+    jsonwebtoken::encode(&Header::new(config.algorithm), claims, &config.encoding_key)
+}
+
+/// Decodes an access token, validating its signature, algorithm, issuer and expiry.
+pub fn decode(
+    config: &JwtConfig,
+    token: &str,
+) -> eyre::Result<Claims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::new(config.algorithm);
+    validation.set_issuer(&[&config.issuer]);
+
+    jsonwebtoken::decode::<Claims>(token, &config.decoding_key, &validation).map(|data| data.claims)
+}
+
+/// Access tokens have three segments while session tokens only have two, which lets a bearer
+/// header carry either kind.
+pub fn is_jwt(token: &str) -> bool {
+    token.matches('.').count() == 2
+}
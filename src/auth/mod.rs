@@ -1,4 +1,17 @@
+mod config;
+mod guards;
+mod identity;
+mod jwt;
+mod password;
 mod routes;
 mod service;
+mod sqlite;
+mod token;
+mod types;
 
+pub use config::{JwtConfig, SessionConfig};
+pub use guards::{AuthenticatedUser, AuthenticationError};
+pub use identity::{from_env as identity_provider_from_env, IdentityProvider};
 pub use routes::routes;
+pub use service::{hash_new_password, insert_password_user, SignUpError};
+pub use token::{generate as generate_token, hash as hash_token, verify as verify_token};
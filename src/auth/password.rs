@@ -0,0 +1,230 @@
+use argon2::{
+    password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use color_eyre::eyre;
+use rand_core::OsRng;
+use std::env;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum PasswordError {
+    #[error("invalid password hashing configuration: {0}")]
+    Config(String),
+
+    #[error("failed to hash or verify password")]
+    Hash(#[from] password_hash::Error),
+
+    #[error("password hashing task failed to complete")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// The password matched and the stored hash uses the configured parameters.
+    Valid,
+    /// The password matched, but the stored hash was produced with outdated parameters
+    /// and should be replaced with a fresh hash.
+    ValidNeedsRehash,
+    Invalid,
+}
+
+/// Reads the argon2id cost parameters from the environment, falling back to the
+/// OWASP recommended minimums when a variable is not set.
+pub fn params() -> eyre::Result<Params, PasswordError> {
+    let memory = env_u32("PASSWORD_HASH_MEMORY_KIB", Params::DEFAULT_M_COST)?;
+    let iterations = env_u32("PASSWORD_HASH_ITERATIONS", Params::DEFAULT_T_COST)?;
+    let parallelism = env_u32("PASSWORD_HASH_PARALLELISM", Params::DEFAULT_P_COST)?;
+
+    Params::new(memory, iterations, parallelism, None)
+        .map_err(|e| PasswordError::Config(e.to_string()))
+}
+
+fn env_u32(key: &str, default: u32) -> eyre::Result<u32, PasswordError> {
+    env::var(key).map_or(Ok(default), |v| {
+        v.parse::<u32>()
+            .map_err(|e| PasswordError::Config(format!("{key}: {e}")))
+    })
+}
+
+fn hasher(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Hashes a password with argon2id into a PHC formatted string.
+///
+/// Hashing is intentionally expensive, so it is executed on tokio's blocking thread pool.
+pub async fn hash(password: &str) -> eyre::Result<String, PasswordError> {
+    let params = params()?;
+    let password = password.to_string();
+
+    tokio::task::spawn_blocking(move || hash_blocking(&password, params)).await?
+}
+
+fn hash_blocking(password: &str, params: Params) -> eyre::Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = hasher(params).hash_password(password.as_bytes(), &salt)?;
+
+    Ok(hash.to_string())
+}
+
+/// Verifies a password against a stored PHC string in constant time.
+pub async fn verify(password: &str, hash: &str) -> eyre::Result<Verification, PasswordError> {
+    let params = params()?;
+    let password = password.to_string();
+    let hash = hash.to_string();
+
+    tokio::task::spawn_blocking(move || verify_blocking(&password, &hash, &params)).await?
+}
+
+/// Burns the same amount of work as a real verification so that callers can't
+/// distinguish an unknown account from a wrong password by response time.
+pub async fn verify_dummy(password: &str) -> eyre::Result<(), PasswordError> {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+    let params = params()?;
+    let password = password.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let hash = if let Some(hash) = DUMMY_HASH.get() {
+            hash.clone()
+        } else {
+            let hash = hash_blocking("dummy-password", params.clone())?;
+            DUMMY_HASH.get_or_init(|| hash).clone()
+        };
+        verify_blocking(&password, &hash, &params).map(|_| ())
+    })
+    .await?
+}
+
+fn verify_blocking(
+    password: &str,
+    hash: &str,
+    params: &Params,
+) -> eyre::Result<Verification, PasswordError> {
+    let parsed = PasswordHash::new(hash)?;
+    // the hash is verified with the parameters it was created with, not the configured ones
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => {}
+        Err(password_hash::Error::Password) => return Ok(Verification::Invalid),
+        Err(e) => return Err(PasswordError::Hash(e)),
+    }
+
+    let outdated = parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+        || Params::try_from(&parsed).map_or(true, |stored| {
+            stored.m_cost() != params.m_cost()
+                || stored.t_cost() != params.t_cost()
+                || stored.p_cost() != params.p_cost()
+        });
+
+    if outdated {
+        Ok(Verification::ValidNeedsRehash)
+    } else {
+        Ok(Verification::Valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth;
+    use crate::tenants;
+    use crate::types::sqlite::test_pool;
+
+    /// Cheaper than the configured parameters, as a hash made before they were raised would be.
+    fn outdated_params() -> Params {
+        Params::new(Params::MIN_M_COST, 1, 1, None).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[rocket::async_test]
+    async fn verifies_the_password_it_hashed_and_rejects_others() -> eyre::Result<()> {
+        let hash = hash("correct horse battery").await?;
+
+        assert_eq!(
+            verify("correct horse battery", &hash).await?,
+            Verification::Valid
+        );
+        assert_eq!(
+            verify("correct horse battery staple", &hash).await?,
+            Verification::Invalid
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_with_outdated_parameters_need_rehashing() -> eyre::Result<()> {
+        let params = params()?;
+        let outdated = hash_blocking("correct horse battery", outdated_params())?;
+
+        assert_eq!(
+            verify_blocking("correct horse battery", &outdated, &params)?,
+            Verification::ValidNeedsRehash
+        );
+        assert_eq!(
+            verify_blocking("correct horse battery", &outdated, &outdated_params())?,
+            Verification::Valid
+        );
+        assert_eq!(
+            verify_blocking("wrong password", &outdated, &params)?,
+            Verification::Invalid
+        );
+
+        Ok(())
+    }
+
+    #[rocket::async_test]
+    async fn signing_in_rehashes_passwords_with_outdated_parameters() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let outdated = hash_blocking("correct horse battery", outdated_params())?;
+        let mut tx = pool.begin().await?;
+        let user = auth::service::insert_password_user(
+            &mut tx,
+            "a@example.com",
+            &outdated,
+            tenant.id,
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+
+        let mut request = auth::service::AuthRequest {
+            email: "a@example.com".to_string(),
+            password: "wrong password".to_string(),
+            tenant_id: tenant.id.to_string(),
+        };
+        assert!(matches!(
+            auth::service::sign_in(&pool, &request).await,
+            Err(auth::service::SignInError::InvalidCredentials)
+        ));
+        request.password = "correct horse battery".to_string();
+        auth::service::sign_in(&pool, &request).await?;
+
+        let credential = auth::sqlite::find_credential(&pool, &user.id)
+            .await?
+            .ok_or_else(|| eyre::eyre!("no credential"))?;
+        assert_ne!(credential.password_hash, outdated);
+        assert_eq!(
+            verify_blocking(
+                "correct horse battery",
+                &credential.password_hash,
+                &params()?
+            )?,
+            Verification::Valid
+        );
+
+        // an unknown email is rejected like a wrong password, after verifying a dummy hash
+        request.email = "b@example.com".to_string();
+        assert!(matches!(
+            auth::service::sign_in(&pool, &request).await,
+            Err(auth::service::SignInError::InvalidCredentials)
+        ));
+
+        Ok(())
+    }
+}
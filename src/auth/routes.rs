@@ -1,39 +1,268 @@
-use crate::auth::service;
-use crate::events::AppEvent;
+use crate::auth::{
+    config::{JwtConfig, SessionConfig},
+    guards::{AuthenticatedUser, SESSION_COOKIE},
+    identity::{self, IdentityProvider},
+    service,
+};
+use crate::events::Publisher;
+use crate::types::error::ApiError;
 use crate::users;
 
-use bus::Bus;
 use color_eyre::eyre;
-use rocket::{http::Status, route::Route, serde::json::Json};
+use rocket::{
+    http::{Cookie, CookieJar, SameSite, Status},
+    response::Redirect,
+    route::Route,
+    serde::json::Json,
+};
+use serde::Serialize;
 use sqlx::SqlitePool;
+use std::sync::Arc;
 
 #[allow(clippy::no_effect_underscore_binding)]
 pub fn routes() -> Vec<Route> {
-    routes![sign_in_route, sign_up_route]
+    routes![
+        sign_in_route,
+        sign_up_route,
+        sign_out_route,
+        revoke_all_sessions_route,
+        issue_tokens_route,
+        refresh_tokens_route,
+        revoke_refresh_token_route,
+        identity_authorize_route,
+        identity_callback_route
+    ]
+}
+
+#[derive(Debug, Serialize)]
+struct SignInResponse {
+    pub user: users::types::User,
+    pub token: String,
+    pub expires_at: chrono::NaiveDateTime,
 }
 
 #[post("/", data = "<payload>")]
 async fn sign_in_route(
     pool: &rocket::State<SqlitePool>,
+    config: &rocket::State<SessionConfig>,
+    cookies: &CookieJar<'_>,
     payload: Json<service::AuthRequest>,
-) -> eyre::Result<Json<users::types::User>, Status> {
-    let profile = service::sign_in(pool.inner(), &payload.into_inner())
+) -> eyre::Result<Json<SignInResponse>, ApiError> {
+    let profile = service::sign_in(pool.inner(), &payload.into_inner()).await?;
+    let user = users::sqlite::find_one(pool.inner(), &profile.user_id)
         .await
-        .unwrap();
-    let user = users::find_user(pool, &profile.user_id.to_string())
+        .map_err(|err| ApiError::internal("find signed in user", &err))?
+        .ok_or_else(|| ApiError::internal("find signed in user", &profile.user_id))?;
+
+    start_session(pool, config, cookies, user).await
+}
+
+/// Creates a session for a user who has just signed in and stores its token in a cookie for
+/// browser clients; the token is also returned for clients using bearer authentication.
+async fn start_session(
+    pool: &SqlitePool,
+    config: &SessionConfig,
+    cookies: &CookieJar<'_>,
+    user: users::types::User,
+) -> eyre::Result<Json<SignInResponse>, ApiError> {
+    let (session, token) = service::create_session(pool, config, &user.id)
         .await
-        .unwrap();
+        .map_err(|err| ApiError::internal("create session", &err))?;
+
+    cookies.add(
+        Cookie::build(SESSION_COOKIE, token.clone())
+            .path("/")
+            .http_only(true)
+            .secure(config.secure_cookie)
+            .same_site(SameSite::Lax)
+            .finish(),
+    );
 
-    Ok(Json(user))
+    Ok(Json(SignInResponse {
+        user,
+        token,
+        expires_at: session.expires_at,
+    }))
 }
 
 #[post("/sign-up", data = "<payload>")]
 async fn sign_up_route(
     pool: &rocket::State<SqlitePool>,
-    bus: &rocket::State<tokio::sync::Mutex<Bus<AppEvent>>>,
+    publisher: Publisher,
+    payload: Json<service::AuthRequest>,
+) -> eyre::Result<Json<users::types::User>, ApiError> {
+    Ok(Json(service::sign_up(pool, &publisher, &payload).await?))
+}
+
+#[post("/sign-out")]
+async fn sign_out_route(
+    pool: &rocket::State<SqlitePool>,
+    cookies: &CookieJar<'_>,
+    user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    // access tokens are stateless; clients sign those out by revoking their refresh token
+    if let Some(session_id) = &user.session_id {
+        service::sign_out(pool, session_id)
+            .await
+            .map_err(|err| ApiError::internal("sign out", &err))?;
+    }
+    cookies.remove(Cookie::named(SESSION_COOKIE));
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/sessions")]
+async fn revoke_all_sessions_route(
+    pool: &rocket::State<SqlitePool>,
+    cookies: &CookieJar<'_>,
+    user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    service::revoke_all_sessions(pool, &user.user_id)
+        .await
+        .map_err(|err| ApiError::internal("revoke sessions", &err))?;
+    cookies.remove(Cookie::named(SESSION_COOKIE));
+
+    Ok(Status::NoContent)
+}
+
+#[post("/token", data = "<payload>")]
+async fn issue_tokens_route(
+    pool: &rocket::State<SqlitePool>,
+    session_config: &rocket::State<SessionConfig>,
+    jwt_config: &rocket::State<JwtConfig>,
     payload: Json<service::AuthRequest>,
-) -> eyre::Result<Json<users::types::User>, Status> {
-    let user = service::sign_up(pool, bus.inner(), &payload).await.unwrap();
+) -> eyre::Result<Json<service::TokenPair>, ApiError> {
+    service::issue_tokens(pool, session_config, jwt_config, &payload)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/token/refresh", data = "<payload>")]
+async fn refresh_tokens_route(
+    pool: &rocket::State<SqlitePool>,
+    session_config: &rocket::State<SessionConfig>,
+    jwt_config: &rocket::State<JwtConfig>,
+    payload: Json<service::RefreshRequest>,
+) -> eyre::Result<Json<service::TokenPair>, ApiError> {
+    service::refresh_tokens(pool, session_config, jwt_config, &payload.refresh_token)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/token/revoke", data = "<payload>")]
+async fn revoke_refresh_token_route(
+    pool: &rocket::State<SqlitePool>,
+    session_config: &rocket::State<SessionConfig>,
+    payload: Json<service::RefreshRequest>,
+) -> eyre::Result<Status, ApiError> {
+    service::revoke_refresh_token(pool, session_config, &payload.refresh_token).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/identity/authorize?<tenant_id>&<login_hint>")]
+async fn identity_authorize_route(
+    pool: &rocket::State<SqlitePool>,
+    provider: &rocket::State<Arc<dyn IdentityProvider>>,
+    tenant_id: &str,
+    login_hint: Option<&str>,
+) -> eyre::Result<Redirect, ApiError> {
+    let url =
+        service::begin_identity_sign_in(pool, provider.as_ref(), tenant_id, login_hint).await?;
+
+    Ok(Redirect::to(url))
+}
+
+#[get("/identity/callback?<code>&<state>&<error>")]
+#[allow(clippy::too_many_arguments)]
+async fn identity_callback_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    config: &rocket::State<SessionConfig>,
+    provider: &rocket::State<Arc<dyn IdentityProvider>>,
+    cookies: &CookieJar<'_>,
+    code: Option<&str>,
+    state: &str,
+    error: Option<&str>,
+) -> eyre::Result<Json<SignInResponse>, ApiError> {
+    let Some(code) = code else {
+        tracing::info!(
+            "identity provider declined sign in: {}",
+            error.unwrap_or("unknown")
+        );
+        return Err(
+            ApiError::new(Status::Unauthorized).with_detail("identity provider declined sign in")
+        );
+    };
+
+    let user = service::complete_identity_sign_in(pool, &publisher, provider.as_ref(), code, state)
+        .await?;
+
+    start_session(pool, config, cookies, user).await
+}
+
+impl From<service::SignInError> for ApiError {
+    fn from(err: service::SignInError) -> Self {
+        match err {
+            service::SignInError::InvalidCredentials => {
+                Self::new(Status::Unauthorized).with_detail(err)
+            }
+            service::SignInError::NotMember => Self::new(Status::Forbidden).with_detail(err),
+            err @ (service::SignInError::Password(_) | service::SignInError::Sqlx(_)) => {
+                Self::internal("sign in", &err)
+            }
+        }
+    }
+}
+
+impl From<service::SignUpError> for ApiError {
+    fn from(err: service::SignUpError) -> Self {
+        match err {
+            service::SignUpError::InvalidInput(err) => err.into(),
+            service::SignUpError::CreateUser(err) => err.into(),
+            err @ (service::SignUpError::Password(_) | service::SignUpError::Sqlx(_)) => {
+                Self::internal("sign up", &err)
+            }
+        }
+    }
+}
+
+impl From<service::TokenError> for ApiError {
+    fn from(err: service::TokenError) -> Self {
+        match err {
+            service::TokenError::SignIn(err) => err.into(),
+            service::TokenError::InvalidInput(err) => err.into(),
+            service::TokenError::InvalidRefreshToken | service::TokenError::RefreshTokenReused => {
+                Self::new(Status::Unauthorized).with_detail(err)
+            }
+            err @ (service::TokenError::Jwt(_) | service::TokenError::Sqlx(_)) => {
+                Self::internal("issue tokens", &err)
+            }
+        }
+    }
+}
 
-    Ok(Json(user))
+impl From<service::IdentitySignInError> for ApiError {
+    fn from(err: service::IdentitySignInError) -> Self {
+        match err {
+            service::IdentitySignInError::InvalidInput(err) => err.into(),
+            service::IdentitySignInError::MissingEmail => {
+                Self::new(Status::UnprocessableEntity).with_detail(err)
+            }
+            service::IdentitySignInError::CreateUser(err) => err.into(),
+            service::IdentitySignInError::InvalidState
+            | service::IdentitySignInError::Identity(
+                identity::IdentityError::Rejected(_)
+                | identity::IdentityError::InvalidIdToken(_)
+                | identity::IdentityError::UnknownIdentity(_),
+            ) => Self::new(Status::Unauthorized).with_detail(err),
+            service::IdentitySignInError::NotMember
+            | service::IdentitySignInError::UnverifiedEmail => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            err => Self::internal("sign in with identity provider", &err),
+        }
+    }
 }
@@ -1,13 +1,24 @@
-use std::convert::TryInto;
+use std::convert::TryFrom;
 
-use crate::events::AppEvent;
+use crate::auth::{
+    self,
+    config::{JwtConfig, SessionConfig},
+    identity::{self, IdentityProvider},
+    jwt, password, token, types,
+};
+use crate::events::{outbox, AppEvent, Publisher};
+use crate::memberships;
+use crate::permissions;
 use crate::profiles;
-use crate::users;
+use crate::tenants::{self, types::TenantId};
+use crate::types::uuid::Uuid;
+use crate::types::validation::{FieldValidationError, Validate, ValidationErrors, Validator};
+use crate::users::{self, types::UserId};
 
-use bus::Bus;
 use color_eyre::eyre;
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use thiserror::Error;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthRequest {
@@ -16,31 +27,624 @@ pub struct AuthRequest {
     pub tenant_id: String,
 }
 
+/// The rules for signing up; signing in doesn't check them, so that a malformed email or password
+/// is rejected just like a wrong one.
+impl Validate for AuthRequest {
+    fn rules(&self, validator: &mut Validator) {
+        validator.parse("email", profiles::types::Email::new(&self.email));
+        validator.max_length("email", &self.email, profiles::types::MAX_EMAIL_LENGTH);
+        validator.min_length("password", &self.password, password::MIN_PASSWORD_LENGTH);
+        validator.parse("tenant_id", TenantId::try_from(self.tenant_id.as_str()));
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SignInError {
+    #[error("invalid email or password")]
+    InvalidCredentials,
+
+    #[error("user is not a member of the tenant")]
+    NotMember,
+
+    #[error("failed to verify password")]
+    Password(#[from] password::PasswordError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
 pub async fn sign_in(
     pool: &SqlitePool,
     payload: &AuthRequest,
-) -> eyre::Result<profiles::types::Profile> {
-    let profile = profiles::find_profile(pool, &payload.email).await?;
+) -> eyre::Result<profiles::types::Profile, SignInError> {
+    let profile = match profiles::find_profile(pool, &payload.email).await {
+        Ok(profile) => profile,
+        Err(profiles::FindProfileError::NotFound(_)) => {
+            password::verify_dummy(&payload.password).await?;
+            return Err(SignInError::InvalidCredentials);
+        }
+        Err(profiles::FindProfileError::Sqlx(err)) => return Err(SignInError::Sqlx(err)),
+    };
+
+    let Some(credential) = auth::sqlite::find_credential(pool, &profile.user_id).await? else {
+        password::verify_dummy(&payload.password).await?;
+        return Err(SignInError::InvalidCredentials);
+    };
+
+    match password::verify(&payload.password, &credential.password_hash).await? {
+        password::Verification::Valid => {}
+        password::Verification::ValidNeedsRehash => {
+            let password_hash = password::hash(&payload.password).await?;
+            auth::sqlite::update_credential(
+                pool,
+                &types::Credential::new(&profile.user_id, &password_hash),
+            )
+            .await?;
+            tracing::info!(
+                "rehashed password for user {} with updated parameters",
+                profile.user_id
+            );
+        }
+        password::Verification::Invalid => return Err(SignInError::InvalidCredentials),
+    }
+
+    // only checked once the password is verified so membership isn't revealed to strangers; a
+    // malformed tenant id is simply one the user isn't a member of
+    let is_member = match TenantId::try_from(payload.tenant_id.as_str()) {
+        Ok(tenant_id) => memberships::sqlite::find_for_user(pool, &profile.user_id, &tenant_id)
+            .await?
+            .is_some(),
+        Err(_) => false,
+    };
+    if !is_member {
+        return Err(SignInError::NotMember);
+    }
 
     Ok(profile)
 }
 
+#[derive(Error, Debug)]
+pub enum SignUpError {
+    #[error("invalid input")]
+    InvalidInput(ValidationErrors),
+
+    #[error("failed to create user")]
+    CreateUser(#[from] users::CreateUserError),
+
+    #[error("failed to hash password")]
+    Password(#[from] password::PasswordError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
 pub async fn sign_up(
     pool: &SqlitePool,
-    bus: &tokio::sync::Mutex<Bus<AppEvent>>,
+    publisher: &Publisher,
     payload: &AuthRequest,
-) -> eyre::Result<users::types::User> {
-    let tenant_id = payload.tenant_id.clone();
-    let user = users::create_user(
-        pool,
-        bus,
+) -> eyre::Result<users::types::User, SignUpError> {
+    payload.validate().map_err(SignUpError::InvalidInput)?;
+    let tenant_id = TenantId::try_from(payload.tenant_id.as_str()).map_err(|e| {
+        SignUpError::InvalidInput(
+            FieldValidationError {
+                field: "tenant_id".to_string(),
+                message: e.to_string(),
+            }
+            .into(),
+        )
+    })?;
+    let password_hash = hash_new_password(&payload.password).await?;
+
+    let mut tx = pool.begin().await?;
+    let user =
+        insert_password_user(&mut tx, &payload.email, &password_hash, tenant_id, None).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::User(users::events::UserEvent::Created(
+                user.clone(),
+            )))
+            .with_actor(&user.id)
+            .with_tenant(&tenant_id),
+    )
+    .await?;
+    tx.commit().await?;
+
+    publisher.notify();
+
+    Ok(user)
+}
+
+/// Checks that a new password is acceptable and hashes it for `insert_password_user`.
+///
+/// Hashing is deliberately slow, so this should happen before any transaction is started.
+pub async fn hash_new_password(password: &str) -> eyre::Result<String, SignUpError> {
+    let mut validator = Validator::new();
+    validator.min_length("password", password, password::MIN_PASSWORD_LENGTH);
+    validator.finish().map_err(SignUpError::InvalidInput)?;
+
+    Ok(password::hash(password).await?)
+}
+
+/// Inserts a user who signs in with an email and password within an existing transaction,
+/// making them a member of `tenant_id` with `role` or the default role when `None`.
+///
+/// Callers are responsible for writing `UserEvent::Created` to the outbox and committing the
+/// transaction.
+pub async fn insert_password_user(
+    tx: &mut Transaction<'_, Sqlite>,
+    email: &str,
+    password_hash: &str,
+    tenant_id: TenantId,
+    role: Option<memberships::types::Role>,
+) -> eyre::Result<users::types::User, SignUpError> {
+    let user = users::insert_user(
+        tx,
         users::CreateUserRequest {
-            email: payload.email.clone(),
-            tenant_id: tenant_id.try_into().unwrap(),
+            auth_id: identity::Identity::password().auth_id(),
+            email: email.to_string(),
+            tenant_id,
+            role,
         },
     )
-    .await
-    .unwrap();
+    .await?;
+    auth::sqlite::insert_credential(&mut *tx, &types::Credential::new(&user.id, password_hash))
+        .await?;
 
     Ok(user)
 }
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Starts a new session for a user, returning the session alongside the signed token
+/// which must be handed back to the client; only a hash of the token is persisted.
+pub async fn create_session(
+    pool: &SqlitePool,
+    config: &SessionConfig,
+    user_id: &UserId,
+) -> eyre::Result<(types::Session, String), SessionError> {
+    let token = token::generate(&config.secret);
+    let session = types::Session::new(user_id, &token::hash(&token), config.ttl);
+
+    let session = auth::sqlite::insert_session(pool, &session).await?;
+
+    Ok((session, token))
+}
+
+pub async fn sign_out(
+    pool: &SqlitePool,
+    session_id: &types::SessionId,
+) -> eyre::Result<(), SessionError> {
+    auth::sqlite::revoke_session(pool, session_id).await?;
+
+    Ok(())
+}
+
+/// Revokes every session and refresh token of a user, signing them out of every device.
+pub async fn revoke_all_sessions(
+    pool: &SqlitePool,
+    user_id: &UserId,
+) -> eyre::Result<u64, SessionError> {
+    let mut tx = pool.begin().await?;
+    let revoked = auth::sqlite::revoke_user_sessions(&mut tx, user_id).await?
+        + auth::sqlite::revoke_user_refresh_tokens(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(revoked)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("failed to sign in")]
+    SignIn(#[from] SignInError),
+
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("invalid or expired refresh token")]
+    InvalidRefreshToken,
+
+    #[error("refresh token was reused; the token family has been revoked")]
+    RefreshTokenReused,
+
+    #[error("failed to encode access token")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Exchanges an email and password for a short-lived access token and a refresh token
+/// starting a new token family.
+pub async fn issue_tokens(
+    pool: &SqlitePool,
+    session_config: &SessionConfig,
+    jwt_config: &JwtConfig,
+    payload: &AuthRequest,
+) -> eyre::Result<TokenPair, TokenError> {
+    let tenant_id = TenantId::try_from(payload.tenant_id.as_str()).map_err(|e| {
+        TokenError::InvalidInput(FieldValidationError {
+            field: "tenant_id".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    let profile = sign_in(pool, payload).await?;
+
+    let mut tx = pool.begin().await?;
+    let pair = issue_token_pair(
+        &mut tx,
+        session_config,
+        jwt_config,
+        &Uuid::new(),
+        &profile.user_id,
+        &tenant_id,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(pair)
+}
+
+/// Rotates a refresh token, returning a fresh access token and replacement refresh token.
+///
+/// Refresh tokens are single use; presenting one which was already rotated revokes every token
+/// in its family, forcing both the legitimate client and any attacker to sign in again.
+pub async fn refresh_tokens(
+    pool: &SqlitePool,
+    session_config: &SessionConfig,
+    jwt_config: &JwtConfig,
+    refresh_token: &str,
+) -> eyre::Result<TokenPair, TokenError> {
+    let token_hash = token::verify(&session_config.secret, refresh_token)
+        .ok_or(TokenError::InvalidRefreshToken)?;
+    let current = auth::sqlite::find_refresh_token(pool, &token_hash)
+        .await?
+        .ok_or(TokenError::InvalidRefreshToken)?;
+
+    if current.revoked_at.is_some() {
+        return Err(TokenError::InvalidRefreshToken);
+    }
+    if current.used_at.is_some() {
+        return Err(revoke_reused_family(pool, &current).await);
+    }
+    if current.expires_at <= chrono::Utc::now().naive_utc() {
+        return Err(TokenError::InvalidRefreshToken);
+    }
+    if memberships::sqlite::find_for_user(pool, &current.user_id, &current.tenant_id)
+        .await?
+        .is_none()
+    {
+        // the user has since been removed from the tenant
+        auth::sqlite::revoke_refresh_token_family(pool, &current.family_id).await?;
+        return Err(TokenError::InvalidRefreshToken);
+    }
+
+    let mut tx = pool.begin().await?;
+    if !auth::sqlite::use_refresh_token(&mut tx, &current.id).await? {
+        // lost a race against another request presenting the same token
+        tx.rollback().await?;
+        return Err(revoke_reused_family(pool, &current).await);
+    }
+    let pair = issue_token_pair(
+        &mut tx,
+        session_config,
+        jwt_config,
+        &current.family_id,
+        &current.user_id,
+        &current.tenant_id,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(pair)
+}
+
+/// Revokes the family of a refresh token, used by clients to sign out.
+pub async fn revoke_refresh_token(
+    pool: &SqlitePool,
+    session_config: &SessionConfig,
+    refresh_token: &str,
+) -> eyre::Result<(), TokenError> {
+    let token_hash = token::verify(&session_config.secret, refresh_token)
+        .ok_or(TokenError::InvalidRefreshToken)?;
+    let current = auth::sqlite::find_refresh_token(pool, &token_hash)
+        .await?
+        .ok_or(TokenError::InvalidRefreshToken)?;
+
+    auth::sqlite::revoke_refresh_token_family(pool, &current.family_id).await?;
+
+    Ok(())
+}
+
+async fn revoke_reused_family(pool: &SqlitePool, token: &types::RefreshToken) -> TokenError {
+    tracing::warn!(
+        "refresh token {} of user {} was reused; revoking token family {}",
+        token.id,
+        token.user_id,
+        token.family_id
+    );
+    match auth::sqlite::revoke_refresh_token_family(pool, &token.family_id).await {
+        Ok(_) => TokenError::RefreshTokenReused,
+        Err(err) => TokenError::Sqlx(err),
+    }
+}
+
+async fn issue_token_pair(
+    tx: &mut Transaction<'_, Sqlite>,
+    session_config: &SessionConfig,
+    jwt_config: &JwtConfig,
+    family_id: &Uuid,
+    user_id: &UserId,
+    tenant_id: &TenantId,
+) -> eyre::Result<TokenPair, TokenError> {
+    let perms = permissions::sqlite::find_effective_by_user(&mut *tx, user_id)
+        .await?
+        .iter()
+        .map(|p| format!("{}:{}", p.action, p.resource))
+        .collect();
+    let claims = jwt::Claims::new(jwt_config, user_id, tenant_id, perms);
+    let access_token = jwt::encode(jwt_config, &claims)?;
+
+    let refresh_token = token::generate(&session_config.secret);
+    auth::sqlite::insert_refresh_token(
+        &mut *tx,
+        &types::RefreshToken::new(
+            family_id,
+            user_id,
+            tenant_id,
+            &token::hash(&refresh_token),
+            jwt_config.refresh_ttl,
+        ),
+    )
+    .await?;
+
+    Ok(TokenPair {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: jwt_config.access_ttl.num_seconds(),
+        refresh_token,
+    })
+}
+
+/// How long a user has to complete a sign in with an identity provider once it's started.
+const IDENTITY_AUTHORIZATION_TTL_MINUTES: i64 = 10;
+
+#[derive(Error, Debug)]
+pub enum IdentitySignInError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("sign in state is unknown, expired or was already used")]
+    InvalidState,
+
+    #[error("identity provider did not supply an email for a new user")]
+    MissingEmail,
+
+    #[error("identity provider has not verified the email of a new user")]
+    UnverifiedEmail,
+
+    #[error("user is not a member of the tenant")]
+    NotMember,
+
+    #[error("identity provider error")]
+    Identity(#[from] identity::IdentityError),
+
+    #[error("failed to create user")]
+    CreateUser(#[from] users::CreateUserError),
+
+    #[error("failed to find user")]
+    FindUser(#[from] users::FindUserError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Starts signing in with the configured identity provider, returning the url to redirect the
+/// user to.
+pub async fn begin_identity_sign_in(
+    pool: &SqlitePool,
+    provider: &dyn IdentityProvider,
+    tenant_id: &str,
+    login_hint: Option<&str>,
+) -> eyre::Result<String, IdentitySignInError> {
+    let tenant_id = TenantId::try_from(tenant_id).map_err(|e| {
+        IdentitySignInError::InvalidInput(FieldValidationError {
+            field: "tenant_id".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    if tenants::sqlite::find_one(pool, &tenant_id).await?.is_none() {
+        return Err(IdentitySignInError::InvalidInput(FieldValidationError {
+            field: "tenant_id".to_string(),
+            message: format!("tenant `{tenant_id}` does not exist"),
+        }));
+    }
+    let authorization = types::IdentityAuthorization::new(&tenant_id);
+    let url = provider
+        .authorization_url(&authorization.pending, login_hint)
+        .await?;
+    auth::sqlite::insert_identity_authorization(pool, &authorization).await?;
+
+    Ok(url)
+}
+
+/// Completes a sign in once the identity provider redirects back with an authorization code,
+/// returning the user linked to the identity and creating them on their first sign in.
+///
+/// A user is only created for a tenant their email has a pending invitation to, which their
+/// first sign in accepts with the invitation's role, and only once the provider verified the
+/// email; anyone else gets `NotMember`, as an existing user outside the tenant does.
+pub async fn complete_identity_sign_in(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    provider: &dyn IdentityProvider,
+    code: &str,
+    state: &str,
+) -> eyre::Result<users::types::User, IdentitySignInError> {
+    let authorization = auth::sqlite::take_identity_authorization(pool, state)
+        .await?
+        .ok_or(IdentitySignInError::InvalidState)?;
+    if authorization.created_at + chrono::Duration::minutes(IDENTITY_AUTHORIZATION_TTL_MINUTES)
+        < chrono::Utc::now().naive_utc()
+    {
+        return Err(IdentitySignInError::InvalidState);
+    }
+
+    let identity = provider.exchange_code(code, &authorization.pending).await?;
+    if let Some(user) = users::find_user_by_auth_id(pool, &identity.auth_id()).await? {
+        return match memberships::sqlite::find_for_user(pool, &user.id, &authorization.tenant_id)
+            .await?
+        {
+            Some(_) => Ok(user),
+            None => Err(IdentitySignInError::NotMember),
+        };
+    }
+
+    let email = identity
+        .email
+        .as_deref()
+        .ok_or(IdentitySignInError::MissingEmail)?;
+    if !identity.email_verified {
+        return Err(IdentitySignInError::UnverifiedEmail);
+    }
+    let email = profiles::types::Email::new(email).map_err(|e| {
+        IdentitySignInError::InvalidInput(FieldValidationError {
+            field: "email".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+
+    let mut tx = pool.begin().await?;
+    let scope = tenants::TenantScope::unchecked(&authorization.tenant_id);
+    let Some(invitation) =
+        tenants::sqlite::accept_invitation_for_email(&mut tx, &scope, &email).await?
+    else {
+        return Err(IdentitySignInError::NotMember);
+    };
+    let user = users::insert_user(
+        &mut tx,
+        users::CreateUserRequest {
+            auth_id: identity.auth_id(),
+            email: email.to_string(),
+            tenant_id: authorization.tenant_id,
+            role: Some(invitation.role),
+        },
+    )
+    .await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::User(users::events::UserEvent::Created(
+                user.clone(),
+            )))
+            .with_actor(&user.id)
+            .with_tenant(&authorization.tenant_id),
+    )
+    .await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(
+                tenants::events::TenantEvent::InvitationAccepted(invitation),
+            ))
+            .with_actor(&user.id),
+    )
+    .await?;
+    tx.commit().await?;
+
+    publisher.notify();
+    tracing::info!(
+        "created user {} for identity {} from {}",
+        user.id,
+        identity.auth_id(),
+        provider.name()
+    );
+
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::sqlite::test_pool;
+
+    fn configs() -> (SessionConfig, JwtConfig) {
+        let session_config = SessionConfig {
+            secret: b"test-session-secret".to_vec(),
+            ttl: chrono::Duration::hours(1),
+            secure_cookie: true,
+        };
+        let jwt_config = JwtConfig {
+            algorithm: jsonwebtoken::Algorithm::HS256,
+            encoding_key: jsonwebtoken::EncodingKey::from_secret(b"test-jwt-secret"),
+            decoding_key: jsonwebtoken::DecodingKey::from_secret(b"test-jwt-secret"),
+            issuer: "test".to_string(),
+            access_ttl: chrono::Duration::minutes(15),
+            refresh_ttl: chrono::Duration::days(30),
+        };
+
+        (session_config, jwt_config)
+    }
+
+    #[rocket::async_test]
+    async fn reusing_a_rotated_refresh_token_revokes_its_family() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let (session_config, jwt_config) = configs();
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let mut tx = pool.begin().await?;
+        let user =
+            insert_password_user(&mut tx, "a@example.com", "unused-hash", tenant.id, None).await?;
+        let first = issue_token_pair(
+            &mut tx,
+            &session_config,
+            &jwt_config,
+            &Uuid::new(),
+            &user.id,
+            &tenant.id,
+        )
+        .await?;
+        tx.commit().await?;
+
+        let second =
+            refresh_tokens(&pool, &session_config, &jwt_config, &first.refresh_token).await?;
+        let third =
+            refresh_tokens(&pool, &session_config, &jwt_config, &second.refresh_token).await?;
+
+        assert!(matches!(
+            refresh_tokens(&pool, &session_config, &jwt_config, &first.refresh_token).await,
+            Err(TokenError::RefreshTokenReused)
+        ));
+        for pair in [&first, &second, &third] {
+            let token_hash = token::verify(&session_config.secret, &pair.refresh_token)
+                .ok_or_else(|| eyre::eyre!("refresh token isn't signed"))?;
+            let token = auth::sqlite::find_refresh_token(&pool, &token_hash)
+                .await?
+                .ok_or_else(|| eyre::eyre!("refresh token wasn't stored"))?;
+            assert!(token.revoked_at.is_some());
+        }
+        // the latest token, which the attacker or the client may hold, is no longer usable
+        assert!(matches!(
+            refresh_tokens(&pool, &session_config, &jwt_config, &third.refresh_token).await,
+            Err(TokenError::InvalidRefreshToken)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use crate::auth::{
+    identity::PendingAuthorization,
+    types::{self, RefreshTokenId, SessionId},
+};
+use crate::tenants::types::TenantId;
+use crate::types::uuid::Uuid;
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use sqlx::SqliteExecutor;
+
+pub async fn find_credential<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<Option<types::Credential>, sqlx::Error> {
+    let credential = match sqlx::query_as!(
+        types::Credential,
+        "SELECT user_id AS \"user_id: UserId\", password_hash, updated_at
+            FROM credentials
+            WHERE user_id = ?",
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(c) => c,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    Ok(Some(credential))
+}
+
+pub async fn insert_credential<'e>(
+    executor: impl SqliteExecutor<'e>,
+    credential: &types::Credential,
+) -> eyre::Result<types::Credential, sqlx::Error> {
+    sqlx::query!(
+        "
+INSERT INTO credentials (user_id, password_hash, updated_at)
+VALUES (?, ?, ?)
+    ",
+        credential.user_id,
+        credential.password_hash,
+        credential.updated_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(credential.clone())
+}
+
+pub async fn update_credential<'e>(
+    executor: impl SqliteExecutor<'e>,
+    credential: &types::Credential,
+) -> eyre::Result<types::Credential, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE credentials SET password_hash = ?, updated_at = ? WHERE user_id = ?",
+        credential.password_hash,
+        credential.updated_at,
+        credential.user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(credential.clone())
+}
+
+/// Finds a session by the hash of its token, ignoring sessions which have expired or been revoked.
+pub async fn find_active_session<'e>(
+    executor: impl SqliteExecutor<'e>,
+    token_hash: &str,
+) -> eyre::Result<Option<types::Session>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let session = match sqlx::query_as!(
+        types::Session,
+        "SELECT id AS \"id: SessionId\", user_id AS \"user_id: UserId\", token_hash, created_at,
+                expires_at, revoked_at
+            FROM sessions
+            WHERE token_hash = ?
+            AND revoked_at IS NULL
+            AND expires_at > ?",
+        token_hash,
+        now
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    Ok(Some(session))
+}
+
+pub async fn insert_session<'e>(
+    executor: impl SqliteExecutor<'e>,
+    session: &types::Session,
+) -> eyre::Result<types::Session, sqlx::Error> {
+    sqlx::query!(
+        "
+INSERT INTO sessions (id, user_id, token_hash, created_at, expires_at, revoked_at)
+VALUES (?, ?, ?, ?, ?, ?)
+    ",
+        session.id,
+        session.user_id,
+        session.token_hash,
+        session.created_at,
+        session.expires_at,
+        session.revoked_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(session.clone())
+}
+
+pub async fn revoke_session<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: &SessionId,
+) -> eyre::Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL",
+        now,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes every active session belonging to a user, returning how many were revoked.
+pub async fn revoke_user_sessions<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<u64, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE sessions SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL",
+        now,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Finds a refresh token by the hash of its value regardless of whether it is still usable,
+/// so callers can detect the reuse of rotated tokens.
+pub async fn find_refresh_token<'e>(
+    executor: impl SqliteExecutor<'e>,
+    token_hash: &str,
+) -> eyre::Result<Option<types::RefreshToken>, sqlx::Error> {
+    let token = match sqlx::query_as!(
+        types::RefreshToken,
+        "SELECT id AS \"id: RefreshTokenId\", family_id AS \"family_id: Uuid\",
+                user_id AS \"user_id: UserId\", tenant_id AS \"tenant_id: TenantId\", token_hash,
+                created_at, expires_at, used_at, revoked_at
+            FROM refresh_tokens
+            WHERE token_hash = ?",
+        token_hash
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    Ok(Some(token))
+}
+
+pub async fn insert_refresh_token<'e>(
+    executor: impl SqliteExecutor<'e>,
+    token: &types::RefreshToken,
+) -> eyre::Result<types::RefreshToken, sqlx::Error> {
+    sqlx::query!(
+        "
+INSERT INTO refresh_tokens (id, family_id, user_id, tenant_id, token_hash, created_at, expires_at, used_at, revoked_at)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ",
+        token.id,
+        token.family_id,
+        token.user_id,
+        token.tenant_id,
+        token.token_hash,
+        token.created_at,
+        token.expires_at,
+        token.used_at,
+        token.revoked_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(token.clone())
+}
+
+/// Marks a refresh token as used, returning `false` when it had already been used or revoked
+/// by a concurrent request.
+pub async fn use_refresh_token<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: &RefreshTokenId,
+) -> eyre::Result<bool, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE refresh_tokens SET used_at = ? WHERE id = ? AND used_at IS NULL AND revoked_at IS NULL",
+        now,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn revoke_refresh_token_family<'e>(
+    executor: impl SqliteExecutor<'e>,
+    family_id: &Uuid,
+) -> eyre::Result<u64, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = ? WHERE family_id = ? AND revoked_at IS NULL",
+        now,
+        family_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn revoke_user_refresh_tokens<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<u64, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL",
+        now,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+struct IdentityAuthorizationRecord {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    tenant_id: TenantId,
+    created_at: chrono::NaiveDateTime,
+}
+
+pub async fn insert_identity_authorization<'e>(
+    executor: impl SqliteExecutor<'e>,
+    authorization: &types::IdentityAuthorization,
+) -> eyre::Result<types::IdentityAuthorization, sqlx::Error> {
+    sqlx::query!(
+        "
+INSERT INTO identity_authorizations (state, nonce, code_verifier, tenant_id, created_at)
+VALUES (?, ?, ?, ?, ?)
+    ",
+        authorization.pending.state,
+        authorization.pending.nonce,
+        authorization.pending.code_verifier,
+        authorization.tenant_id,
+        authorization.created_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(authorization.clone())
+}
+
+/// Removes and returns a pending authorization so that its state can only be redeemed once.
+pub async fn take_identity_authorization<'e>(
+    executor: impl SqliteExecutor<'e>,
+    state: &str,
+) -> eyre::Result<Option<types::IdentityAuthorization>, sqlx::Error> {
+    let authorization = match sqlx::query_as!(
+        IdentityAuthorizationRecord,
+        "DELETE FROM identity_authorizations
+            WHERE state = ?
+            RETURNING state AS \"state!\", nonce AS \"nonce!\", code_verifier AS \"code_verifier!\",
+                tenant_id AS \"tenant_id!: TenantId\", created_at AS \"created_at!\"",
+        state
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(a) => a,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    Ok(Some(types::IdentityAuthorization {
+        pending: PendingAuthorization {
+            state: authorization.state,
+            nonce: authorization.nonce,
+            code_verifier: authorization.code_verifier,
+        },
+        tenant_id: authorization.tenant_id,
+        created_at: authorization.created_at,
+    }))
+}
@@ -0,0 +1,46 @@
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generates a new opaque session token signed with `secret`.
+///
+/// The token takes the form `<random>.<signature>`, both base64url encoded, so forged or
+/// corrupted tokens can be rejected before ever reaching the database.
+pub fn generate(secret: &[u8]) -> String {
+    let mut bytes = [0_u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    let value = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+    format!("{}.{}", value, sign(secret, &value))
+}
+
+/// Verifies the signature of a token produced by [`generate`], returning the hash that it is
+/// stored under when valid.
+pub fn verify(secret: &[u8], token: &str) -> Option<String> {
+    let (value, signature) = token.split_once('.')?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(secret).ok()?;
+    mac.update(value.as_bytes());
+    // `verify_slice` compares in constant time
+    mac.verify_slice(&signature).ok()?;
+
+    Some(hash(token))
+}
+
+/// Hashes a token for storage so a leaked database can't be used to hijack sessions.
+pub fn hash(token: &str) -> String {
+    base64::encode_config(Sha256::digest(token.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+fn sign(secret: &[u8], value: &str) -> String {
+    // HMAC accepts keys of any length, so this can't fail
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap_or_else(|e| panic!("{}", e));
+    mac.update(value.as_bytes());
+
+    base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD)
+}
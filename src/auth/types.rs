@@ -0,0 +1,109 @@
+use crate::auth::identity::PendingAuthorization;
+use crate::tenants::types::TenantId;
+use crate::types::{id::define_id, uuid::Uuid};
+use crate::users::types::UserId;
+
+use serde::{Deserialize, Serialize};
+
+define_id!(SessionId, "session");
+define_id!(RefreshTokenId, "refresh token");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub user_id: UserId,
+    pub password_hash: String,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl Credential {
+    pub fn new(user_id: &UserId, password_hash: &str) -> Self {
+        Self {
+            user_id: *user_id,
+            password_hash: password_hash.to_string(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Session {
+    pub id: SessionId,
+    pub user_id: UserId,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Session {
+    pub fn new(user_id: &UserId, token_hash: &str, ttl: chrono::Duration) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: SessionId::new(),
+            user_id: *user_id,
+            token_hash: token_hash.to_string(),
+            created_at: now,
+            expires_at: now + ttl,
+            revoked_at: None,
+        }
+    }
+}
+
+/// A single-use token exchanged for a new access token.
+///
+/// Every token issued from the same sign in shares a `family_id`, so presenting an already used
+/// token (a sign that it was stolen) can revoke the whole chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshToken {
+    pub id: RefreshTokenId,
+    pub family_id: Uuid,
+    pub user_id: UserId,
+    pub tenant_id: TenantId,
+    pub token_hash: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl RefreshToken {
+    pub fn new(
+        family_id: &Uuid,
+        user_id: &UserId,
+        tenant_id: &TenantId,
+        token_hash: &str,
+        ttl: chrono::Duration,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: RefreshTokenId::new(),
+            family_id: *family_id,
+            user_id: *user_id,
+            tenant_id: *tenant_id,
+            token_hash: token_hash.to_string(),
+            created_at: now,
+            expires_at: now + ttl,
+            used_at: None,
+            revoked_at: None,
+        }
+    }
+}
+
+/// An identity provider sign in which has been started but not yet completed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityAuthorization {
+    pub pending: PendingAuthorization,
+    pub tenant_id: TenantId,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl IdentityAuthorization {
+    pub fn new(tenant_id: &TenantId) -> Self {
+        Self {
+            pending: PendingAuthorization::new(),
+            tenant_id: *tenant_id,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::events::{sqlite::EventRecord, types::DeadLetter, Envelope};
+use crate::types::pagination::Pagination;
+
+use color_eyre::eyre;
+use sqlx::SqliteExecutor;
+
+/// A dead letter joined with the event it failed to handle, before the event is deserialized.
+#[derive(Debug)]
+struct DeadLetterRecord {
+    id: i64,
+    handler: String,
+    sequence: i64,
+    event: String,
+    created_at: chrono::NaiveDateTime,
+    error: String,
+    attempts: i64,
+    failed_at: chrono::NaiveDateTime,
+    redriven_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<DeadLetterRecord> for DeadLetter {
+    type Error = sqlx::Error;
+
+    fn try_from(record: DeadLetterRecord) -> eyre::Result<Self, Self::Error> {
+        let envelope = Envelope::from_record(&EventRecord {
+            sequence: record.sequence,
+            event: record.event,
+            created_at: record.created_at,
+        })
+        .map_err(|e| sqlx::Error::Decode(e.into()))?;
+
+        Ok(Self {
+            id: record.id,
+            handler: record.handler,
+            sequence: record.sequence,
+            envelope,
+            error: record.error,
+            attempts: record.attempts,
+            failed_at: record.failed_at,
+            redriven_at: record.redriven_at,
+        })
+    }
+}
+
+/// Records that a handler gave up on an event after `attempts` attempts, returning the id of the
+/// dead letter.
+pub async fn insert<'e>(
+    executor: impl SqliteExecutor<'e>,
+    handler: &str,
+    sequence: i64,
+    error: &str,
+    attempts: u32,
+) -> eyre::Result<i64, sqlx::Error> {
+    let failed_at = chrono::Utc::now().naive_utc();
+
+    Ok(sqlx::query!(
+        "
+INSERT INTO event_dead_letters (handler, sequence, error, attempts, failed_at)
+VALUES (?, ?, ?, ?, ?)
+        ",
+        handler,
+        sequence,
+        error,
+        attempts,
+        failed_at
+    )
+    .execute(executor)
+    .await?
+    .last_insert_rowid())
+}
+
+pub async fn find_one<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: i64,
+) -> eyre::Result<Option<DeadLetter>, sqlx::Error> {
+    sqlx::query_as!(
+        DeadLetterRecord,
+        "SELECT event_dead_letters.id AS \"id!\", handler, event_dead_letters.sequence,
+                events.event, events.created_at, error, attempts, failed_at, redriven_at
+            FROM event_dead_letters
+            JOIN events ON events.sequence = event_dead_letters.sequence
+            WHERE event_dead_letters.id = ?",
+        id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(DeadLetter::try_from)
+    .transpose()
+}
+
+/// Finds a page of dead letters, newest first, of one handler or of every handler when `handler`
+/// is `None`. Dead letters which were redriven are only included when `redriven` is set.
+pub async fn find_page<'e>(
+    executor: impl SqliteExecutor<'e>,
+    handler: Option<&str>,
+    redriven: bool,
+    pagination: &Pagination,
+) -> eyre::Result<Vec<DeadLetter>, sqlx::Error> {
+    let limit = pagination.limit();
+    let offset = pagination.offset();
+    sqlx::query_as!(
+        DeadLetterRecord,
+        "SELECT event_dead_letters.id AS \"id!\", handler, event_dead_letters.sequence,
+                events.event, events.created_at, error, attempts, failed_at, redriven_at
+            FROM event_dead_letters
+            JOIN events ON events.sequence = event_dead_letters.sequence
+            WHERE (? IS NULL OR handler = ?)
+            AND (? OR redriven_at IS NULL)
+            ORDER BY event_dead_letters.id DESC
+            LIMIT ? OFFSET ?",
+        handler,
+        handler,
+        redriven,
+        limit,
+        offset
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(DeadLetter::try_from)
+    .collect()
+}
+
+pub async fn count<'e>(
+    executor: impl SqliteExecutor<'e>,
+    handler: Option<&str>,
+    redriven: bool,
+) -> eyre::Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(1) FROM event_dead_letters
+            WHERE (? IS NULL OR handler = ?)
+            AND (? OR redriven_at IS NULL)",
+        handler,
+        handler,
+        redriven
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(count.into())
+}
+
+/// Records that redriving a dead letter failed again, counting the attempt.
+pub async fn record_failure<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: i64,
+    error: &str,
+) -> eyre::Result<(), sqlx::Error> {
+    let failed_at = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE event_dead_letters SET error = ?, attempts = attempts + 1, failed_at = ?
+            WHERE id = ?",
+        error,
+        failed_at,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks a dead letter as redriven once its handler has handled the event.
+pub async fn mark_redriven<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: i64,
+) -> eyre::Result<(), sqlx::Error> {
+    let redriven_at = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE event_dead_letters SET redriven_at = ? WHERE id = ?",
+        redriven_at,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use crate::events::{dead_letters, types, Envelope, EventHandler, EventLog, EventReader};
+
+use color_eyre::eyre;
+use sqlx::SqlitePool;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait before retrying after the database failed to record a dead letter.
+const DEAD_LETTER_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How often a handler is given an event it fails to handle, and how long to wait in between,
+/// before the event is dead-lettered.
+///
+/// Webhook deliveries are retried by a policy of their own, configured the same way.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        Self::from_env_with_prefix("EVENT_HANDLER")
+    }
+
+    /// Reads the policy from `<prefix>_MAX_ATTEMPTS`, `<prefix>_INITIAL_BACKOFF_MS` and
+    /// `<prefix>_MAX_BACKOFF_MS`.
+    pub fn from_env_with_prefix(prefix: &str) -> Self {
+        Self {
+            max_attempts: env_u64(&format!("{prefix}_MAX_ATTEMPTS"), 5)
+                .try_into()
+                .unwrap_or(u32::MAX)
+                .max(1),
+            initial_backoff: Duration::from_millis(env_u64(
+                &format!("{prefix}_INITIAL_BACKOFF_MS"),
+                500,
+            )),
+            max_backoff: Duration::from_millis(env_u64(
+                &format!("{prefix}_MAX_BACKOFF_MS"),
+                60_000,
+            )),
+        }
+    }
+
+    /// How long to wait after the given failed attempt, doubling after every attempt up to the
+    /// maximum backoff.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Hands the events of the log to every handler interested in them, each reading the log in its
+/// own task.
+///
+/// A handler failing to handle an event is retried with exponential backoff, and once it has
+/// failed every attempt the event is dead-lettered and the handler moves on to the next event.
+/// Dead letters are redriven one at a time through the events API, so a redriven event may be
+/// handled after events which followed it in the log.
+#[derive(Clone)]
+pub struct Dispatcher {
+    pool: SqlitePool,
+    handlers: Vec<Arc<dyn EventHandler>>,
+    retry: RetryPolicy,
+}
+
+impl Dispatcher {
+    pub fn new(pool: SqlitePool, handlers: Vec<Arc<dyn EventHandler>>, retry: RetryPolicy) -> Self {
+        Self {
+            pool,
+            handlers,
+            retry,
+        }
+    }
+
+    pub const fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Starts handling events, each handler from the cursor it left off at.
+    pub async fn start(&self, log: &EventLog) -> eyre::Result<(), sqlx::Error> {
+        for handler in &self.handlers {
+            let rx = log.reader(handler.name()).await?;
+            tokio::spawn(self.clone().run(handler.clone(), rx));
+        }
+
+        Ok(())
+    }
+
+    /// Finds a running handler by name.
+    pub fn handler(&self, name: &str) -> Option<&Arc<dyn EventHandler>> {
+        self.handlers.iter().find(|handler| handler.name() == name)
+    }
+
+    async fn run(self, handler: Arc<dyn EventHandler>, mut rx: EventReader) {
+        while let Some(event) = rx.recv().await {
+            if !accepts(handler.as_ref(), &event.envelope) {
+                continue;
+            }
+
+            let Err((attempts, e)) = self.handle(handler.as_ref(), &event.envelope).await else {
+                continue;
+            };
+            tracing::error!(
+                "dead-lettering event #{} for {} after {} attempts: {:?}",
+                event.sequence,
+                handler.name(),
+                attempts,
+                e
+            );
+            let error = format!("{e:#}");
+            while let Err(e) =
+                dead_letters::insert(&self.pool, handler.name(), event.sequence, &error, attempts)
+                    .await
+            {
+                tracing::error!(
+                    "failed to dead-letter event #{} for {}: {:?}",
+                    event.sequence,
+                    handler.name(),
+                    e
+                );
+                tokio::time::sleep(DEAD_LETTER_RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// Hands an event to a handler until it succeeds or runs out of attempts, returning how many
+    /// attempts were made along with the last error when it never succeeded.
+    async fn handle(
+        &self,
+        handler: &dyn EventHandler,
+        envelope: &Envelope,
+    ) -> eyre::Result<(), (u32, eyre::Report)> {
+        let mut attempt = 1;
+        loop {
+            match handler.handle(&self.pool, envelope).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retry.max_attempts => return Err((attempt, e)),
+                Err(e) => {
+                    let backoff = self.retry.backoff(attempt);
+                    tracing::warn!(
+                        "{} failed to handle event {} on attempt {}, retrying in {:?}: {:?}",
+                        handler.name(),
+                        envelope.id,
+                        attempt,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the handler is interested in the type of the event.
+fn accepts(handler: &dyn EventHandler, envelope: &Envelope) -> bool {
+    handler
+        .event_types()
+        .iter()
+        .any(|pattern| types::type_matches(pattern, &envelope.kind))
+}
+
+fn env_u64(var: &str, default: u64) -> u64 {
+    env::var(var).map_or(default, |v| {
+        v.parse::<u64>().unwrap_or_else(|e| panic!("{}", e))
+    })
+}
@@ -0,0 +1,182 @@
+use crate::events::{outbox, sqlite, Envelope};
+
+use color_eyre::eyre;
+use sqlx::SqlitePool;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+
+/// How many events a reader loads from the log at once.
+const READ_BATCH_SIZE: i64 = 100;
+
+/// How many outbox events the relay appends to the log in one transaction.
+const RELAY_BATCH_SIZE: i64 = 100;
+
+/// How long to wait before retrying after the database failed to read events.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The durable log of events in the `events` table, where each event is given the next sequence
+/// number.
+///
+/// Events aren't published to the log directly: services write them to the outbox within the
+/// transaction of the change they describe, and a background relay appends committed outbox
+/// events to the log and marks them delivered in one transaction, then wakes up readers. The relay
+/// runs whenever it's notified of a commit and on an interval, which picks up events committed
+/// just before the server stopped.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    relay: Arc<Notify>,
+    appended: watch::Receiver<i64>,
+    pool: SqlitePool,
+}
+
+impl EventLog {
+    /// Starts relaying events from the outbox to the returned log every `interval`, or sooner when
+    /// notified.
+    pub fn start(pool: SqlitePool, interval: Duration) -> Self {
+        let relay = Arc::new(Notify::new());
+        let (notify, appended) = watch::channel(0);
+
+        let relay_pool = pool.clone();
+        let notified = relay.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = notified.notified() => {}
+                }
+                loop {
+                    match relay_outbox(&relay_pool).await {
+                        Ok(Some(sequence)) => {
+                            notify.send_replace(sequence);
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::error!("failed to relay events from the outbox: {:?}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Self {
+            relay,
+            appended,
+            pool,
+        }
+    }
+
+    /// Notifies the relay that events have been committed to the outbox, so that they're
+    /// published without waiting for the next interval.
+    pub fn notify(&self) {
+        self.relay.notify_one();
+    }
+
+    /// Reads the log for a handler from the cursor stored under its name, so that it picks up
+    /// where it left off after a restart.
+    pub async fn reader(&self, handler: &'static str) -> eyre::Result<EventReader, sqlx::Error> {
+        let position = sqlite::find_cursor(&self.pool, handler).await?;
+
+        Ok(EventReader {
+            pool: self.pool.clone(),
+            handler,
+            position,
+            handled: None,
+            buffer: VecDeque::new(),
+            appended: self.appended.clone(),
+        })
+    }
+}
+
+/// An event read from the log, along with its sequence number.
+#[derive(Debug, Clone)]
+pub struct LoggedEvent {
+    pub sequence: i64,
+    pub envelope: Envelope,
+}
+
+/// Reads the events of the log in order for one handler.
+///
+/// Delivery is at least once: an event is only recorded as handled once the handler asks for the
+/// next one, so an event being handled when the server stops is read again after it restarts.
+#[derive(Debug)]
+pub struct EventReader {
+    pool: SqlitePool,
+    handler: &'static str,
+    /// The sequence number of the last event loaded from the log.
+    position: i64,
+    /// The sequence number of the last event returned, until it's stored as the handler's cursor.
+    handled: Option<i64>,
+    buffer: VecDeque<LoggedEvent>,
+    appended: watch::Receiver<i64>,
+}
+
+impl EventReader {
+    /// Waits for the next event, returning `None` once the log has been closed.
+    ///
+    /// Events which can't be deserialized are logged and skipped, and failing to read the log is
+    /// retried until it succeeds.
+    pub async fn recv(&mut self) -> Option<LoggedEvent> {
+        if let Some(sequence) = self.handled.take() {
+            if let Err(e) = sqlite::save_cursor(&self.pool, self.handler, sequence).await {
+                tracing::error!("failed to save the cursor of {}: {:?}", self.handler, e);
+            }
+        }
+
+        loop {
+            if let Some(event) = self.buffer.pop_front() {
+                self.handled = Some(event.sequence);
+                return Some(event);
+            }
+
+            // marked as seen before reading so that events appended meanwhile aren't missed
+            self.appended.borrow_and_update();
+            let records = match sqlite::find_after(&self.pool, self.position, READ_BATCH_SIZE).await
+            {
+                Ok(records) => records,
+                Err(e) => {
+                    tracing::error!("failed to read events for {}: {:?}", self.handler, e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            if records.is_empty() {
+                self.appended.changed().await.ok()?;
+                continue;
+            }
+            for record in records {
+                self.position = record.sequence;
+                match Envelope::from_record(&record) {
+                    Ok(envelope) => self.buffer.push_back(LoggedEvent {
+                        sequence: record.sequence,
+                        envelope,
+                    }),
+                    Err(e) => tracing::error!(
+                        "skipping event #{} for {} as it failed to deserialize: {:?}",
+                        record.sequence,
+                        self.handler,
+                        e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Appends a batch of undelivered events from the outbox to the log, returning the sequence
+/// number of the last one appended or `None` when there were none.
+async fn relay_outbox(pool: &SqlitePool) -> eyre::Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut sequence = None;
+    for record in outbox::find_undelivered(&mut tx, RELAY_BATCH_SIZE).await? {
+        sequence = Some(sqlite::append(&mut tx, &record.event).await?);
+        outbox::mark_delivered(&mut tx, record.id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(sequence)
+}
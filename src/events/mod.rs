@@ -0,0 +1,55 @@
+mod dead_letters;
+mod dispatcher;
+mod log;
+mod publisher;
+mod routes;
+mod service;
+
+pub mod outbox;
+pub mod sqlite;
+pub mod types;
+pub use dispatcher::{Dispatcher, RetryPolicy};
+pub use log::{EventLog, EventReader};
+pub use publisher::Publisher;
+pub use routes::routes;
+pub use types::Envelope;
+
+use crate::memberships;
+use crate::permissions;
+use crate::profiles;
+use crate::tenants;
+use crate::users;
+
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppEvent {
+    User(users::events::UserEvent),
+    Profile(profiles::events::ProfileEvent),
+    Permission(permissions::events::PermissionEvent),
+    Tenant(tenants::events::TenantEvent),
+    Membership(memberships::events::MembershipEvent),
+}
+
+/// Handles the events of the log, one at a time and in order.
+///
+/// A handler returning an error is given the event again, with backoff, until it runs out of
+/// attempts and the event is dead-lettered, so handling an event must be safe to repeat.
+#[rocket::async_trait]
+pub trait EventHandler: Send + Sync {
+    /// The name the handler's cursor and dead letters are stored under, which must stay the same
+    /// across restarts.
+    fn name(&self) -> &'static str;
+
+    /// The patterns of the event types the handler is given, such as `user.created` or `user.*`;
+    /// every event by default.
+    fn event_types(&self) -> &'static [&'static str] {
+        &["*"]
+    }
+
+    /// Handles an event, given the database to read or record anything it needs.
+    async fn handle(&self, pool: &SqlitePool, envelope: &Envelope) -> eyre::Result<()>;
+}
@@ -0,0 +1,68 @@
+use crate::events::Envelope;
+
+use color_eyre::eyre;
+use sqlx::SqliteExecutor;
+
+/// An event written to the outbox which hasn't been delivered to the log yet.
+#[derive(Debug)]
+pub struct OutboxRecord {
+    pub id: i64,
+    pub event: String,
+}
+
+/// Writes an event to the outbox, to be published once the transaction it's written in commits.
+///
+/// Services write events within the same transaction as the change they describe, so that an
+/// event is published if and only if the change is committed.
+pub async fn insert<'e>(
+    executor: impl SqliteExecutor<'e>,
+    envelope: &Envelope,
+) -> eyre::Result<(), sqlx::Error> {
+    let event = serde_json::to_string(envelope)
+        .map_err(|e| sqlx::Error::Protocol(format!("failed to serialize event: {e}")))?;
+    let created_at = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        "INSERT INTO event_outbox (event, created_at) VALUES (?, ?)",
+        event,
+        created_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds up to `limit` undelivered events, in the order they were written.
+pub async fn find_undelivered<'e>(
+    executor: impl SqliteExecutor<'e>,
+    limit: i64,
+) -> eyre::Result<Vec<OutboxRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        OutboxRecord,
+        "SELECT id AS \"id!\", event FROM event_outbox
+            WHERE delivered_at IS NULL
+            ORDER BY id
+            LIMIT ?",
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn mark_delivered<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: i64,
+) -> eyre::Result<(), sqlx::Error> {
+    let delivered_at = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE event_outbox SET delivered_at = ? WHERE id = ?",
+        delivered_at,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::events::{AppEvent, Envelope, EventLog};
+use crate::fairings::REQUEST_ID_HEADER;
+
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use thiserror::Error;
+
+/// Wraps the events of a request in envelopes, to be written to the outbox, and notifies the
+/// relay once they're committed.
+///
+/// As a request guard, the request's `x-request-id` becomes the correlation and causation id of
+/// its events. Background tasks publish with `Publisher::new` instead, leaving them unset.
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    log: EventLog,
+    request_id: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum PublisherError {
+    #[error("event log is not managed by rocket")]
+    Unconfigured,
+}
+
+impl Publisher {
+    pub const fn new(log: EventLog) -> Self {
+        Self {
+            log,
+            request_id: None,
+        }
+    }
+
+    pub fn envelope(&self, event: AppEvent) -> Envelope {
+        Envelope {
+            correlation_id: self.request_id.clone(),
+            causation_id: self.request_id.clone(),
+            ..Envelope::new(event)
+        }
+    }
+
+    /// Notifies the relay that events have been committed to the outbox.
+    pub fn notify(&self) {
+        self.log.notify();
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Publisher {
+    type Error = PublisherError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(log) = request.rocket().state::<EventLog>() else {
+            return Outcome::Failure((Status::InternalServerError, PublisherError::Unconfigured));
+        };
+
+        Outcome::Success(Self {
+            log: log.clone(),
+            request_id: request
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .map(ToString::to_string),
+        })
+    }
+}
@@ -0,0 +1,102 @@
+use crate::auth::AuthenticatedUser;
+use crate::events::{service, types::DeadLetter, Dispatcher};
+use crate::permissions;
+use crate::types::{error::ApiError, pagination::Paginated};
+
+use color_eyre::eyre;
+use rocket::{http::Status, route::Route, serde::json::Json};
+use sqlx::SqlitePool;
+
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_dead_letters_route,
+        find_dead_letter_route,
+        redrive_dead_letter_route
+    ]
+}
+
+#[get("/dead-letters?<handler>&<redriven>&<page>&<per_page>")]
+async fn list_dead_letters_route(
+    pool: &rocket::State<SqlitePool>,
+    handler: Option<&str>,
+    redriven: bool,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Paginated<DeadLetter>>, ApiError> {
+    service::list_dead_letters(
+        pool,
+        &attributes,
+        &requesting_user.user_id,
+        handler,
+        redriven,
+        page,
+        per_page,
+    )
+    .await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/dead-letters/<id>")]
+async fn find_dead_letter_route(
+    pool: &rocket::State<SqlitePool>,
+    id: i64,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<DeadLetter>, ApiError> {
+    service::find_dead_letter(pool, &attributes, &requesting_user.user_id, id)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/dead-letters/<id>/redrive")]
+async fn redrive_dead_letter_route(
+    pool: &rocket::State<SqlitePool>,
+    dispatcher: &rocket::State<Dispatcher>,
+    id: i64,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<DeadLetter>, ApiError> {
+    service::redrive_dead_letter(pool, dispatcher, &attributes, &requesting_user.user_id, id)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+impl From<service::FindDeadLetterError> for ApiError {
+    fn from(err: service::FindDeadLetterError) -> Self {
+        match err {
+            service::FindDeadLetterError::InvalidInput(err) => err.into(),
+            service::FindDeadLetterError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindDeadLetterError::NotFound(_) => {
+                Self::new(Status::NotFound).with_detail(err)
+            }
+            service::FindDeadLetterError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindDeadLetterError::Sqlx(_) => Self::internal("find dead letter", &err),
+        }
+    }
+}
+
+impl From<service::RedriveDeadLetterError> for ApiError {
+    fn from(err: service::RedriveDeadLetterError) -> Self {
+        match err {
+            service::RedriveDeadLetterError::Find(err) => err.into(),
+            service::RedriveDeadLetterError::AlreadyRedriven(_)
+            | service::RedriveDeadLetterError::UnknownHandler(_) => {
+                Self::new(Status::Conflict).with_detail(err)
+            }
+            service::RedriveDeadLetterError::HandlerFailed(_) => {
+                Self::new(Status::BadGateway).with_detail(err)
+            }
+            err @ service::RedriveDeadLetterError::Sqlx(_) => {
+                Self::internal("redrive dead letter", &err)
+            }
+        }
+    }
+}
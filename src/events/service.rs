@@ -0,0 +1,144 @@
+use crate::events::{dead_letters, types::DeadLetter, Dispatcher};
+use crate::permissions::{self, types::Resource};
+use crate::tenants::types::Tenant;
+use crate::types::{
+    pagination::{Paginated, Pagination},
+    validation::FieldValidationError,
+};
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FindDeadLetterError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("dead letter `{0}` does not exist")]
+    NotFound(i64),
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(permissions::HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists a page of dead letters, newest first, optionally of one handler and including those
+/// which were redriven.
+///
+/// Dead letters hold events of every tenant, so reading them requires `read-events` on every
+/// tenant, which only operators are granted.
+pub async fn list_dead_letters(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    handler: Option<&str>,
+    redriven: bool,
+    page: Option<u32>,
+    per_page: Option<u32>,
+) -> eyre::Result<Paginated<DeadLetter>, FindDeadLetterError> {
+    let pagination = Pagination::new(page, per_page).map_err(FindDeadLetterError::InvalidInput)?;
+    ensure_permission(pool, attributes, requesting_user_id, "read-events").await?;
+
+    let dead_letters = dead_letters::find_page(pool, handler, redriven, &pagination).await?;
+    let total = dead_letters::count(pool, handler, redriven).await?;
+
+    Ok(Paginated::new(dead_letters, pagination, total))
+}
+
+pub async fn find_dead_letter(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: i64,
+) -> eyre::Result<DeadLetter, FindDeadLetterError> {
+    ensure_permission(pool, attributes, requesting_user_id, "read-events").await?;
+
+    dead_letters::find_one(pool, id)
+        .await?
+        .ok_or(FindDeadLetterError::NotFound(id))
+}
+
+#[derive(Error, Debug)]
+pub enum RedriveDeadLetterError {
+    #[error("failed to find dead letter")]
+    Find(#[from] FindDeadLetterError),
+
+    #[error("dead letter `{0}` was already redriven")]
+    AlreadyRedriven(i64),
+
+    #[error("handler `{0}` is not running")]
+    UnknownHandler(String),
+
+    #[error("handler failed to handle the event again: {0}")]
+    HandlerFailed(String),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Hands the event of a dead letter to its handler once more, requiring `write-events` on every
+/// tenant.
+///
+/// The dead letter is marked redriven once the handler succeeds; when it fails again, the attempt
+/// and its error are recorded on the dead letter, which can be redriven again later.
+pub async fn redrive_dead_letter(
+    pool: &SqlitePool,
+    dispatcher: &Dispatcher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: i64,
+) -> eyre::Result<DeadLetter, RedriveDeadLetterError> {
+    ensure_permission(pool, attributes, requesting_user_id, "write-events").await?;
+
+    let dead_letter = dead_letters::find_one(pool, id)
+        .await?
+        .ok_or(FindDeadLetterError::NotFound(id))?;
+    if dead_letter.redriven_at.is_some() {
+        return Err(RedriveDeadLetterError::AlreadyRedriven(id));
+    }
+    let handler = dispatcher
+        .handler(&dead_letter.handler)
+        .ok_or_else(|| RedriveDeadLetterError::UnknownHandler(dead_letter.handler.clone()))?;
+
+    if let Err(e) = handler.handle(pool, &dead_letter.envelope).await {
+        let error = format!("{e:#}");
+        dead_letters::record_failure(pool, id, &error).await?;
+        return Err(RedriveDeadLetterError::HandlerFailed(error));
+    }
+    dead_letters::mark_redriven(pool, id).await?;
+
+    Ok(dead_letters::find_one(pool, id)
+        .await?
+        .ok_or(FindDeadLetterError::NotFound(id))?)
+}
+
+async fn ensure_permission(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    action: &str,
+) -> eyre::Result<(), FindDeadLetterError> {
+    let can = permissions::has_permission_to(
+        pool,
+        attributes,
+        requesting_user_id,
+        action,
+        Resource::WILDCARD,
+        &Tenant::kind().to_string(),
+    )
+    .await
+    .map_err(FindDeadLetterError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(FindDeadLetterError::PermissionDenied)
+    }
+}
@@ -0,0 +1,83 @@
+use color_eyre::eyre;
+use sqlx::SqliteExecutor;
+
+/// An event as stored in the log, before its JSON is deserialized.
+#[derive(Debug)]
+pub struct EventRecord {
+    pub sequence: i64,
+    pub event: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Appends a serialized event to the log, returning the sequence number it was given.
+pub async fn append<'e>(
+    executor: impl SqliteExecutor<'e>,
+    event: &str,
+) -> eyre::Result<i64, sqlx::Error> {
+    let created_at = chrono::Utc::now().naive_utc();
+
+    Ok(sqlx::query!(
+        "INSERT INTO events (event, created_at) VALUES (?, ?)",
+        event,
+        created_at
+    )
+    .execute(executor)
+    .await?
+    .last_insert_rowid())
+}
+
+/// Finds up to `limit` events appended after `sequence`, oldest first.
+pub async fn find_after<'e>(
+    executor: impl SqliteExecutor<'e>,
+    sequence: i64,
+    limit: i64,
+) -> eyre::Result<Vec<EventRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        EventRecord,
+        "SELECT sequence AS \"sequence!\", event, created_at FROM events
+            WHERE sequence > ?
+            ORDER BY sequence
+            LIMIT ?",
+        sequence,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Finds the sequence number of the last event a handler has handled, which is zero for a handler
+/// which hasn't handled any.
+pub async fn find_cursor<'e>(
+    executor: impl SqliteExecutor<'e>,
+    handler: &str,
+) -> eyre::Result<i64, sqlx::Error> {
+    Ok(sqlx::query_scalar!(
+        "SELECT sequence FROM event_cursors WHERE handler = ?",
+        handler
+    )
+    .fetch_optional(executor)
+    .await?
+    .unwrap_or_default())
+}
+
+pub async fn save_cursor<'e>(
+    executor: impl SqliteExecutor<'e>,
+    handler: &str,
+    sequence: i64,
+) -> eyre::Result<(), sqlx::Error> {
+    let updated_at = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        "
+INSERT INTO event_cursors (handler, sequence, updated_at) VALUES (?, ?, ?)
+ON CONFLICT (handler) DO UPDATE SET sequence = excluded.sequence, updated_at = excluded.updated_at
+        ",
+        handler,
+        sequence,
+        updated_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
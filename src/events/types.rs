@@ -0,0 +1,276 @@
+use crate::events::{sqlite::EventRecord, AppEvent};
+use crate::tenants::types::TenantId;
+use crate::types::{id::define_id, uuid::Uuid};
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use serde::{de::Error as _, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+define_id!(EventId, "event");
+
+/// The schema version events are written in, which is bumped whenever the stored form of an event
+/// changes so that events written before can be upcast to it when read.
+///
+/// 1. The bare event, externally tagged by the `PascalCase` names of its enum variants, as written
+///    before events had envelopes.
+/// 2. The data of the event within an `Envelope`, named by its type.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// An event along with where and when it happened, which is how events are stored in the log,
+/// read by handlers and sent to other systems.
+///
+/// Serializes as JSON with the event's `type`, such as `user.created`, next to its `data`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "StoredEnvelope")]
+pub struct Envelope {
+    pub id: EventId,
+    /// The name of the event's type, made of its domain and variant in snake case.
+    pub kind: String,
+    pub occurred_at: chrono::NaiveDateTime,
+    /// The tenant the event happened within, if any.
+    pub tenant_id: Option<TenantId>,
+    /// The user whose request caused the event, if any.
+    pub actor_id: Option<UserId>,
+    /// The id of the request the event ultimately stems from, shared by every event it caused.
+    pub correlation_id: Option<String>,
+    /// The id of the request or event which directly caused the event.
+    pub causation_id: Option<String>,
+    pub event: AppEvent,
+}
+
+/// The form an envelope is serialized in, with the event as its data.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEnvelope {
+    id: EventId,
+    #[serde(rename = "type")]
+    kind: String,
+    version: u32,
+    occurred_at: chrono::NaiveDateTime,
+    tenant_id: Option<TenantId>,
+    actor_id: Option<UserId>,
+    correlation_id: Option<String>,
+    causation_id: Option<String>,
+    data: Value,
+}
+
+impl Envelope {
+    /// Wraps an event which has just happened, within the tenant it belongs to if it names one.
+    pub fn new(event: AppEvent) -> Self {
+        Self {
+            id: EventId::new(),
+            kind: event.kind(),
+            occurred_at: chrono::Utc::now().naive_utc(),
+            tenant_id: event.tenant_id(),
+            actor_id: None,
+            correlation_id: None,
+            causation_id: None,
+            event,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_actor(mut self, actor_id: &UserId) -> Self {
+        self.actor_id = Some(*actor_id);
+        self
+    }
+
+    /// Sets the tenant of an event which doesn't name one itself, such as a user signing up into a
+    /// tenant.
+    #[must_use]
+    pub const fn with_tenant(mut self, tenant_id: &TenantId) -> Self {
+        self.tenant_id = Some(*tenant_id);
+        self
+    }
+
+    /// Deserializes an envelope from the log, upcasting it from the schema version it was
+    /// written in.
+    pub fn from_record(record: &EventRecord) -> eyre::Result<Self, serde_json::Error> {
+        let value: Value = serde_json::from_str(&record.event)?;
+        let version: u32 = match value.get("version") {
+            Some(version) => serde_json::from_value(version.clone())?,
+            None => 1,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(serde_json::Error::custom(format!(
+                "unsupported schema version {version}"
+            )));
+        }
+
+        if version >= 2 {
+            return serde_json::from_value(value);
+        }
+
+        let mut envelope: Self = serde_json::from_value(upcast_bare_event(value, record)?)?;
+        envelope.tenant_id = envelope.event.tenant_id();
+        Ok(envelope)
+    }
+}
+
+/// Whether an event's type matches a pattern, which is either a type such as `user.created`, every
+/// type of a domain such as `user.*`, or `*` for every type.
+pub fn type_matches(pattern: &str, kind: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(domain) => domain.ends_with('.') && kind.starts_with(domain),
+        None => pattern == kind,
+    }
+}
+
+/// The domains which name the first part of an event's type, one for each variant of `AppEvent`.
+pub const DOMAINS: &[&str] = &["user", "profile", "permission", "tenant", "membership"];
+
+/// Whether a pattern is one `type_matches` can match events against, within a known domain.
+pub fn is_type_pattern(pattern: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let Some((domain, variant)) = pattern.split_once('.') else {
+        return false;
+    };
+
+    DOMAINS.contains(&domain)
+        && (variant == "*"
+            || (!variant.is_empty() && variant.chars().all(|c| c.is_ascii_lowercase() || c == '_')))
+}
+
+/// An event a handler still failed to handle after retrying, kept so that it can be inspected and
+/// redriven.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub handler: String,
+    pub sequence: i64,
+    #[serde(rename = "event")]
+    pub envelope: Envelope,
+    /// The error of the last attempt at handling the event.
+    pub error: String,
+    pub attempts: i64,
+    pub failed_at: chrono::NaiveDateTime,
+    /// When the event was handled after being redriven; the dead letter is kept as a record.
+    pub redriven_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Serialize for Envelope {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (_, data) = serde_json::to_value(&self.event)
+            .and_then(split_event)
+            .map_err(serde::ser::Error::custom)?;
+
+        StoredEnvelope {
+            id: self.id,
+            kind: self.kind.clone(),
+            version: SCHEMA_VERSION,
+            occurred_at: self.occurred_at,
+            tenant_id: self.tenant_id,
+            actor_id: self.actor_id,
+            correlation_id: self.correlation_id.clone(),
+            causation_id: self.causation_id.clone(),
+            data,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl TryFrom<StoredEnvelope> for Envelope {
+    type Error = serde_json::Error;
+
+    fn try_from(stored: StoredEnvelope) -> Result<Self, Self::Error> {
+        let Some((domain, variant)) = stored.kind.split_once('.') else {
+            return Err(serde_json::Error::custom(format!(
+                "invalid event type `{}`",
+                stored.kind
+            )));
+        };
+        let event = serde_json::from_value(tagged(domain, tagged(variant, stored.data)))?;
+
+        Ok(Self {
+            id: stored.id,
+            kind: stored.kind,
+            occurred_at: stored.occurred_at,
+            tenant_id: stored.tenant_id,
+            actor_id: stored.actor_id,
+            correlation_id: stored.correlation_id,
+            causation_id: stored.causation_id,
+            event,
+        })
+    }
+}
+
+impl AppEvent {
+    /// The tenant the event names, if any; user and profile events belong to no tenant.
+    pub fn tenant_id(&self) -> Option<TenantId> {
+        match self {
+            Self::User(_) | Self::Profile(_) => None,
+            Self::Permission(event) => event.tenant_id(),
+            Self::Tenant(event) => Some(event.tenant_id()),
+            Self::Membership(event) => Some(event.tenant_id()),
+        }
+    }
+
+    /// The name of the event's type, such as `user.created` or `tenant.invitation_accepted`.
+    pub fn kind(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| split_event(value).ok())
+            .map(|(kind, _)| kind)
+            .unwrap_or_default()
+    }
+}
+
+/// Splits an externally tagged event, `{"domain": {"variant": data}}`, into its type name and
+/// data.
+fn split_event(value: Value) -> eyre::Result<(String, Value), serde_json::Error> {
+    let (domain, value) = untagged(value)?;
+    let (variant, data) = untagged(value)?;
+
+    Ok((format!("{domain}.{variant}"), data))
+}
+
+fn untagged(value: Value) -> eyre::Result<(String, Value), serde_json::Error> {
+    match value {
+        Value::Object(map) if map.len() == 1 => Ok(map.into_iter().next().unwrap_or_default()),
+        value => Err(serde_json::Error::custom(format!(
+            "expected an externally tagged enum, found {value}"
+        ))),
+    }
+}
+
+fn tagged(tag: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(tag.to_string(), value);
+    Value::Object(map)
+}
+
+/// Wraps an event written in schema version 1 in an envelope.
+///
+/// Nothing is known of who caused such an event beyond what it names itself, and it's given an id
+/// derived from its sequence number so that the id stays the same however often the event is read.
+fn upcast_bare_event(value: Value, record: &EventRecord) -> eyre::Result<Value, serde_json::Error> {
+    let (kind, data) = split_event(value)?;
+    let (domain, variant) = kind.split_once('.').unwrap_or_default();
+    let sequence = u128::try_from(record.sequence).map_err(serde_json::Error::custom)?;
+
+    serde_json::to_value(StoredEnvelope {
+        id: EventId(Uuid::from_u128(sequence)),
+        kind: format!("{}.{}", snake_case(domain), snake_case(variant)),
+        version: 2,
+        occurred_at: record.created_at,
+        tenant_id: None,
+        actor_id: None,
+        correlation_id: None,
+        causation_id: None,
+        data,
+    })
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
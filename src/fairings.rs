@@ -1,7 +1,10 @@
+use crate::auth;
 use crate::events;
+use crate::permissions;
+use crate::types;
+use crate::webhooks;
 
-use bus::Bus;
-use rocket::{fairing, fairing::Fairing, http, Build, Rocket};
+use rocket::{fairing, fairing::Fairing, http, Build, Orbit, Rocket};
 use sqlx::SqlitePool;
 use std::env;
 use std::sync::Arc;
@@ -27,20 +30,31 @@ impl Fairing for EventProcessor {
     }
 
     async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
-        let size = env::var("EVENT_PROCESS_BUS_SIZE")
-            .unwrap_or_else(|_| "10".to_string())
-            .parse::<usize>()
+        let interval = env::var("EVENT_RELAY_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u64>()
             .unwrap_or_else(|e| panic!("{}", e));
-        let mut bus = Bus::new(size);
+        let Some(pool) = rocket.state::<SqlitePool>().cloned() else {
+            panic!("event processor requires the database fairing");
+        };
+        let log = events::EventLog::start(pool.clone(), std::time::Duration::from_secs(interval));
+        tracing::info!("relaying events from the outbox every {} seconds", interval);
 
-        for handler in &self.handlers {
-            handler
-                .handle(Arc::new(tokio::sync::Mutex::new(bus.add_rx())))
-                .unwrap_or_else(|e| panic!("{}", e.to_string()));
-        }
+        let dispatcher =
+            events::Dispatcher::new(pool, self.handlers.clone(), events::RetryPolicy::from_env());
+        dispatcher
+            .start(&log)
+            .await
+            .unwrap_or_else(|e| panic!("failed to start event handlers: {e}"));
+        tracing::info!(
+            "retrying event handlers up to {} times",
+            dispatcher.retry().max_attempts
+        );
 
-        let bus: tokio::sync::Mutex<Bus<events::AppEvent>> = tokio::sync::Mutex::new(bus);
-        Ok(rocket.manage::<tokio::sync::Mutex<Bus<events::AppEvent>>>(bus))
+        // shared so that background tasks, such as the permission sweeper, can notify the relay too
+        Ok(rocket
+            .manage::<events::EventLog>(log)
+            .manage::<events::Dispatcher>(dispatcher))
     }
 }
 
@@ -70,6 +84,21 @@ impl Fairing for SqliteDatabase {
             .await
             .unwrap_or_else(|_| panic!("failed to run migrations"));
         tracing::info!("latest database migrations executed");
+        let ids = types::uuid::IdConfig::from_env();
+        types::uuid::configure(ids);
+        let converted = types::sqlite::convert_id_storage(&pool, ids.storage)
+            .await
+            .unwrap_or_else(|e| {
+                panic!("failed to convert ids to {:?} storage: {}", ids.storage, e)
+            });
+        if converted > 0 {
+            tracing::info!("converted {} ids to {:?} storage", converted, ids.storage);
+        }
+        tracing::info!(
+            "generating {:?} ids stored as {:?}",
+            ids.version,
+            ids.storage
+        );
         sqlx::query!("PRAGMA foreign_keys = ON;")
             .execute(&pool)
             .await
@@ -80,6 +109,148 @@ impl Fairing for SqliteDatabase {
     }
 }
 
+/// Periodically deletes expired permission grants once the server has launched.
+///
+/// The interval is read from `PERMISSION_SWEEP_INTERVAL_SECONDS`, defaulting to a minute.
+pub struct PermissionSweeper;
+
+#[rocket::async_trait]
+impl Fairing for PermissionSweeper {
+    fn info(&self) -> fairing::Info {
+        fairing::Info {
+            name: "PermissionSweeper",
+            kind: fairing::Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let interval = env::var("PERMISSION_SWEEP_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let (Some(pool), Some(event_log)) = (
+            rocket.state::<SqlitePool>().cloned(),
+            rocket.state::<events::EventLog>().cloned(),
+        ) else {
+            panic!("permission sweeper requires the database and event processor fairings");
+        };
+        let publisher = events::Publisher::new(event_log);
+        let mut shutdown = rocket.shutdown();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(std::time::Duration::from_secs(interval));
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = &mut shutdown => break,
+                }
+                match permissions::sweep_expired_permissions(&pool, &publisher).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("swept {} expired permissions", count),
+                    Err(err) => tracing::error!("failed to sweep expired permissions: {:?}", err),
+                }
+            }
+        });
+        tracing::info!("sweeping expired permissions every {} seconds", interval);
+    }
+}
+
+/// Periodically retries the failed webhook deliveries which are due once the server has launched.
+///
+/// The interval is read from `WEBHOOK_RETRY_INTERVAL_SECONDS`, defaulting to five seconds.
+pub struct WebhookRetrier {
+    retry: events::RetryPolicy,
+}
+
+impl WebhookRetrier {
+    pub const fn new(retry: events::RetryPolicy) -> Self {
+        Self { retry }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for WebhookRetrier {
+    fn info(&self) -> fairing::Info {
+        fairing::Info {
+            name: "WebhookRetrier",
+            kind: fairing::Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let interval = env::var("WEBHOOK_RETRY_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u64>()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let (Some(pool), Some(sender)) = (
+            rocket.state::<SqlitePool>().cloned(),
+            rocket.state::<webhooks::Sender>().cloned(),
+        ) else {
+            panic!("webhook retrier requires the database fairing and a webhook sender");
+        };
+        let retry = self.retry;
+        let mut shutdown = rocket.shutdown();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(std::time::Duration::from_secs(interval));
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = &mut shutdown => break,
+                }
+                match webhooks::retry_deliveries(&pool, &sender, &retry).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("retrying {} webhook deliveries", count),
+                    Err(err) => tracing::error!("failed to retry webhook deliveries: {:?}", err),
+                }
+            }
+        });
+        tracing::info!(
+            "retrying webhook deliveries up to {} times, checking every {} seconds",
+            retry.max_attempts,
+            interval
+        );
+    }
+}
+
+pub struct Authentication;
+
+#[rocket::async_trait]
+impl Fairing for Authentication {
+    fn info(&self) -> fairing::Info {
+        fairing::Info {
+            name: "Authentication",
+            kind: fairing::Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = auth::SessionConfig::from_env();
+        tracing::info!(
+            "sessions configured with a ttl of {} seconds",
+            config.ttl.num_seconds()
+        );
+
+        let jwt_config = auth::JwtConfig::from_env();
+        tracing::info!(
+            "access tokens configured with {:?} and a ttl of {} seconds",
+            jwt_config.algorithm,
+            jwt_config.access_ttl.num_seconds()
+        );
+
+        let identity_provider = auth::identity_provider_from_env();
+        tracing::info!(
+            "signing in with identity provider {}",
+            identity_provider.name()
+        );
+
+        Ok(rocket
+            .manage::<auth::SessionConfig>(config)
+            .manage::<auth::JwtConfig>(jwt_config)
+            .manage::<Arc<dyn auth::IdentityProvider>>(identity_provider))
+    }
+}
+
 pub const REQUEST_ID_HEADER: &str = "x-request-id";
 pub struct RequestID;
 
@@ -15,11 +15,13 @@ extern crate rocket;
 mod auth;
 mod events;
 mod fairings;
+mod memberships;
 mod permissions;
 mod profiles;
 mod tenants;
 mod types;
 mod users;
+mod webhooks;
 
 use color_eyre::eyre;
 use rocket::http::Status;
@@ -39,21 +41,40 @@ async fn main() -> eyre::Result<()> {
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
+    // shared by the webhook handler, the webhook retrier and the route redelivering webhooks
+    let webhook_sender = webhooks::Sender::from_env();
+    let webhook_retry = events::RetryPolicy::from_env_with_prefix("WEBHOOK");
+
     // web framework
     let _rocket = rocket::build()
         .mount("/api", routes())
         .mount("/api/auth", auth::routes())
+        .mount("/api/events", events::routes())
+        .mount("/api/invitations", tenants::invitation_routes())
+        .mount("/api/members", memberships::routes())
         .mount("/api/permissions", permissions::routes())
+        .mount("/api/roles", permissions::role_routes())
         .mount("/api/tenants", tenants::routes())
         .mount("/api/users", users::routes())
+        .mount("/api/webhooks", webhooks::routes())
+        .register("/api", types::error::catchers())
         .attach(fairings::RequestID)
+        .attach(fairings::Authentication)
         .attach(fairings::SqliteDatabase)
         .attach(fairings::EventProcessor::new(vec![
+            memberships::events::MembershipsEventHandler::new_handler(),
             permissions::events::PermissionsEventHandler::new_handler(),
             profiles::events::ProfilesEventHandler::new_handler(),
             tenants::events::TenantsEventHandler::new_handler(),
             users::events::UsersEventHandler::new_handler(),
+            webhooks::events::WebhooksEventHandler::new_handler(
+                webhook_sender.clone(),
+                webhook_retry,
+            ),
         ]))
+        .attach(fairings::PermissionSweeper)
+        .attach(fairings::WebhookRetrier::new(webhook_retry))
+        .manage(webhook_sender)
         .launch()
         .await?;
 
@@ -0,0 +1,60 @@
+use crate::events;
+use crate::memberships::types::Membership;
+use crate::tenants::types::TenantId;
+
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+/// Changes made through the membership API.
+///
+/// Memberships recorded as part of creating a user or tenant are implied by
+/// `UserEvent::Created` and `TenantEvent::Created` and aren't published separately.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipEvent {
+    Added(Membership),
+    RoleChanged(Membership),
+    Removed(Membership),
+}
+
+impl MembershipEvent {
+    pub const fn tenant_id(&self) -> TenantId {
+        match self {
+            Self::Added(membership) | Self::RoleChanged(membership) | Self::Removed(membership) => {
+                membership.tenant_id
+            }
+        }
+    }
+}
+
+pub struct MembershipsEventHandler;
+
+impl MembershipsEventHandler {
+    pub fn new_handler() -> Arc<dyn events::EventHandler> {
+        Arc::new(Self {})
+    }
+}
+
+#[rocket::async_trait]
+impl events::EventHandler for MembershipsEventHandler {
+    fn name(&self) -> &'static str {
+        "memberships"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["membership.*"]
+    }
+
+    async fn handle(&self, _pool: &SqlitePool, envelope: &events::Envelope) -> eyre::Result<()> {
+        tracing::debug!(
+            "handling {} {}: {:?}",
+            envelope.kind,
+            envelope.id,
+            envelope.event
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use crate::auth::SessionConfig;
+use crate::events::{outbox, AppEvent, Publisher};
+use crate::memberships::{self, domain::events, types};
+use crate::permissions;
+use crate::profiles;
+use crate::tenants::{self, TenantContext, TenantScope};
+use crate::types::validation::FieldValidationError;
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use serde::Deserialize;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FindMembershipError {
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("id `{0}` does not exist")]
+    NotFound(String),
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(permissions::HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists the members of the current tenant; any user who can read the tenant can see its
+/// members.
+pub async fn list_members(
+    pool: &SqlitePool,
+    context: &TenantContext,
+) -> eyre::Result<Vec<types::Membership>, FindMembershipError> {
+    ensure_permission(pool, context, "read-tenant").await?;
+
+    memberships::sqlite::find_all(pool, &context.scope)
+        .await
+        .map_err(FindMembershipError::Sqlx)
+}
+
+/// Inserts a membership and assigns its role within an existing transaction.
+///
+/// Callers are responsible for committing the transaction, which lets memberships be recorded
+/// atomically alongside the user or tenant they're created with.
+pub async fn insert_membership(
+    tx: &mut Transaction<'_, Sqlite>,
+    scope: &TenantScope,
+    user_id: &UserId,
+    role: types::Role,
+) -> eyre::Result<types::Membership, sqlx::Error> {
+    let membership = memberships::sqlite::insert(&mut *tx, scope, user_id, role).await?;
+    assign_role(tx, &membership).await?;
+
+    Ok(membership)
+}
+
+#[derive(Error, Debug)]
+pub enum AddMemberError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("failed to find membership")]
+    Find(#[from] FindMembershipError),
+
+    #[error("failed to invite user")]
+    Invite(#[from] tenants::InviteError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberRequest {
+    pub user_id: String,
+    pub role: types::Role,
+}
+
+/// Invites an existing user to the current tenant, by the email of their profile.
+///
+/// Nobody is made a member of a tenant without agreeing to it, so this makes the same invitation
+/// as inviting their email would, under the same permissions, and the user joins by accepting it.
+pub async fn add_member(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    config: &SessionConfig,
+    context: &TenantContext,
+    payload: AddMemberRequest,
+) -> eyre::Result<tenants::CreatedInvitation, AddMemberError> {
+    let user_id = UserId::try_from(payload.user_id.as_str()).map_err(|e| {
+        AddMemberError::InvalidInput(FieldValidationError {
+            field: "user_id".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    ensure_permission(pool, context, "write-tenant").await?;
+
+    let Some(profile) = profiles::sqlite::find_by_user_id(pool, &user_id).await? else {
+        return Err(AddMemberError::InvalidInput(FieldValidationError {
+            field: "user_id".to_string(),
+            message: format!("user `{user_id}` does not exist"),
+        }));
+    };
+
+    Ok(tenants::create_invitation(
+        pool,
+        publisher,
+        config,
+        context,
+        tenants::CreateInvitationRequest {
+            email: profile.email.to_string(),
+            role: payload.role,
+            expires_in_hours: None,
+        },
+    )
+    .await?)
+}
+
+#[derive(Error, Debug)]
+pub enum ChangeMemberError {
+    #[error("a tenant must keep at least one owner")]
+    LastOwner,
+
+    #[error("failed to find membership")]
+    Find(#[from] FindMembershipError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeRoleRequest {
+    pub role: types::Role,
+}
+
+/// Changes the role of a member, replacing the role they're assigned on the tenant.
+pub async fn change_member_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    user_id: &UserId,
+    payload: ChangeRoleRequest,
+) -> eyre::Result<types::Membership, ChangeMemberError> {
+    ensure_permission(pool, context, "write-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(ChangeMemberError::Sqlx)?;
+    let Some(mut membership) =
+        memberships::sqlite::find_one(&mut tx, &context.scope, user_id).await?
+    else {
+        return Err(ChangeMemberError::Find(FindMembershipError::NotFound(
+            user_id.to_string(),
+        )));
+    };
+    if membership.role == types::Role::Owner || payload.role == types::Role::Owner {
+        ensure_permission(pool, context, "execute-tenant").await?;
+    }
+    if membership.role == types::Role::Owner && payload.role != types::Role::Owner {
+        ensure_other_owner(&mut tx, &context.scope).await?;
+    }
+
+    memberships::sqlite::update_role(&mut tx, &context.scope, user_id, payload.role).await?;
+    membership.role = payload.role;
+    assign_role(&mut tx, &membership).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Membership(events::MembershipEvent::RoleChanged(
+                membership.clone(),
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(ChangeMemberError::Sqlx)?;
+    tx.commit().await.map_err(ChangeMemberError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(membership)
+}
+
+/// Removes a user from the current tenant along with every grant and role they hold within it.
+pub async fn remove_member(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    user_id: &UserId,
+) -> eyre::Result<(), ChangeMemberError> {
+    ensure_permission(pool, context, "write-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(ChangeMemberError::Sqlx)?;
+    let Some(membership) = memberships::sqlite::find_one(&mut tx, &context.scope, user_id).await?
+    else {
+        return Err(ChangeMemberError::Find(FindMembershipError::NotFound(
+            user_id.to_string(),
+        )));
+    };
+    if membership.role == types::Role::Owner {
+        ensure_permission(pool, context, "execute-tenant").await?;
+        ensure_other_owner(&mut tx, &context.scope).await?;
+    }
+
+    memberships::sqlite::delete(&mut tx, &context.scope, user_id).await?;
+    permissions::sqlite::delete_by_user_and_resource(
+        &mut tx,
+        user_id,
+        &permissions::types::Resource::Tenant(context.scope.tenant_id().to_string()),
+    )
+    .await?;
+    permissions::sqlite::delete_tenant_role_assignments(&mut tx, &context.scope, user_id).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Membership(events::MembershipEvent::Removed(
+                membership,
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(ChangeMemberError::Sqlx)?;
+    tx.commit().await.map_err(ChangeMemberError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(())
+}
+
+/// Assigns the global role backing a membership on its tenant, replacing the one assigned for the
+/// member's previous role.
+async fn assign_role(
+    tx: &mut Transaction<'_, Sqlite>,
+    membership: &types::Membership,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource = permissions::types::Resource::Tenant(membership.tenant_id.to_string());
+    let role = permissions::sqlite::find_global_role(&mut *tx, &membership.role.to_string())
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    permissions::sqlite::delete_global_role_assignments(&mut *tx, &membership.user_id, &resource)
+        .await?;
+    permissions::sqlite::insert_role_assignment(
+        &mut *tx,
+        &permissions::types::RoleAssignment {
+            role_id: role.id,
+            user_id: membership.user_id,
+            resource,
+        },
+    )
+    .await
+}
+
+async fn ensure_other_owner(
+    tx: &mut Transaction<'_, Sqlite>,
+    scope: &TenantScope,
+) -> eyre::Result<(), ChangeMemberError> {
+    if memberships::sqlite::count_by_role(&mut *tx, scope, types::Role::Owner).await? <= 1 {
+        return Err(ChangeMemberError::LastOwner);
+    }
+
+    Ok(())
+}
+
+async fn ensure_permission(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    action: &str,
+) -> eyre::Result<(), FindMembershipError> {
+    let can = permissions::has_permission_to(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        action,
+        &context.scope.tenant_id().to_string(),
+        &tenants::types::Tenant::kind().to_string(),
+    )
+    .await
+    .map_err(FindMembershipError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(FindMembershipError::PermissionDenied)
+    }
+}
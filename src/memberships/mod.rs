@@ -0,0 +1,8 @@
+mod domain;
+mod routes;
+
+pub mod sqlite;
+pub mod types;
+pub use domain::events;
+pub use domain::service::*;
+pub use routes::routes;
@@ -0,0 +1,110 @@
+use crate::auth::SessionConfig;
+use crate::events::Publisher;
+use crate::memberships::{domain::service, types};
+use crate::tenants::{self, TenantContext};
+use crate::types::{error::ApiError, id::InvalidId};
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use rocket::{http::Status, response::status, route::Route, serde::json::Json};
+use sqlx::SqlitePool;
+
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_members_route,
+        add_member_route,
+        change_member_role_route,
+        remove_member_route
+    ]
+}
+
+#[get("/")]
+async fn list_members_route(
+    pool: &rocket::State<SqlitePool>,
+    context: TenantContext,
+) -> eyre::Result<Json<Vec<types::Membership>>, ApiError> {
+    service::list_members(pool, &context)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/", data = "<payload>")]
+async fn add_member_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    config: &rocket::State<SessionConfig>,
+    payload: Json<service::AddMemberRequest>,
+    context: TenantContext,
+) -> eyre::Result<status::Created<Json<tenants::CreatedInvitation>>, ApiError> {
+    let created =
+        service::add_member(pool, &publisher, config, &context, payload.into_inner()).await?;
+
+    Ok(
+        status::Created::new(format!("/api/invitations/{}", created.invitation.id))
+            .body(Json(created)),
+    )
+}
+
+#[put("/<user_id>", data = "<payload>")]
+async fn change_member_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
+    payload: Json<service::ChangeRoleRequest>,
+    context: TenantContext,
+) -> eyre::Result<Json<types::Membership>, ApiError> {
+    service::change_member_role(pool, &publisher, &context, &user_id?, payload.into_inner())
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[delete("/<user_id>")]
+async fn remove_member_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Status, ApiError> {
+    service::remove_member(pool, &publisher, &context, &user_id?).await?;
+
+    Ok(Status::NoContent)
+}
+
+impl From<service::AddMemberError> for ApiError {
+    fn from(err: service::AddMemberError) -> Self {
+        match err {
+            service::AddMemberError::InvalidInput(err) => err.into(),
+            service::AddMemberError::Find(err) => err.into(),
+            service::AddMemberError::Invite(err) => err.into(),
+            err @ service::AddMemberError::Sqlx(_) => Self::internal("add member", &err),
+        }
+    }
+}
+
+impl From<service::ChangeMemberError> for ApiError {
+    fn from(err: service::ChangeMemberError) -> Self {
+        match err {
+            service::ChangeMemberError::LastOwner => Self::new(Status::Conflict).with_detail(err),
+            service::ChangeMemberError::Find(err) => err.into(),
+            err @ service::ChangeMemberError::Sqlx(_) => Self::internal("change membership", &err),
+        }
+    }
+}
+
+impl From<service::FindMembershipError> for ApiError {
+    fn from(err: service::FindMembershipError) -> Self {
+        match err {
+            service::FindMembershipError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindMembershipError::NotFound(_) => {
+                Self::new(Status::NotFound).with_detail(err)
+            }
+            service::FindMembershipError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindMembershipError::Sqlx(_) => Self::internal("find membership", &err),
+        }
+    }
+}
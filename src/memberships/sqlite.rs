@@ -0,0 +1,265 @@
+use crate::memberships::types;
+use crate::tenants::{types::TenantId, TenantScope};
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor};
+use std::convert::TryFrom;
+
+#[derive(sqlx::FromRow)]
+struct MembershipRecord {
+    user_id: UserId,
+    tenant_id: TenantId,
+    role: String,
+    joined_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<MembershipRecord> for types::Membership {
+    type Error = sqlx::Error;
+
+    fn try_from(record: MembershipRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: record.user_id,
+            tenant_id: record.tenant_id,
+            role: types::Role::try_from(record.role).map_err(|e| sqlx::Error::Decode(e.into()))?,
+            joined_at: record.joined_at,
+        })
+    }
+}
+
+/// Finds a user's membership of a tenant.
+///
+/// This is how access to a tenant is established in the first place, so unlike the other
+/// queries here it isn't scoped by a `TenantScope`.
+pub async fn find_for_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+    tenant_id: &TenantId,
+) -> eyre::Result<Option<types::Membership>, sqlx::Error> {
+    let membership = match sqlx::query_as!(
+        MembershipRecord,
+        "SELECT user_id AS \"user_id: UserId\", tenant_id AS \"tenant_id: TenantId\", role,
+                joined_at
+            FROM memberships
+            WHERE user_id = ? AND tenant_id = ?",
+        user_id,
+        tenant_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(m) => m,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Membership::try_from(membership).map(Some)
+}
+
+/// Finds every tenant membership of a user, unscoped like `find_for_user` as it is used to
+/// resolve which tenants a user belongs to when checking access to them.
+pub async fn find_all_for_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<Vec<types::Membership>, sqlx::Error> {
+    sqlx::query_as!(
+        MembershipRecord,
+        "SELECT user_id AS \"user_id: UserId\", tenant_id AS \"tenant_id: TenantId\", role,
+                joined_at
+            FROM memberships
+            WHERE user_id = ?",
+        user_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Membership::try_from)
+    .collect()
+}
+
+/// Finds every tenant membership of several users in one query, unscoped like
+/// `find_all_for_user`.
+///
+/// The ids are bound one by one rather than as a list, so that they are compared in the storage
+/// of the ids they're compared to.
+pub async fn find_all_for_users<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_ids: &[UserId],
+) -> eyre::Result<Vec<types::Membership>, sqlx::Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(
+        "SELECT user_id, tenant_id, role, joined_at FROM memberships WHERE user_id IN (",
+    );
+    let mut ids = query.separated(", ");
+    for user_id in user_ids {
+        ids.push_bind(*user_id);
+    }
+    query.push(")");
+
+    query
+        .build_query_as::<MembershipRecord>()
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(types::Membership::try_from)
+        .collect()
+}
+
+pub async fn find_one<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    user_id: &UserId,
+) -> eyre::Result<Option<types::Membership>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let membership = match sqlx::query_as!(
+        MembershipRecord,
+        "SELECT user_id AS \"user_id: UserId\", tenant_id AS \"tenant_id: TenantId\", role,
+                joined_at
+            FROM memberships
+            WHERE tenant_id = ? AND user_id = ?",
+        tenant_id,
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(m) => m,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Membership::try_from(membership).map(Some)
+}
+
+pub async fn find_all<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+) -> eyre::Result<Vec<types::Membership>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query_as!(
+        MembershipRecord,
+        "SELECT user_id AS \"user_id: UserId\", tenant_id AS \"tenant_id: TenantId\", role,
+                joined_at
+            FROM memberships
+            WHERE tenant_id = ?
+            ORDER BY joined_at, user_id",
+        tenant_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Membership::try_from)
+    .collect()
+}
+
+pub async fn count_by_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    role: types::Role,
+) -> eyre::Result<i64, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let role = role.to_string();
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(1) FROM memberships WHERE tenant_id = ? AND role = ?",
+        tenant_id,
+        role
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(count.into())
+}
+
+pub async fn insert<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    user_id: &UserId,
+    role: types::Role,
+) -> eyre::Result<types::Membership, sqlx::Error> {
+    let membership = types::Membership::new(user_id, scope.tenant_id(), role);
+    let role = membership.role.to_string();
+
+    sqlx::query!(
+        "
+INSERT INTO memberships (user_id, tenant_id, role, joined_at)
+VALUES (?, ?, ?, ?)
+    ",
+        membership.user_id,
+        membership.tenant_id,
+        role,
+        membership.joined_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(membership)
+}
+
+pub async fn update_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    user_id: &UserId,
+    role: types::Role,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let role = role.to_string();
+
+    sqlx::query!(
+        "UPDATE memberships SET role = ? WHERE tenant_id = ? AND user_id = ?",
+        role,
+        tenant_id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    user_id: &UserId,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query!(
+        "DELETE FROM memberships WHERE tenant_id = ? AND user_id = ?",
+        tenant_id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenants;
+    use crate::types::sqlite::test_pool;
+    use crate::users;
+
+    #[rocket::async_test]
+    async fn memberships_of_another_tenant_cannot_be_read_or_deleted() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|member")).await?;
+        let tenant_a = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let tenant_b = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("b")).await?;
+        let scope_a = TenantScope::unchecked(&tenant_a.id);
+        let scope_b = TenantScope::unchecked(&tenant_b.id);
+        let membership = insert(&pool, &scope_b, &user.id, types::Role::Owner).await?;
+
+        assert_eq!(find_one(&pool, &scope_a, &user.id).await?, None);
+        assert!(find_all(&pool, &scope_a).await?.is_empty());
+        assert_eq!(count_by_role(&pool, &scope_a, types::Role::Owner).await?, 0);
+        update_role(&pool, &scope_a, &user.id, types::Role::Member).await?;
+        delete(&pool, &scope_a, &user.id).await?;
+        assert_eq!(find_one(&pool, &scope_b, &user.id).await?, Some(membership));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use crate::tenants::types::TenantId;
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The role a user holds within a tenant.
+///
+/// Each is backed by the global `permissions::types::Role` of the same name, which members are
+/// assigned on the tenant and which determines the actions they can take on it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl TryFrom<&str> for Role {
+    type Error = eyre::Report;
+
+    fn try_from(s: &str) -> eyre::Result<Self> {
+        match s {
+            "owner" => Ok(Self::Owner),
+            "admin" => Ok(Self::Admin),
+            "member" => Ok(Self::Member),
+            _ => Err(eyre::eyre!("invalid role `{s}`")),
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = eyre::Report;
+
+    fn try_from(s: String) -> eyre::Result<Self> {
+        Self::try_from(s.as_str())
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Member => "member",
+        };
+
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Membership {
+    pub user_id: UserId,
+    pub tenant_id: TenantId,
+    pub role: Role,
+    pub joined_at: chrono::NaiveDateTime,
+}
+
+impl Membership {
+    pub fn new(user_id: &UserId, tenant_id: &TenantId, role: Role) -> Self {
+        Self {
+            user_id: *user_id,
+            tenant_id: *tenant_id,
+            role,
+            joined_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
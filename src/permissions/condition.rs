@@ -0,0 +1,351 @@
+use crate::tenants::{types::TenantId, TenantContext};
+
+use chrono::{Datelike, NaiveTime, Weekday};
+use color_eyre::eyre;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::IpAddr;
+
+/// How deeply `all`, `any` and `not` may nest within a condition.
+const MAX_CONDITION_DEPTH: usize = 8;
+
+/// An expression restricting when a grant applies, evaluated against the attributes of each
+/// request checking it.
+///
+/// Conditions are stored as JSON alongside the grant, such as
+/// `{"all": [{"time_between": {"start": "09:00:00", "end": "17:00:00"}}, "same_tenant"]}`.
+/// Times are in UTC and a range ending before it starts wraps past midnight. A condition
+/// depending on an attribute the request lacks, such as a client IP, never holds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    TimeBetween {
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+    Weekdays(Vec<Weekday>),
+    IpIn(Vec<Cidr>),
+    TenantIn(Vec<TenantId>),
+    /// The resource belongs to the tenant of the request: it is that tenant, or a user who is a
+    /// member of it.
+    SameTenant,
+}
+
+impl Condition {
+    /// Whether the condition holds for a request, where `resource_tenant_ids` are the tenants
+    /// the requested resource belongs to.
+    pub fn holds(&self, attributes: &Attributes, resource_tenant_ids: &[String]) -> bool {
+        match self {
+            Self::All(conditions) => conditions
+                .iter()
+                .all(|condition| condition.holds(attributes, resource_tenant_ids)),
+            Self::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.holds(attributes, resource_tenant_ids)),
+            Self::Not(condition) => !condition.holds(attributes, resource_tenant_ids),
+            Self::TimeBetween { start, end } => {
+                let time = attributes.time.time();
+                if start <= end {
+                    *start <= time && time < *end
+                } else {
+                    *start <= time || time < *end
+                }
+            }
+            Self::Weekdays(days) => days.contains(&attributes.time.weekday()),
+            Self::IpIn(ranges) => attributes
+                .client_ip
+                .is_some_and(|ip| ranges.iter().any(|range| range.contains(ip))),
+            Self::TenantIn(tenant_ids) => attributes
+                .tenant_id
+                .as_ref()
+                .is_some_and(|tenant_id| tenant_ids.contains(tenant_id)),
+            Self::SameTenant => attributes.tenant_id.as_ref().is_some_and(|tenant_id| {
+                resource_tenant_ids
+                    .iter()
+                    .any(|id| *id == tenant_id.to_string())
+            }),
+        }
+    }
+
+    fn validate(&self, depth: usize) -> eyre::Result<()> {
+        if depth > MAX_CONDITION_DEPTH {
+            return Err(eyre::eyre!(
+                "conditions cannot nest more than {} levels deep",
+                MAX_CONDITION_DEPTH
+            ));
+        }
+
+        match self {
+            Self::All(conditions) | Self::Any(conditions) => {
+                if conditions.is_empty() {
+                    return Err(eyre::eyre!("`all` and `any` need at least one condition"));
+                }
+                conditions
+                    .iter()
+                    .try_for_each(|condition| condition.validate(depth + 1))
+            }
+            Self::Not(condition) => condition.validate(depth + 1),
+            Self::Weekdays(days) if days.is_empty() => {
+                Err(eyre::eyre!("`weekdays` needs at least one day"))
+            }
+            Self::IpIn(ranges) if ranges.is_empty() => {
+                Err(eyre::eyre!("`ip_in` needs at least one range"))
+            }
+            Self::TenantIn(tenant_ids) if tenant_ids.is_empty() => {
+                Err(eyre::eyre!("`tenant_in` needs at least one tenant"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<&str> for Condition {
+    type Error = eyre::Report;
+
+    fn try_from(s: &str) -> eyre::Result<Self> {
+        let condition: Self =
+            serde_json::from_str(s).map_err(|e| eyre::eyre!("invalid condition: {}", e))?;
+        condition.validate(1)?;
+
+        Ok(condition)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{json}")
+    }
+}
+
+/// A range of IP addresses in CIDR notation, such as `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = eyre::Report;
+
+    fn try_from(s: String) -> eyre::Result<Self> {
+        let (addr, prefix) = s.split_once('/').unwrap_or((&s, ""));
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|e| eyre::eyre!("invalid address in range `{}`: {}", s, e))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = if prefix.is_empty() {
+            max_prefix
+        } else {
+            prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| eyre::eyre!("invalid prefix length in range `{}`", s))?
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        format!("{}/{}", cidr.addr, cidr.prefix)
+    }
+}
+
+/// The attributes of a request which conditions on grants are evaluated against.
+///
+/// As a request guard this never fails: the tenant is only present when the requesting user's
+/// membership of it has been verified, as for a `TenantContext`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attributes {
+    pub time: chrono::NaiveDateTime,
+    pub client_ip: Option<IpAddr>,
+    pub tenant_id: Option<TenantId>,
+}
+
+impl Attributes {
+    pub fn new(client_ip: Option<IpAddr>, tenant_id: Option<&TenantId>) -> Self {
+        Self {
+            time: chrono::Utc::now().naive_utc(),
+            client_ip,
+            tenant_id: tenant_id.copied(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Attributes {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let context = request.guard::<TenantContext>().await.succeeded();
+
+        Outcome::Success(context.map_or_else(
+            || Self::new(request.client_ip(), None),
+            |context| context.attributes,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(time: &str, client_ip: Option<&str>, tenant_id: Option<&TenantId>) -> Attributes {
+        Attributes {
+            time: format!("2022-11-07T{time}")
+                .parse()
+                .unwrap_or_else(|e| panic!("{}", e)),
+            client_ip: client_ip.map(|ip| ip.parse().unwrap_or_else(|e| panic!("{}", e))),
+            tenant_id: tenant_id.copied(),
+        }
+    }
+
+    fn nested_nots(levels: usize) -> String {
+        format!(
+            "{}\"same_tenant\"{}",
+            "{\"not\": ".repeat(levels - 1),
+            "}".repeat(levels - 1)
+        )
+    }
+
+    #[test]
+    fn rejects_conditions_nested_too_deeply_or_left_empty() {
+        assert!(Condition::try_from(nested_nots(MAX_CONDITION_DEPTH).as_str()).is_ok());
+        assert!(Condition::try_from(nested_nots(MAX_CONDITION_DEPTH + 1).as_str()).is_err());
+
+        for invalid in [
+            r#"{"all": []}"#,
+            r#"{"any": []}"#,
+            r#"{"not": {"any": []}}"#,
+            r#"{"weekdays": []}"#,
+            r#"{"ip_in": []}"#,
+            r#"{"tenant_in": []}"#,
+            r#"{"ip_in": ["10.0.0.0/33"]}"#,
+            r#"{"ip_in": ["::/129"]}"#,
+            r#"{"ip_in": ["10.0.0.0/-1"]}"#,
+        ] {
+            assert!(Condition::try_from(invalid).is_err(), "{invalid}");
+        }
+        assert!(Condition::try_from(r#"{"ip_in": ["10.0.0.0/32", "::/128", "::/0"]}"#).is_ok());
+    }
+
+    #[test]
+    fn evaluates_conditions_against_the_request() -> eyre::Result<()> {
+        let tenant_id = TenantId::new();
+        let other_tenant_id = TenantId::new();
+        let night =
+            Condition::try_from(r#"{"time_between": {"start": "22:00:00", "end": "06:00:00"}}"#)?;
+        let private = Condition::try_from(r#"{"ip_in": ["10.0.0.0/8"]}"#)?;
+        let resource_tenant_ids = [tenant_id.to_string()];
+
+        let cases = [
+            (
+                "wraps past midnight before it",
+                &night,
+                attributes("23:30:00", None, None),
+                true,
+            ),
+            (
+                "wraps past midnight after it",
+                &night,
+                attributes("05:59:59", None, None),
+                true,
+            ),
+            (
+                "ends before its end",
+                &night,
+                attributes("06:00:00", None, None),
+                false,
+            ),
+            (
+                "outside the wrapped range",
+                &night,
+                attributes("12:00:00", None, None),
+                false,
+            ),
+            (
+                "ipv4 in range",
+                &private,
+                attributes("12:00:00", Some("10.1.2.3"), None),
+                true,
+            ),
+            (
+                "ipv4-mapped ipv6 in range",
+                &private,
+                attributes("12:00:00", Some("::ffff:10.1.2.3"), None),
+                true,
+            ),
+            (
+                "ipv4-mapped ipv6 out of range",
+                &private,
+                attributes("12:00:00", Some("::ffff:192.168.0.1"), None),
+                false,
+            ),
+            (
+                "no client ip",
+                &private,
+                attributes("12:00:00", None, None),
+                false,
+            ),
+            (
+                "same tenant",
+                &Condition::SameTenant,
+                attributes("12:00:00", None, Some(&tenant_id)),
+                true,
+            ),
+            (
+                "another tenant",
+                &Condition::SameTenant,
+                attributes("12:00:00", None, Some(&other_tenant_id)),
+                false,
+            ),
+            (
+                "no tenant",
+                &Condition::SameTenant,
+                attributes("12:00:00", None, None),
+                false,
+            ),
+        ];
+        for (name, condition, attributes, holds) in cases {
+            assert_eq!(
+                condition.holds(&attributes, &resource_tenant_ids),
+                holds,
+                "{name}"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -1,12 +1,45 @@
 use crate::events;
-use crate::permissions::types::Permission;
+use crate::permissions::types::{Denial, Permission, Resource, Role, RoleAssignment};
+use crate::tenants::types::TenantId;
 
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PermissionEvent {
     Granted(Permission),
     Revoked(Permission),
+    Denied(Denial),
+    DenialRemoved(Permission),
+    RoleCreated(Role),
+    RoleUpdated(Role),
+    RoleDeleted(Role),
+    RoleAssigned(RoleAssignment),
+    RoleUnassigned(RoleAssignment),
+}
+
+impl PermissionEvent {
+    /// The tenant of the role, or of the resource when it's a tenant.
+    pub fn tenant_id(&self) -> Option<TenantId> {
+        let resource = match self {
+            Self::Granted(permission)
+            | Self::Revoked(permission)
+            | Self::DenialRemoved(permission) => &permission.resource,
+            Self::Denied(denial) => &denial.resource,
+            Self::RoleCreated(role) | Self::RoleUpdated(role) | Self::RoleDeleted(role) => {
+                return role.tenant_id;
+            }
+            Self::RoleAssigned(assignment) | Self::RoleUnassigned(assignment) => {
+                &assignment.resource
+            }
+        };
+        match resource {
+            Resource::Tenant(id) => TenantId::try_from(id.as_str()).ok(),
+            Resource::User(_) => None,
+        }
+    }
 }
 
 pub struct PermissionsEventHandler;
@@ -17,19 +50,27 @@ impl PermissionsEventHandler {
     }
 }
 
+#[rocket::async_trait]
 impl events::EventHandler for PermissionsEventHandler {
-    fn handle(
+    fn name(&self) -> &'static str {
+        "permissions"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["permission.*"]
+    }
+
+    async fn handle(
         &self,
-        rx: Arc<tokio::sync::Mutex<bus::BusReader<events::AppEvent>>>,
+        _pool: &SqlitePool,
+        envelope: &events::Envelope,
     ) -> color_eyre::eyre::Result<()> {
-        tokio::spawn(async move {
-            let mut rx = rx.lock().await;
-            loop {
-                if let Ok(event) = rx.recv() {
-                    tracing::debug!("recv: {:?}", event);
-                }
-            }
-        });
+        tracing::debug!(
+            "handling {} {}: {:?}",
+            envelope.kind,
+            envelope.id,
+            envelope.event
+        );
 
         Ok(())
     }
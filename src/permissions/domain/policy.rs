@@ -0,0 +1,533 @@
+use crate::memberships;
+use crate::permissions::{self, types};
+use crate::users::types::UserId;
+
+use color_eyre::eyre;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The outcome of evaluating a permission, along with the rule which decided it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub allowed: bool,
+    /// Missing when the permission was denied because no rule matched it.
+    pub reason: Option<Reason>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reason {
+    pub rule: types::Rule,
+    pub matched: types::Match,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = if self.allowed { "allowed" } else { "denied" };
+        match &self.reason {
+            Some(reason) => write!(f, "{outcome} by {} ({:?})", reason.rule, reason.matched),
+            None => write!(f, "{outcome} as no rule matches"),
+        }
+    }
+}
+
+/// Evaluates whether a user may take an action on a resource against every rule applying to them,
+/// where `attributes` are those of the request checking it.
+pub async fn evaluate(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    permission: &types::Permission,
+) -> eyre::Result<Decision, sqlx::Error> {
+    let tenant_ids = find_tenant_ids(pool, &permission.resource).await?;
+    let rules = permissions::sqlite::find_rules(pool, &permission.user_id).await?;
+
+    Ok(decide(
+        &rules,
+        &permission.action,
+        &permission.resource,
+        &tenant_ids,
+        attributes,
+    ))
+}
+
+/// Evaluates whether a user may delegate a permission they hold to others.
+///
+/// Delegating takes the capability to grant the action's target on the resource, such as
+/// `execute-grant-user` to pass on any action on a user, and holding the permission itself
+/// through a rule other than a non-delegable grant. The decision is the first of these to fail.
+pub async fn evaluate_delegation(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    permission: &types::Permission,
+) -> eyre::Result<Decision, sqlx::Error> {
+    let tenant_ids = find_tenant_ids(pool, &permission.resource).await?;
+    let rules = permissions::sqlite::find_rules(pool, &permission.user_id).await?;
+
+    let capability = decide(
+        &rules,
+        &permission.action.grant(),
+        &permission.resource,
+        &tenant_ids,
+        attributes,
+    );
+    if !capability.allowed {
+        return Ok(capability);
+    }
+
+    let delegable = rules
+        .into_iter()
+        .filter(|rule| rule.delegable)
+        .collect::<Vec<_>>();
+    Ok(decide(
+        &delegable,
+        &permission.action,
+        &permission.resource,
+        &tenant_ids,
+        attributes,
+    ))
+}
+
+/// The tenants a resource belongs to: the tenant itself, or the tenants a user is a member of.
+async fn find_tenant_ids(
+    pool: &SqlitePool,
+    resource: &types::Resource,
+) -> eyre::Result<Vec<String>, sqlx::Error> {
+    Ok(match resource {
+        types::Resource::Tenant(id) => vec![id.clone()],
+        // a user resource which isn't a user id, such as the wildcard, belongs to no tenant
+        types::Resource::User(id) => match UserId::try_from(id.as_str()) {
+            Ok(user_id) => memberships::sqlite::find_all_for_user(pool, &user_id)
+                .await?
+                .into_iter()
+                .map(|membership| membership.tenant_id.to_string())
+                .collect(),
+            Err(_) => vec![],
+        },
+    })
+}
+
+/// Evaluates many actions on resources for one user at once, in the order given.
+///
+/// The rules applying to the user are loaded once for every check, as are the tenants of any
+/// users among the resources, so checking a whole list costs the same few queries as checking
+/// a single permission.
+pub async fn evaluate_all(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    user_id: &UserId,
+    checks: &[(types::Actionable, types::Resource)],
+) -> eyre::Result<Vec<Decision>, sqlx::Error> {
+    let user_ids = checks
+        .iter()
+        .filter_map(|(_, resource)| match resource {
+            types::Resource::User(id) => UserId::try_from(id.as_str()).ok(),
+            types::Resource::Tenant(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut tenant_ids_by_user: HashMap<String, Vec<String>> = HashMap::new();
+    if !user_ids.is_empty() {
+        for membership in memberships::sqlite::find_all_for_users(pool, &user_ids).await? {
+            tenant_ids_by_user
+                .entry(membership.user_id.to_string())
+                .or_default()
+                .push(membership.tenant_id.to_string());
+        }
+    }
+    let rules = permissions::sqlite::find_rules(pool, user_id).await?;
+
+    Ok(checks
+        .iter()
+        .map(|(action, resource)| {
+            let tenant_ids = match resource {
+                types::Resource::Tenant(id) => vec![id.clone()],
+                types::Resource::User(id) => {
+                    tenant_ids_by_user.get(id).cloned().unwrap_or_default()
+                }
+            };
+            decide(&rules, action, resource, &tenant_ids, attributes)
+        })
+        .collect())
+}
+
+/// Decides a permission from the rules applying to a user.
+///
+/// Any matching deny rule overrides every allow rule, and without a matching allow rule the
+/// permission is denied. Rules with a condition only match while it holds for `attributes`. When
+/// several rules match, the reason given is the most specific one: an exact match before a
+/// wildcard before one inherited from a tenant, and direct rules before those from roles.
+pub fn decide(
+    rules: &[types::Rule],
+    action: &types::Actionable,
+    resource: &types::Resource,
+    tenant_ids: &[String],
+    attributes: &permissions::Attributes,
+) -> Decision {
+    let most_specific = |effect: types::Effect| {
+        rules
+            .iter()
+            .filter(|rule| rule.effect == effect)
+            .filter(|rule| {
+                rule.condition
+                    .as_ref()
+                    .is_none_or(|condition| condition.holds(attributes, tenant_ids))
+            })
+            .filter_map(|rule| {
+                rule.matches(action, resource, tenant_ids, attributes.tenant_id.as_ref())
+                    .map(|matched| (matched, rule))
+            })
+            .min_by_key(|(matched, rule)| (*matched, rule.source != types::Source::Direct))
+            .map(|(matched, rule)| Reason {
+                rule: rule.clone(),
+                matched,
+            })
+    };
+
+    if let Some(reason) = most_specific(types::Effect::Deny) {
+        return Decision {
+            allowed: false,
+            reason: Some(reason),
+        };
+    }
+
+    let reason = most_specific(types::Effect::Allow);
+    Decision {
+        allowed: reason.is_some(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::Condition;
+    use crate::tenants::types::TenantId;
+
+    fn rule(effect: types::Effect, action: &str, resource: &types::Resource) -> types::Rule {
+        types::Rule {
+            effect,
+            action: types::Actionable::try_from(action).unwrap_or_else(|e| panic!("{}", e)),
+            resource: resource.clone(),
+            source: types::Source::Direct,
+            condition: None,
+            delegable: true,
+        }
+    }
+
+    fn allow(action: &str, resource: &types::Resource) -> types::Rule {
+        rule(types::Effect::Allow, action, resource)
+    }
+
+    fn deny(action: &str, resource: &types::Resource) -> types::Rule {
+        rule(types::Effect::Deny, action, resource)
+    }
+
+    fn through_role(rule: types::Rule, tenant_id: Option<TenantId>) -> types::Rule {
+        types::Rule {
+            source: types::Source::Role {
+                id: types::RoleId::new(),
+                name: "role".to_string(),
+                tenant_id,
+            },
+            ..rule
+        }
+    }
+
+    fn with_condition(rule: types::Rule, condition: Condition) -> types::Rule {
+        types::Rule {
+            condition: Some(condition),
+            ..rule
+        }
+    }
+
+    struct Case {
+        name: &'static str,
+        rules: Vec<types::Rule>,
+        action: &'static str,
+        resource: types::Resource,
+        tenant_ids: Vec<String>,
+        allowed: bool,
+        /// The index of the rule expected to decide, and how it matched.
+        reason: Option<(usize, types::Match)>,
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn decides_by_the_most_specific_matching_rule() {
+        let tenant = TenantId::new();
+        let other_tenant = TenantId::new();
+        let user = types::Resource::User(UserId::new().to_string());
+        let any_user = types::Resource::User(types::Resource::WILDCARD.to_string());
+        let on_tenant = types::Resource::Tenant(tenant.to_string());
+        let in_tenant = vec![tenant.to_string()];
+        let in_other_tenant = vec![other_tenant.to_string()];
+
+        let cases = vec![
+            Case {
+                name: "no rules",
+                rules: vec![],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "exact grant",
+                rules: vec![allow("read-user", &user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Exact)),
+            },
+            Case {
+                name: "grant of another action",
+                rules: vec![allow("read-tenant", &user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "wildcard grant",
+                rules: vec![allow("read-user", &any_user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Wildcard)),
+            },
+            Case {
+                name: "exact grant before wildcard",
+                rules: vec![allow("read-user", &any_user), allow("read-user", &user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((1, types::Match::Exact)),
+            },
+            Case {
+                name: "write grant allows read",
+                rules: vec![allow("write-user", &user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Exact)),
+            },
+            Case {
+                name: "read grant doesn't allow write",
+                rules: vec![allow("read-user", &user)],
+                action: "write-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "read denial denies write",
+                rules: vec![allow("write-user", &user), deny("read-user", &user)],
+                action: "write-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: Some((1, types::Match::Exact)),
+            },
+            Case {
+                name: "write denial leaves read",
+                rules: vec![allow("write-user", &user), deny("write-user", &user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Exact)),
+            },
+            Case {
+                name: "wildcard denial overrides exact grant",
+                rules: vec![allow("read-user", &user), deny("read-user", &any_user)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: Some((1, types::Match::Wildcard)),
+            },
+            Case {
+                name: "grant inherited from the user's tenant",
+                rules: vec![allow("read-permissions", &on_tenant)],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: true,
+                reason: Some((0, types::Match::Inherited)),
+            },
+            Case {
+                name: "grant on a tenant the user isn't in",
+                rules: vec![allow("read-permissions", &on_tenant)],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_other_tenant.clone(),
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "grant on another tenant of the user than the request's",
+                rules: vec![allow(
+                    "read-permissions",
+                    &types::Resource::Tenant(other_tenant.to_string()),
+                )],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: vec![tenant.to_string(), other_tenant.to_string()],
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "grant on the tenant doesn't reach the user's account",
+                rules: vec![allow("read-user", &on_tenant)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "tenant role's write-user doesn't reach the user's account",
+                rules: vec![through_role(allow("write-user", &on_tenant), Some(tenant))],
+                action: "write-user",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "wildcard before inherited",
+                rules: vec![
+                    allow("read-permissions", &on_tenant),
+                    allow("read-permissions", &any_user),
+                ],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: true,
+                reason: Some((1, types::Match::Wildcard)),
+            },
+            Case {
+                name: "denial inherited from the user's tenant overrides exact grant",
+                rules: vec![
+                    allow("read-permissions", &user),
+                    deny("read-permissions", &on_tenant),
+                ],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: false,
+                reason: Some((1, types::Match::Inherited)),
+            },
+            Case {
+                name: "tenant role's write-user on the user",
+                rules: vec![through_role(allow("write-user", &user), Some(tenant))],
+                action: "write-user",
+                resource: user.clone(),
+                tenant_ids: in_tenant.clone(),
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "tenant role wildcard within its tenant",
+                rules: vec![through_role(
+                    allow("read-permissions", &any_user),
+                    Some(tenant),
+                )],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_tenant,
+                allowed: true,
+                reason: Some((0, types::Match::Wildcard)),
+            },
+            Case {
+                name: "tenant role wildcard outside its tenant",
+                rules: vec![through_role(
+                    allow("read-permissions", &any_user),
+                    Some(tenant),
+                )],
+                action: "read-permissions",
+                resource: user.clone(),
+                tenant_ids: in_other_tenant,
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "global role wildcard",
+                rules: vec![through_role(allow("read-user", &any_user), None)],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Wildcard)),
+            },
+            Case {
+                name: "direct grant before role grant",
+                rules: vec![
+                    through_role(allow("read-user", &user), None),
+                    allow("read-user", &user),
+                ],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((1, types::Match::Exact)),
+            },
+            Case {
+                name: "grant whose condition doesn't hold",
+                rules: vec![with_condition(
+                    allow("read-user", &user),
+                    Condition::TenantIn(vec![other_tenant]),
+                )],
+                action: "read-user",
+                resource: user.clone(),
+                tenant_ids: vec![],
+                allowed: false,
+                reason: None,
+            },
+            Case {
+                name: "grant whose condition holds",
+                rules: vec![with_condition(
+                    allow("read-user", &user),
+                    Condition::Not(Box::new(Condition::TenantIn(vec![other_tenant]))),
+                )],
+                action: "read-user",
+                resource: user,
+                tenant_ids: vec![],
+                allowed: true,
+                reason: Some((0, types::Match::Exact)),
+            },
+        ];
+
+        // checked within `tenant`, which tenant rules only reach its members through
+        let attributes = permissions::Attributes::new(None, Some(&tenant));
+        for case in cases {
+            let action =
+                types::Actionable::try_from(case.action).unwrap_or_else(|e| panic!("{}", e));
+            let decision = decide(
+                &case.rules,
+                &action,
+                &case.resource,
+                &case.tenant_ids,
+                &attributes,
+            );
+
+            assert_eq!(decision.allowed, case.allowed, "{}: {decision}", case.name);
+            assert_eq!(
+                decision
+                    .reason
+                    .map(|reason| (reason.rule.to_string(), reason.matched)),
+                case.reason
+                    .map(|(index, matched)| (case.rules[index].to_string(), matched)),
+                "{}",
+                case.name
+            );
+        }
+    }
+}
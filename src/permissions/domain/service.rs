@@ -1,11 +1,19 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
 
+use crate::events::{outbox, AppEvent, Envelope, Publisher};
+use crate::memberships;
 use crate::permissions;
-use crate::permissions::types;
+use crate::permissions::domain::{events, policy};
+use crate::permissions::types::{self, RoleId};
+use crate::tenants::{self, TenantContext};
+use crate::types::pagination::{Paginated, Pagination};
 use crate::types::sqlite;
 use crate::types::validation::FieldValidationError;
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
+use serde::Deserialize;
 use sqlx::SqlitePool;
 use thiserror::Error;
 
@@ -18,9 +26,12 @@ pub enum HasPermissionError {
     Sqlx(#[from] sqlx::Error),
 }
 
+/// Checks whether a user may take an action on a resource within a request with the given
+/// attributes; see `policy::decide` for how the rules applying to the user are evaluated.
 pub async fn has_permission_to(
     pool: &SqlitePool,
-    user_id: &str,
+    attributes: &permissions::Attributes,
+    user_id: &UserId,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
@@ -35,14 +46,375 @@ pub async fn has_permission_to(
     let permission = types::Permission::new(user_id, action, &resource)
         .map_err(HasPermissionError::InvalidInput)?;
 
-    match permissions::sqlite::has_permission_to(pool, &permission).await {
-        Ok(has_permission) => Ok(has_permission),
-        Err(err) => Err(HasPermissionError::Sqlx(err)),
+    let decision = policy::evaluate(pool, attributes, &permission).await?;
+    tracing::debug!("{}: {}", permission, decision);
+
+    Ok(decision.allowed)
+}
+
+/// Checks many actions on resources for one user at once, such as to filter a list down to what
+/// they may see, returning whether each is allowed in the order given.
+pub async fn has_permissions_to(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    user_id: &UserId,
+    checks: &[(types::Actionable, types::Resource)],
+) -> eyre::Result<Vec<bool>, HasPermissionError> {
+    let decisions = policy::evaluate_all(pool, attributes, user_id, checks).await?;
+
+    Ok(decisions
+        .into_iter()
+        .map(|decision| decision.allowed)
+        .collect())
+}
+
+#[derive(Error, Debug)]
+pub enum ExplainPermissionError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("unauthorized to explain permission")]
+    Unauthorized,
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Evaluates whether a user may take an action on a resource, returning the rule which decided
+/// it. Conditional grants are evaluated against the attributes of the explaining request.
+///
+/// Users can explain their own permissions; explaining someone else's requires being allowed to
+/// execute the kind of the resource on it.
+pub async fn explain_permission(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+) -> eyre::Result<policy::Decision, ExplainPermissionError> {
+    let permission = parse_permission(user_id, action, resource_id, resource_kind)
+        .map_err(ExplainPermissionError::InvalidInput)?;
+    if requesting_user_id != user_id
+        && !can_administer(pool, attributes, requesting_user_id, &permission.resource)
+            .await
+            .map_err(ExplainPermissionError::AccessCheckFailed)?
+    {
+        return Err(ExplainPermissionError::Unauthorized);
+    }
+
+    Ok(policy::evaluate(pool, attributes, &permission).await?)
+}
+
+#[derive(Error, Debug)]
+pub enum CheckPermissionError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("unauthorized to check permission")]
+    Unauthorized,
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Checks whether a user may take an action on a resource on behalf of a requesting user.
+///
+/// Users can check their own permissions; checking someone else's requires `read-permissions` on
+/// the resource, as listing who has access to it does.
+pub async fn check_permission(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+) -> eyre::Result<bool, CheckPermissionError> {
+    let permission = parse_permission(user_id, action, resource_id, resource_kind)
+        .map_err(CheckPermissionError::InvalidInput)?;
+    if requesting_user_id != user_id
+        && !has_permission_to(
+            pool,
+            attributes,
+            requesting_user_id,
+            &types::Actionable::Read(types::Permission::kind()).to_string(),
+            permission.resource.id(),
+            &permission.resource.kind().to_string(),
+        )
+        .await
+        .map_err(CheckPermissionError::AccessCheckFailed)?
+    {
+        return Err(CheckPermissionError::Unauthorized);
     }
+
+    let decision = policy::evaluate(pool, attributes, &permission).await?;
+    tracing::debug!("{}: {}", permission, decision);
+
+    Ok(decision.allowed)
+}
+
+#[derive(Error, Debug)]
+pub enum ListPermissionsError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("unauthorized to read permissions")]
+    Unauthorized,
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists a page of the grants a user holds directly, with their conditions and expiry.
+///
+/// Listing a user's permissions requires `read-permissions` on them, which every user holds on
+/// themselves and tenant owners and admins hold on their members.
+pub async fn list_user_permissions(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+    page: Option<u32>,
+    per_page: Option<u32>,
+) -> eyre::Result<Paginated<types::Permission>, ListPermissionsError> {
+    let pagination = Pagination::new(page, per_page).map_err(ListPermissionsError::InvalidInput)?;
+    ensure_can_read_permissions(
+        pool,
+        attributes,
+        requesting_user_id,
+        &types::Resource::User(user_id.to_string()),
+    )
+    .await?;
+
+    let permissions = permissions::sqlite::find_page_by_user(pool, user_id, &pagination).await?;
+    let total = permissions::sqlite::count_by_user(pool, user_id).await?;
+
+    Ok(Paginated::new(permissions, pagination, total))
+}
+
+/// Lists every rule applying to a user, including those from their roles and the denials
+/// overriding them, which together decide what they can do.
+pub async fn list_effective_permissions(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+) -> eyre::Result<Vec<types::Rule>, ListPermissionsError> {
+    ensure_can_read_permissions(
+        pool,
+        attributes,
+        requesting_user_id,
+        &types::Resource::User(user_id.to_string()),
+    )
+    .await?;
+
+    Ok(permissions::sqlite::find_rules(pool, user_id).await?)
+}
+
+/// Lists who holds a rule on a resource, which requires `read-permissions` on it.
+pub async fn list_resource_access(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    resource_kind: &str,
+    resource_id: &str,
+) -> eyre::Result<Vec<types::Access>, ListPermissionsError> {
+    let resource = types::Resource::try_from((resource_id, resource_kind)).map_err(|e| {
+        ListPermissionsError::InvalidInput(FieldValidationError {
+            field: "resource".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    ensure_can_read_permissions(pool, attributes, requesting_user_id, &resource).await?;
+
+    Ok(permissions::sqlite::find_access_to_resource(pool, &resource).await?)
+}
+
+async fn ensure_can_read_permissions(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    resource: &types::Resource,
+) -> eyre::Result<(), ListPermissionsError> {
+    let can = has_permission_to(
+        pool,
+        attributes,
+        requesting_user_id,
+        &types::Actionable::Read(types::Permission::kind()).to_string(),
+        resource.id(),
+        &resource.kind().to_string(),
+    )
+    .await
+    .map_err(ListPermissionsError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(ListPermissionsError::Unauthorized)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DenyPermissionError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("unauthorized to deny permission")]
+    Unauthorized,
+
+    #[error("denial does not exist")]
+    NotFound,
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Blocks a user from an action on a resource, overriding any grant they hold without removing
+/// it.
+///
+/// Denying requires being allowed to execute the kind of the resource on it, such as
+/// `execute-tenant` for a tenant.
+#[allow(clippy::too_many_arguments)]
+pub async fn deny_permission(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+    reason: Option<String>,
+) -> eyre::Result<types::Denial, DenyPermissionError> {
+    let permission = parse_permission(user_id, action, resource_id, resource_kind)
+        .map_err(DenyPermissionError::InvalidInput)?;
+    ensure_can_deny(pool, attributes, requesting_user_id, &permission).await?;
+
+    let denial = types::Denial::new(&permission, reason, requesting_user_id);
+    let mut tx = pool.begin().await?;
+    permissions::sqlite::insert_denial(&mut tx, &denial).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(events::PermissionEvent::Denied(
+                denial.clone(),
+            )))
+            .with_actor(requesting_user_id),
+    )
+    .await?;
+    tx.commit().await?;
+
+    publisher.notify();
+
+    Ok(denial)
+}
+
+/// Lifts a denial, letting the user's grants take effect again.
+#[allow(clippy::too_many_arguments)]
+pub async fn remove_denial(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+) -> eyre::Result<(), DenyPermissionError> {
+    let permission = parse_permission(user_id, action, resource_id, resource_kind)
+        .map_err(DenyPermissionError::InvalidInput)?;
+    ensure_can_deny(pool, attributes, requesting_user_id, &permission).await?;
+
+    let mut tx = pool.begin().await?;
+    if !permissions::sqlite::delete_denial(&mut tx, &permission).await? {
+        return Err(DenyPermissionError::NotFound);
+    }
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(
+                events::PermissionEvent::DenialRemoved(permission),
+            ))
+            .with_actor(requesting_user_id),
+    )
+    .await?;
+    tx.commit().await?;
+
+    publisher.notify();
+
+    Ok(())
+}
+
+async fn ensure_can_deny(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    permission: &types::Permission,
+) -> eyre::Result<(), DenyPermissionError> {
+    let can = can_administer(pool, attributes, requesting_user_id, &permission.resource)
+        .await
+        .map_err(DenyPermissionError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(DenyPermissionError::Unauthorized)
+    }
+}
+
+/// Whether a user may manage the rules on a resource, which requires executing its kind on it.
+async fn can_administer(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    user_id: &UserId,
+    resource: &types::Resource,
+) -> eyre::Result<bool, HasPermissionError> {
+    has_permission_to(
+        pool,
+        attributes,
+        user_id,
+        &types::Actionable::Execute(resource.kind()).to_string(),
+        resource.id(),
+        &resource.kind().to_string(),
+    )
+    .await
+}
+
+fn parse_permission(
+    user_id: &UserId,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+) -> eyre::Result<types::Permission, FieldValidationError> {
+    let resource = types::Resource::try_from((resource_id, resource_kind)).map_err(|e| {
+        FieldValidationError {
+            field: "resource".to_string(),
+            message: e.to_string(),
+        }
+    })?;
+
+    types::Permission::new(user_id, action, &resource)
 }
 
 #[derive(Error, Debug)]
 pub enum GrantPermissionError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
     #[error("unauthorized to grant permission")]
     Unauthorized,
 
@@ -53,24 +425,57 @@ pub enum GrantPermissionError {
     CreateFailed(CreatePermissionError),
 }
 
+/// Optional restrictions on a grant.
+#[derive(Debug, Default)]
+pub struct GrantOptions {
+    /// A condition as JSON, which the attributes of a request must satisfy for the grant to apply.
+    pub condition: Option<String>,
+    /// When the grant expires, as an RFC 3339 timestamp or a date and time in UTC.
+    pub expires_at: Option<String>,
+    /// Stops the receiving user from passing the grant on to others.
+    pub non_delegable: bool,
+}
+
+/// Delegates an action the requesting user holds themselves to another user.
+///
+/// The requesting user needs the capability to grant the action (see
+/// `policy::evaluate_delegation`), and the grant records them as its grantor so that revoking
+/// their own grant also revokes it. Granting a permission the user already holds replaces its
+/// restrictions and grantor.
+#[allow(clippy::too_many_arguments)]
 pub async fn grant_permission(
     pool: &SqlitePool,
-    requesting_user_id: &str,
-    receiving_user_id: &str,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    receiving_user_id: &UserId,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
+    options: GrantOptions,
 ) -> eyre::Result<types::Permission, GrantPermissionError> {
-    match has_permission_to(pool, requesting_user_id, action, resource_id, resource_kind).await {
-        Ok(has_permission) => {
-            if !has_permission {
-                return Err(GrantPermissionError::Unauthorized);
-            }
-        }
-        Err(err) => return Err(GrantPermissionError::AccessCheckFailed(err)),
+    let delegated = parse_permission(requesting_user_id, action, resource_id, resource_kind)
+        .map_err(GrantPermissionError::InvalidInput)?;
+    let decision = policy::evaluate_delegation(pool, attributes, &delegated)
+        .await
+        .map_err(|e| GrantPermissionError::AccessCheckFailed(HasPermissionError::Sqlx(e)))?;
+    if !decision.allowed {
+        return Err(GrantPermissionError::Unauthorized);
     }
 
-    match create_permission(pool, receiving_user_id, action, resource_id, resource_kind).await {
+    match create_permission(
+        pool,
+        publisher,
+        attributes,
+        receiving_user_id,
+        action,
+        resource_id,
+        resource_kind,
+        &options,
+        &delegated.user_id,
+    )
+    .await
+    {
         Ok(p) => Ok(p),
         Err(e) => Err(GrantPermissionError::CreateFailed(e)),
     }
@@ -84,34 +489,66 @@ pub enum RevokePermissionError {
     #[error("failed to check permission of requesting user")]
     AccessCheckFailed(HasPermissionError),
 
-    #[error("failed to create permission for recipient user")]
+    #[error("failed to delete permission of recipient user")]
     DeleteFailed(HasPermissionError),
 }
 
+/// Revokes a user's grant along with every grant delegated onward from it, returning them all.
+///
+/// The requesting user needs both the action and the capability to grant it, as when granting.
+#[allow(clippy::too_many_arguments)]
 pub async fn revoke_permission(
     pool: &SqlitePool,
-    requesting_user_id: &str,
-    receiving_user_id: &str,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    receiving_user_id: &UserId,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
-) -> eyre::Result<(), RevokePermissionError> {
-    match has_permission_to(pool, requesting_user_id, action, resource_id, resource_kind).await {
-        Ok(has_permission) => {
-            if !has_permission {
-                return Err(RevokePermissionError::Unauthorized);
+) -> eyre::Result<Vec<types::Permission>, RevokePermissionError> {
+    let grant = types::Actionable::try_from(action)
+        .map(|action| action.grant().to_string())
+        .map_err(|e| {
+            RevokePermissionError::AccessCheckFailed(HasPermissionError::InvalidInput(
+                FieldValidationError {
+                    field: "action".to_string(),
+                    message: e.to_string(),
+                },
+            ))
+        })?;
+    for action in [action, grant.as_str()] {
+        match has_permission_to(
+            pool,
+            attributes,
+            requesting_user_id,
+            action,
+            resource_id,
+            resource_kind,
+        )
+        .await
+        {
+            Ok(has_permission) => {
+                if !has_permission {
+                    return Err(RevokePermissionError::Unauthorized);
+                }
             }
+            Err(err) => return Err(RevokePermissionError::AccessCheckFailed(err)),
         }
-        Err(err) => return Err(RevokePermissionError::AccessCheckFailed(err)),
     }
 
-    if let Err(err) =
-        delete_permission(pool, receiving_user_id, action, resource_id, resource_kind).await
-    {
-        return Err(RevokePermissionError::DeleteFailed(err));
-    }
-
-    Ok(())
+    delete_permission(
+        pool,
+        publisher,
+        attributes,
+        requesting_user_id,
+        receiving_user_id,
+        action,
+        resource_id,
+        resource_kind,
+    )
+    .await
+    .map_err(RevokePermissionError::DeleteFailed)
 }
 
 #[derive(Error, Debug)]
@@ -123,12 +560,19 @@ pub enum CreatePermissionError {
     Sqlx(#[from] sqlx::Error),
 }
 
+/// Grants a permission on behalf of the user delegating it, publishing
+/// `PermissionEvent::Granted`.
+#[allow(clippy::too_many_arguments)]
 async fn create_permission(
     pool: &SqlitePool,
-    user_id: &str,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    user_id: &UserId,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
+    options: &GrantOptions,
+    granted_by: &UserId,
 ) -> eyre::Result<types::Permission, CreatePermissionError> {
     let resource = types::Resource::try_from((resource_id, resource_kind)).map_err(|e| {
         CreatePermissionError::InvalidInput(FieldValidationError {
@@ -136,43 +580,114 @@ async fn create_permission(
             message: e.to_string(),
         })
     })?;
-    let permission = types::Permission::new(user_id, action, &resource)
+    let mut permission = types::Permission::new(user_id, action, &resource)
         .map_err(CreatePermissionError::InvalidInput)?;
+    permission.condition = options
+        .condition
+        .as_deref()
+        .map(permissions::Condition::try_from)
+        .transpose()
+        .map_err(|e| {
+            CreatePermissionError::InvalidInput(FieldValidationError {
+                field: "condition".to_string(),
+                message: e.to_string(),
+            })
+        })?;
+    permission.expires_at = options
+        .expires_at
+        .as_deref()
+        .map(parse_expiry)
+        .transpose()
+        .map_err(CreatePermissionError::InvalidInput)?;
+    permission.delegable = !options.non_delegable;
+    permission.granted_by = Some(*granted_by);
 
     let mut tx = pool
         .begin()
         .await
         .map_err(CreatePermissionError::Sqlx)?;
-    if let Err(err) = permissions::sqlite::insert(&mut tx, &permission).await {
-        match err {
-            sqlx::Error::Database(e) => {
-                if let Some(code) = e.code() {
-                    // we can ignore a unique constraint violation since it's essentially
-                    // equivalent to a noop
-                    if let sqlite::ErrorCode::UniqueConstraintViolation =
-                        sqlite::ErrorCode::from(code)
-                    {
-                        return Ok(permission);
-                    }
-                }
-            }
-            err => return Err(CreatePermissionError::Sqlx(err)),
-        }
-    }
+    permissions::sqlite::insert(&mut tx, &permission)
+        .await
+        .map_err(CreatePermissionError::Sqlx)?;
+    outbox::insert(
+        &mut tx,
+        &grant_envelope(
+            publisher,
+            attributes,
+            granted_by,
+            events::PermissionEvent::Granted(permission.clone()),
+        ),
+    )
+    .await
+    .map_err(CreatePermissionError::Sqlx)?;
 
     tx.commit()
         .await
         .map_err(CreatePermissionError::Sqlx)?;
+
+    publisher.notify();
+
     Ok(permission)
 }
 
+fn parse_expiry(expires_at: &str) -> eyre::Result<chrono::NaiveDateTime, FieldValidationError> {
+    let expires_at = chrono::DateTime::parse_from_rfc3339(expires_at)
+        .map(|expires_at| expires_at.naive_utc())
+        .or_else(|_| expires_at.parse::<chrono::NaiveDateTime>())
+        .map_err(|e| FieldValidationError {
+            field: "expires_at".to_string(),
+            message: e.to_string(),
+        })?;
+    if expires_at <= chrono::Utc::now().naive_utc() {
+        return Err(FieldValidationError {
+            field: "expires_at".to_string(),
+            message: "expiry must be in the future".to_string(),
+        });
+    }
+
+    Ok(expires_at)
+}
+
+/// Deletes every expired grant, publishing `PermissionEvent::Revoked` for each, and returns how
+/// many were deleted.
+pub async fn sweep_expired_permissions(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+) -> eyre::Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let expired = permissions::sqlite::delete_expired(&mut tx).await?;
+    let count = expired.len();
+    for permission in expired {
+        outbox::insert(
+            &mut tx,
+            &publisher.envelope(AppEvent::Permission(events::PermissionEvent::Revoked(
+                permission,
+            ))),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    if count > 0 {
+        publisher.notify();
+    }
+
+    Ok(count)
+}
+
+/// Revokes a grant along with every grant delegated onward from it, publishing
+/// `PermissionEvent::Revoked` for each.
+#[allow(clippy::too_many_arguments)]
 async fn delete_permission(
     pool: &SqlitePool,
-    user_id: &str,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    user_id: &UserId,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
-) -> eyre::Result<(), HasPermissionError> {
+) -> eyre::Result<Vec<types::Permission>, HasPermissionError> {
     let resource = types::Resource::try_from((resource_id, resource_kind)).map_err(|e| {
         HasPermissionError::InvalidInput(FieldValidationError {
             field: "resource".to_string(),
@@ -186,10 +701,721 @@ async fn delete_permission(
         .begin()
         .await
         .map_err(HasPermissionError::Sqlx)?;
-    permissions::sqlite::delete(&mut tx, &permission)
+    let revoked = permissions::sqlite::delete_with_delegated(&mut tx, &permission)
+        .await
+        .map_err(HasPermissionError::Sqlx)?;
+    for permission in &revoked {
+        outbox::insert(
+            &mut tx,
+            &grant_envelope(
+                publisher,
+                attributes,
+                requesting_user_id,
+                events::PermissionEvent::Revoked(permission.clone()),
+            ),
+        )
         .await
         .map_err(HasPermissionError::Sqlx)?;
+    }
 
     tx.commit().await.map_err(HasPermissionError::Sqlx)?;
+
+    if !revoked.is_empty() {
+        publisher.notify();
+    }
+
+    Ok(revoked)
+}
+
+/// Wraps an event about a grant made or revoked by `actor_id` for the outbox.
+///
+/// Grants on users belong to no tenant themselves, so their events are recorded within the tenant
+/// of the request, when it has one, for that tenant's webhooks to receive them.
+fn grant_envelope(
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    actor_id: &UserId,
+    event: events::PermissionEvent,
+) -> Envelope {
+    let envelope = publisher
+        .envelope(AppEvent::Permission(event))
+        .with_actor(actor_id);
+    match (envelope.tenant_id, &attributes.tenant_id) {
+        (None, Some(tenant_id)) => envelope.with_tenant(tenant_id),
+        _ => envelope,
+    }
+}
+
+const MAX_ROLE_NAME_LENGTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum FindRoleError {
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("id `{0}` does not exist")]
+    NotFound(String),
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists the roles of the current tenant along with the global roles.
+pub async fn list_roles(
+    pool: &SqlitePool,
+    context: &TenantContext,
+) -> eyre::Result<Vec<types::Role>, FindRoleError> {
+    ensure_tenant_permission(pool, context, "read-tenant").await?;
+
+    permissions::sqlite::find_roles(pool, &context.scope)
+        .await
+        .map_err(FindRoleError::Sqlx)
+}
+
+pub async fn find_role(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    id: &RoleId,
+) -> eyre::Result<types::Role, FindRoleError> {
+    ensure_tenant_permission(pool, context, "read-tenant").await?;
+
+    permissions::sqlite::find_role(pool, &context.scope, id)
+        .await?
+        .ok_or_else(|| FindRoleError::NotFound(id.to_string()))
+}
+
+#[derive(Error, Debug)]
+pub enum ChangeRoleError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("a role with the same name already exists")]
+    AlreadyExists,
+
+    #[error("global roles cannot be changed")]
+    GlobalRole,
+
+    #[error("failed to find role")]
+    Find(#[from] FindRoleError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleRequest {
+    pub name: String,
+    pub actions: Vec<String>,
+}
+
+/// Creates a role of the current tenant, which requires `execute-tenant`.
+///
+/// Roles can only bundle actions on what the tenant owns, never on the global accounts of its
+/// members or operator actions.
+pub async fn create_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    payload: RoleRequest,
+) -> eyre::Result<types::Role, ChangeRoleError> {
+    let (name, actions) = validate_role(&payload)?;
+    ensure_tenant_permission(pool, context, "execute-tenant").await?;
+
+    let role = types::Role::new(Some(context.scope.tenant_id()), &name, actions);
+    let mut tx = pool.begin().await.map_err(ChangeRoleError::Sqlx)?;
+    permissions::sqlite::insert_role(&mut tx, &context.scope, &role)
+        .await
+        .map_err(role_conflict)?;
+    for action in &role.actions {
+        permissions::sqlite::insert_role_action(&mut tx, &context.scope, &role.id, action).await?;
+    }
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(events::PermissionEvent::RoleCreated(
+                role.clone(),
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(ChangeRoleError::Sqlx)?;
+    tx.commit().await.map_err(ChangeRoleError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(role)
+}
+
+/// Renames a role of the current tenant and replaces its actions, which immediately changes what
+/// every user assigned the role can do.
+pub async fn update_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    id: &RoleId,
+    payload: RoleRequest,
+) -> eyre::Result<types::Role, ChangeRoleError> {
+    let (name, actions) = validate_role(&payload)?;
+    ensure_tenant_permission(pool, context, "execute-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(ChangeRoleError::Sqlx)?;
+    let mut role = find_tenant_role(&mut tx, context, id).await?;
+    permissions::sqlite::update_role_name(&mut tx, &context.scope, id, &name)
+        .await
+        .map_err(role_conflict)?;
+    permissions::sqlite::delete_role_actions(&mut tx, &context.scope, id).await?;
+    for action in &actions {
+        permissions::sqlite::insert_role_action(&mut tx, &context.scope, id, action).await?;
+    }
+    role.name = name;
+    role.actions = actions;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(events::PermissionEvent::RoleUpdated(
+                role.clone(),
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(ChangeRoleError::Sqlx)?;
+    tx.commit().await.map_err(ChangeRoleError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(role)
+}
+
+/// Deletes a role of the current tenant, unassigning it from every user.
+pub async fn delete_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    id: &RoleId,
+) -> eyre::Result<(), ChangeRoleError> {
+    ensure_tenant_permission(pool, context, "execute-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(ChangeRoleError::Sqlx)?;
+    let role = find_tenant_role(&mut tx, context, id).await?;
+    permissions::sqlite::delete_role(&mut tx, &context.scope, id).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(events::PermissionEvent::RoleDeleted(
+                role,
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(ChangeRoleError::Sqlx)?;
+    tx.commit().await.map_err(ChangeRoleError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(())
+}
+
+/// Lists the users a role is assigned to and the resources it is assigned on.
+pub async fn list_role_assignments(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    role_id: &RoleId,
+) -> eyre::Result<Vec<types::RoleAssignment>, FindRoleError> {
+    let role = find_role(pool, context, role_id).await?;
+
+    let mut assignments = permissions::sqlite::find_role_assignments(pool, role_id).await?;
+    if role.is_global() {
+        // global roles are assigned across tenants; only reveal those within this one
+        let tenant_id = context.scope.tenant_id().to_string();
+        assignments
+            .retain(|a| matches!(&a.resource, types::Resource::Tenant(id) if *id == tenant_id));
+    }
+
+    Ok(assignments)
+}
+
+#[derive(Error, Debug)]
+pub enum AssignRoleError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("role is already assigned")]
+    AlreadyAssigned,
+
+    #[error("global roles are assigned through tenant memberships")]
+    GlobalRole,
+
+    #[error("failed to find role")]
+    Find(#[from] FindRoleError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignRoleRequest {
+    pub user_id: String,
+    pub resource_id: String,
+    pub resource_kind: String,
+}
+
+/// Assigns a role of the current tenant to one of its members.
+///
+/// Roles can only be assigned on resources within the tenant: the tenant itself, one of its
+/// members, or every member through the `*` wildcard.
+pub async fn assign_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    role_id: &RoleId,
+    payload: AssignRoleRequest,
+) -> eyre::Result<types::RoleAssignment, AssignRoleError> {
+    ensure_tenant_permission(pool, context, "execute-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(AssignRoleError::Sqlx)?;
+    let assignment = resolve_assignment(&mut tx, context, role_id, payload).await?;
+    if let Err(err) = permissions::sqlite::insert_role_assignment(&mut tx, &assignment).await {
+        return Err(if is_unique_violation(&err) {
+            AssignRoleError::AlreadyAssigned
+        } else {
+            AssignRoleError::Sqlx(err)
+        });
+    }
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(events::PermissionEvent::RoleAssigned(
+                assignment.clone(),
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(AssignRoleError::Sqlx)?;
+    tx.commit().await.map_err(AssignRoleError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(assignment)
+}
+
+pub async fn unassign_role(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    role_id: &RoleId,
+    payload: AssignRoleRequest,
+) -> eyre::Result<(), AssignRoleError> {
+    ensure_tenant_permission(pool, context, "execute-tenant").await?;
+
+    let mut tx = pool.begin().await.map_err(AssignRoleError::Sqlx)?;
+    let assignment = resolve_assignment(&mut tx, context, role_id, payload).await?;
+    if !permissions::sqlite::delete_role_assignment(&mut tx, &assignment).await? {
+        return Err(AssignRoleError::Find(FindRoleError::NotFound(format!(
+            "{}:{}",
+            assignment.user_id, assignment.resource
+        ))));
+    }
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Permission(
+                events::PermissionEvent::RoleUnassigned(assignment),
+            ))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(AssignRoleError::Sqlx)?;
+    tx.commit().await.map_err(AssignRoleError::Sqlx)?;
+
+    publisher.notify();
+
     Ok(())
 }
+
+/// Validates an assignment of a tenant role to a member on a resource within the tenant.
+async fn resolve_assignment(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    context: &TenantContext,
+    role_id: &RoleId,
+    payload: AssignRoleRequest,
+) -> eyre::Result<types::RoleAssignment, AssignRoleError> {
+    let role = permissions::sqlite::find_role(&mut *tx, &context.scope, role_id)
+        .await?
+        .ok_or_else(|| FindRoleError::NotFound(role_id.to_string()))?;
+    if role.is_global() {
+        return Err(AssignRoleError::GlobalRole);
+    }
+
+    let resource =
+        types::Resource::try_from((payload.resource_id.as_str(), payload.resource_kind.as_str()))
+            .map_err(|e| {
+            AssignRoleError::InvalidInput(FieldValidationError {
+                field: "resource_kind".to_string(),
+                message: e.to_string(),
+            })
+        })?;
+    let within_tenant = match &resource {
+        types::Resource::Tenant(id) => *id == context.scope.tenant_id().to_string(),
+        types::Resource::User(id) if id == types::Resource::WILDCARD => true,
+        types::Resource::User(id) => match UserId::try_from(id.as_str()) {
+            Ok(user_id) => memberships::sqlite::find_one(&mut *tx, &context.scope, &user_id)
+                .await?
+                .is_some(),
+            Err(_) => false,
+        },
+    };
+    if !within_tenant {
+        return Err(AssignRoleError::InvalidInput(FieldValidationError {
+            field: "resource_id".to_string(),
+            message: format!("`{resource}` is not within the current tenant"),
+        }));
+    }
+
+    let user_id = UserId::try_from(payload.user_id.as_str()).map_err(|e| {
+        AssignRoleError::InvalidInput(FieldValidationError {
+            field: "user_id".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    let Some(member) = memberships::sqlite::find_one(&mut *tx, &context.scope, &user_id).await?
+    else {
+        return Err(AssignRoleError::InvalidInput(FieldValidationError {
+            field: "user_id".to_string(),
+            message: format!("user `{}` is not a member of the tenant", payload.user_id),
+        }));
+    };
+
+    Ok(types::RoleAssignment {
+        role_id: role.id,
+        user_id: member.user_id,
+        resource,
+    })
+}
+
+async fn find_tenant_role(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    context: &TenantContext,
+    id: &RoleId,
+) -> eyre::Result<types::Role, ChangeRoleError> {
+    let role = permissions::sqlite::find_role(&mut *tx, &context.scope, id)
+        .await?
+        .ok_or_else(|| FindRoleError::NotFound(id.to_string()))?;
+    if role.is_global() {
+        return Err(ChangeRoleError::GlobalRole);
+    }
+
+    Ok(role)
+}
+
+fn validate_role(
+    payload: &RoleRequest,
+) -> eyre::Result<(String, Vec<types::Actionable>), ChangeRoleError> {
+    let name = payload.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_ROLE_NAME_LENGTH {
+        return Err(ChangeRoleError::InvalidInput(FieldValidationError {
+            field: "name".to_string(),
+            message: format!("name must be between 1 and {MAX_ROLE_NAME_LENGTH} characters"),
+        }));
+    }
+    if payload.actions.is_empty() {
+        return Err(ChangeRoleError::InvalidInput(FieldValidationError {
+            field: "actions".to_string(),
+            message: "a role must grant at least one action".to_string(),
+        }));
+    }
+
+    let mut seen = HashSet::new();
+    let actions = payload
+        .actions
+        .iter()
+        .filter(|action| seen.insert(action.as_str()))
+        .map(|action| {
+            types::Actionable::try_from(action.as_str()).map_err(|e| {
+                ChangeRoleError::InvalidInput(FieldValidationError {
+                    field: "actions".to_string(),
+                    message: e.to_string(),
+                })
+            })
+        })
+        .collect::<eyre::Result<Vec<_>, _>>()?;
+    if let Some(action) = actions.iter().find(|action| !action.is_tenant_scoped()) {
+        return Err(ChangeRoleError::InvalidInput(FieldValidationError {
+            field: "actions".to_string(),
+            message: format!(
+                "`{action}` is not an action on the tenant, its webhooks or the permissions \
+                 within it"
+            ),
+        }));
+    }
+
+    Ok((name, actions))
+}
+
+fn role_conflict(err: sqlx::Error) -> ChangeRoleError {
+    if is_unique_violation(&err) {
+        ChangeRoleError::AlreadyExists
+    } else {
+        ChangeRoleError::Sqlx(err)
+    }
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            matches!(
+                sqlite::ErrorCode::from(code),
+                sqlite::ErrorCode::UniqueConstraintViolation
+            )
+        }),
+        _ => false,
+    }
+}
+
+async fn ensure_tenant_permission(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    action: &str,
+) -> eyre::Result<(), FindRoleError> {
+    let can = has_permission_to(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        action,
+        &context.scope.tenant_id().to_string(),
+        &tenants::types::Tenant::kind().to_string(),
+    )
+    .await
+    .map_err(FindRoleError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(FindRoleError::PermissionDenied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventLog, EventReader};
+    use crate::tenants::types::TenantId;
+    use crate::types::sqlite::test_pool;
+    use crate::users;
+
+    use std::time::Duration;
+
+    /// A publisher whose events are relayed to the log once it's notified, and a reader of them.
+    async fn publisher(pool: &SqlitePool) -> eyre::Result<(Publisher, EventReader)> {
+        let log = EventLog::start(pool.clone(), Duration::from_hours(1));
+        let reader = log.reader("test").await?;
+
+        Ok((Publisher::new(log), reader))
+    }
+
+    /// Reads events until `count` grants have been revoked, returning them; other events are
+    /// skipped.
+    async fn revoked(reader: &mut EventReader, count: usize) -> eyre::Result<Vec<String>> {
+        let mut revoked = Vec::new();
+        while revoked.len() < count {
+            let event = tokio::time::timeout(Duration::from_secs(5), reader.recv())
+                .await?
+                .ok_or_else(|| eyre::eyre!("event log closed"))?;
+            if let AppEvent::Permission(events::PermissionEvent::Revoked(permission)) =
+                event.envelope.event
+            {
+                revoked.push(permission.to_string());
+            }
+        }
+        revoked.sort();
+
+        Ok(revoked)
+    }
+
+    fn grant(
+        user_id: &UserId,
+        action: &str,
+        tenant_id: &TenantId,
+    ) -> eyre::Result<types::Permission> {
+        Ok(types::Permission {
+            user_id: *user_id,
+            action: types::Actionable::try_from(action)?,
+            resource: types::Resource::Tenant(tenant_id.to_string()),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
+        })
+    }
+
+    #[rocket::async_test]
+    async fn expired_grants_are_ignored_and_swept() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let (publisher, mut reader) = publisher(&pool).await?;
+        let attributes = permissions::Attributes::new(None, None);
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|user")).await?;
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let tenant_id = tenant.id.to_string();
+        let expired = types::Permission {
+            expires_at: Some(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1)),
+            ..grant(&user.id, "write-tenant", &tenant.id)?
+        };
+        permissions::sqlite::insert(&pool, &expired).await?;
+        permissions::sqlite::insert(&pool, &grant(&user.id, "read-tenant", &tenant.id)?).await?;
+
+        assert!(
+            !has_permission_to(
+                &pool,
+                &attributes,
+                &user.id,
+                "write-tenant",
+                &tenant_id,
+                "tenant"
+            )
+            .await?
+        );
+        assert!(
+            has_permission_to(
+                &pool,
+                &attributes,
+                &user.id,
+                "read-tenant",
+                &tenant_id,
+                "tenant"
+            )
+            .await?
+        );
+
+        assert_eq!(sweep_expired_permissions(&pool, &publisher).await?, 1);
+        assert_eq!(revoked(&mut reader, 1).await?, vec![expired.to_string()]);
+        let remaining =
+            permissions::sqlite::find_page_by_user(&pool, &user.id, &Pagination::new(None, None)?)
+                .await?
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+        assert_eq!(
+            remaining,
+            vec![grant(&user.id, "read-tenant", &tenant.id)?.to_string()]
+        );
+        // nothing is left to sweep, nor published
+        assert_eq!(sweep_expired_permissions(&pool, &publisher).await?, 0);
+
+        Ok(())
+    }
+
+    /// Has `from` grant `write-tenant` on a tenant to `to`.
+    async fn grant_write_tenant(
+        pool: &SqlitePool,
+        publisher: &Publisher,
+        attributes: &permissions::Attributes,
+        from: UserId,
+        to: UserId,
+        tenant_id: &str,
+        non_delegable: bool,
+    ) -> eyre::Result<types::Permission, GrantPermissionError> {
+        grant_permission(
+            pool,
+            publisher,
+            attributes,
+            &from,
+            &to,
+            "write-tenant",
+            tenant_id,
+            "tenant",
+            GrantOptions {
+                non_delegable,
+                ..GrantOptions::default()
+            },
+        )
+        .await
+    }
+
+    #[rocket::async_test]
+    async fn revoking_a_grant_revokes_the_grants_delegated_from_it() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let (publisher, mut reader) = publisher(&pool).await?;
+        let attributes = permissions::Attributes::new(None, None);
+        let [a, b, c, d] = [
+            users::sqlite::insert(&pool, &users::types::User::new("test|a")).await?,
+            users::sqlite::insert(&pool, &users::types::User::new("test|b")).await?,
+            users::sqlite::insert(&pool, &users::types::User::new("test|c")).await?,
+            users::sqlite::insert(&pool, &users::types::User::new("test|d")).await?,
+        ];
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let tenant_id = tenant.id.to_string();
+        permissions::sqlite::insert(&pool, &grant(&a.id, "write-tenant", &tenant.id)?).await?;
+        let delegate = |from, to, non_delegable| {
+            grant_write_tenant(
+                &pool,
+                &publisher,
+                &attributes,
+                from,
+                to,
+                &tenant_id,
+                non_delegable,
+            )
+        };
+
+        // holding the action isn't enough to pass it on without `execute-grant-tenant`
+        assert!(matches!(
+            delegate(a.id, b.id, false).await,
+            Err(GrantPermissionError::Unauthorized)
+        ));
+        for user in [&a, &b, &c] {
+            permissions::sqlite::insert(
+                &pool,
+                &grant(&user.id, "execute-grant-tenant", &tenant.id)?,
+            )
+            .await?;
+        }
+        delegate(a.id, b.id, false).await?;
+        delegate(b.id, c.id, true).await?;
+        // c may grant, but not pass on a grant made non-delegable
+        assert!(matches!(
+            delegate(c.id, d.id, false).await,
+            Err(GrantPermissionError::Unauthorized)
+        ));
+
+        let revoked_grants = revoke_permission(
+            &pool,
+            &publisher,
+            &attributes,
+            &a.id,
+            &a.id,
+            "write-tenant",
+            &tenant_id,
+            "tenant",
+        )
+        .await?;
+        let mut expected = [&a, &b, &c]
+            .iter()
+            .map(|user| grant(&user.id, "write-tenant", &tenant.id).map(|p| p.to_string()))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        expected.sort();
+        let mut returned = revoked_grants
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        returned.sort();
+        assert_eq!(returned, expected);
+        assert_eq!(revoked(&mut reader, 3).await?, expected);
+        for user in [&a, &b, &c] {
+            assert!(
+                !has_permission_to(
+                    &pool,
+                    &attributes,
+                    &user.id,
+                    "write-tenant",
+                    &tenant_id,
+                    "tenant"
+                )
+                .await?
+            );
+        }
+
+        Ok(())
+    }
+}
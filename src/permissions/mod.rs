@@ -1,8 +1,10 @@
+mod condition;
 mod domain;
 mod routes;
 
 pub mod sqlite;
 pub mod types;
+pub use condition::{Attributes, Condition};
 pub use domain::events;
 pub use domain::service::*;
-pub use routes::routes;
+pub use routes::{role_routes, routes};
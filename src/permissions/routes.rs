@@ -1,6 +1,16 @@
-use crate::permissions::domain::service;
+use crate::auth::AuthenticatedUser;
+use crate::events::Publisher;
+use crate::permissions::{
+    self,
+    domain::{policy, service},
+    types::{self, RoleId},
+};
+use crate::tenants::TenantContext;
+use crate::types::{error::ApiError, id::InvalidId, pagination::Paginated};
+use crate::users::types::UserId;
 
-use rocket::{http::Status, route::Route};
+use color_eyre::eyre;
+use rocket::{http::Status, response::status, route::Route, serde::json::Json};
 use sqlx::SqlitePool;
 
 #[allow(clippy::no_effect_underscore_binding)]
@@ -8,75 +18,503 @@ pub fn routes() -> Vec<Route> {
     routes![
         has_permission_to,
         grant_user_permission_route,
-        revoke_user_permission_route
+        revoke_user_permission_route,
+        explain_permission_route,
+        list_user_permissions_route,
+        list_effective_permissions_route,
+        list_resource_access_route,
+        deny_user_permission_route,
+        remove_user_denial_route
+    ]
+}
+
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn role_routes() -> Vec<Route> {
+    routes![
+        list_roles_route,
+        create_role_route,
+        find_role_route,
+        update_role_route,
+        delete_role_route,
+        list_role_assignments_route,
+        assign_role_route,
+        unassign_role_route
     ]
 }
 
 #[get("/<user_id>/<action>/<resource_id>/<resource_kind>")]
 async fn has_permission_to(
     pool: &rocket::State<SqlitePool>,
-    user_id: &str,
+    user_id: Result<UserId, InvalidId>,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
-) -> Status {
-    let has_permission =
-        match service::has_permission_to(pool, user_id, action, resource_id, resource_kind).await {
-            Ok(has_permission) => has_permission,
-            Err(_) => todo!(),
-        };
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    let has_permission = service::check_permission(
+        pool,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
+        action,
+        resource_id,
+        resource_kind,
+    )
+    .await?;
 
     if has_permission {
-        Status::Ok
+        Ok(Status::Ok)
     } else {
-        todo!()
+        Err(ApiError::new(Status::NotFound).with_detail("user does not hold the permission"))
     }
 }
 
-#[post("/<user_id>/<action>/<resource_id>/<resource_kind>")]
+#[post(
+    "/<user_id>/<action>/<resource_id>/<resource_kind>?<condition>&<expires_at>&<non_delegable>"
+)]
+#[allow(clippy::too_many_arguments)]
 async fn grant_user_permission_route(
     pool: &rocket::State<SqlitePool>,
-    user_id: &str,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
-) -> Status {
-    let requesting_user_id = "mock-testing-id";
-    match service::grant_permission(
+    condition: Option<String>,
+    expires_at: Option<String>,
+    non_delegable: bool,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<types::Permission>, ApiError> {
+    let permission = service::grant_permission(
         pool,
-        requesting_user_id,
-        user_id,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
         action,
         resource_id,
         resource_kind,
+        service::GrantOptions {
+            condition,
+            expires_at,
+            non_delegable,
+        },
     )
-    .await
-    {
-        Ok(_) => Status::Ok,
-        Err(_) => todo!(),
-    }
+    .await?;
+
+    Ok(Json(permission))
 }
 
 #[delete("/<user_id>/<action>/<resource_id>/<resource_kind>")]
+#[allow(clippy::too_many_arguments)]
 async fn revoke_user_permission_route(
     pool: &rocket::State<SqlitePool>,
-    user_id: &str,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Vec<types::Permission>>, ApiError> {
+    let revoked = service::revoke_permission(
+        pool,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
+        action,
+        resource_id,
+        resource_kind,
+    )
+    .await?;
+
+    Ok(Json(revoked))
+}
+
+#[get("/explain/<user_id>/<action>/<resource_id>/<resource_kind>")]
+async fn explain_permission_route(
+    pool: &rocket::State<SqlitePool>,
+    user_id: Result<UserId, InvalidId>,
     action: &str,
     resource_id: &str,
     resource_kind: &str,
-) -> Status {
-    let requesting_user_id = "mock-testing-id";
-    match service::revoke_permission(
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<policy::Decision>, ApiError> {
+    let decision = service::explain_permission(
         pool,
-        requesting_user_id,
-        user_id,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
         action,
         resource_id,
         resource_kind,
     )
+    .await?;
+
+    Ok(Json(decision))
+}
+
+#[get("/users/<id>?<page>&<per_page>")]
+async fn list_user_permissions_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<UserId, InvalidId>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Paginated<types::Permission>>, ApiError> {
+    service::list_user_permissions(
+        pool,
+        &attributes,
+        &requesting_user.user_id,
+        &id?,
+        page,
+        per_page,
+    )
     .await
-    {
-        Ok(_) => Status::Ok,
-        Err(_) => todo!(),
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/users/<id>/effective")]
+async fn list_effective_permissions_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<UserId, InvalidId>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Vec<types::Rule>>, ApiError> {
+    service::list_effective_permissions(pool, &attributes, &requesting_user.user_id, &id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[get("/resources/<kind>/<id>")]
+async fn list_resource_access_route(
+    pool: &rocket::State<SqlitePool>,
+    kind: &str,
+    id: &str,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Vec<types::Access>>, ApiError> {
+    service::list_resource_access(pool, &attributes, &requesting_user.user_id, kind, id)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/deny/<user_id>/<action>/<resource_id>/<resource_kind>?<reason>")]
+#[allow(clippy::too_many_arguments)]
+async fn deny_user_permission_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+    reason: Option<String>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<types::Denial>, ApiError> {
+    service::deny_permission(
+        pool,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
+        action,
+        resource_id,
+        resource_kind,
+        reason,
+    )
+    .await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[delete("/deny/<user_id>/<action>/<resource_id>/<resource_kind>")]
+#[allow(clippy::too_many_arguments)]
+async fn remove_user_denial_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    user_id: Result<UserId, InvalidId>,
+    action: &str,
+    resource_id: &str,
+    resource_kind: &str,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    service::remove_denial(
+        pool,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &user_id?,
+        action,
+        resource_id,
+        resource_kind,
+    )
+    .await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/")]
+async fn list_roles_route(
+    pool: &rocket::State<SqlitePool>,
+    context: TenantContext,
+) -> eyre::Result<Json<Vec<types::Role>>, ApiError> {
+    service::list_roles(pool, &context)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/", data = "<payload>")]
+async fn create_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    payload: Json<service::RoleRequest>,
+    context: TenantContext,
+) -> eyre::Result<status::Created<Json<types::Role>>, ApiError> {
+    service::create_role(pool, &publisher, &context, payload.into_inner())
+        .await
+        .map(|role| status::Created::new(format!("/api/roles/{}", role.id)).body(Json(role)))
+        .map_err(ApiError::from)
+}
+
+#[get("/<id>")]
+async fn find_role_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<RoleId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Json<types::Role>, ApiError> {
+    service::find_role(pool, &context, &id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[put("/<id>", data = "<payload>")]
+async fn update_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<RoleId, InvalidId>,
+    payload: Json<service::RoleRequest>,
+    context: TenantContext,
+) -> eyre::Result<Json<types::Role>, ApiError> {
+    service::update_role(pool, &publisher, &context, &id?, payload.into_inner())
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[delete("/<id>")]
+async fn delete_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<RoleId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Status, ApiError> {
+    service::delete_role(pool, &publisher, &context, &id?).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/<id>/assignments")]
+async fn list_role_assignments_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<RoleId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Json<Vec<types::RoleAssignment>>, ApiError> {
+    service::list_role_assignments(pool, &context, &id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/<id>/assignments", data = "<payload>")]
+async fn assign_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<RoleId, InvalidId>,
+    payload: Json<service::AssignRoleRequest>,
+    context: TenantContext,
+) -> eyre::Result<status::Created<Json<types::RoleAssignment>>, ApiError> {
+    service::assign_role(pool, &publisher, &context, &id?, payload.into_inner())
+        .await
+        .map(|assignment| {
+            status::Created::new(format!(
+                "/api/roles/{}/assignments/{}/{}/{}",
+                assignment.role_id,
+                assignment.user_id,
+                assignment.resource.id(),
+                assignment.resource.kind()
+            ))
+            .body(Json(assignment))
+        })
+        .map_err(ApiError::from)
+}
+
+#[delete("/<id>/assignments/<user_id>/<resource_id>/<resource_kind>")]
+async fn unassign_role_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<RoleId, InvalidId>,
+    user_id: Result<UserId, InvalidId>,
+    resource_id: &str,
+    resource_kind: &str,
+    context: TenantContext,
+) -> eyre::Result<Status, ApiError> {
+    let payload = service::AssignRoleRequest {
+        user_id: user_id?.to_string(),
+        resource_id: resource_id.to_string(),
+        resource_kind: resource_kind.to_string(),
+    };
+    service::unassign_role(pool, &publisher, &context, &id?, payload).await?;
+
+    Ok(Status::NoContent)
+}
+
+impl From<service::HasPermissionError> for ApiError {
+    fn from(err: service::HasPermissionError) -> Self {
+        match err {
+            service::HasPermissionError::InvalidInput(err) => err.into(),
+            err @ service::HasPermissionError::Sqlx(_) => Self::internal("check permission", &err),
+        }
+    }
+}
+
+impl From<service::GrantPermissionError> for ApiError {
+    fn from(err: service::GrantPermissionError) -> Self {
+        match err {
+            service::GrantPermissionError::InvalidInput(err)
+            | service::GrantPermissionError::CreateFailed(
+                service::CreatePermissionError::InvalidInput(err),
+            ) => err.into(),
+            service::GrantPermissionError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::GrantPermissionError::AccessCheckFailed(err) => err.into(),
+            err @ service::GrantPermissionError::CreateFailed(_) => {
+                Self::internal("grant permission", &err)
+            }
+        }
+    }
+}
+
+impl From<service::RevokePermissionError> for ApiError {
+    fn from(err: service::RevokePermissionError) -> Self {
+        match err {
+            service::RevokePermissionError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::RevokePermissionError::AccessCheckFailed(err)
+            | service::RevokePermissionError::DeleteFailed(err) => err.into(),
+        }
+    }
+}
+
+impl From<service::ExplainPermissionError> for ApiError {
+    fn from(err: service::ExplainPermissionError) -> Self {
+        match err {
+            service::ExplainPermissionError::InvalidInput(err) => err.into(),
+            service::ExplainPermissionError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::ExplainPermissionError::AccessCheckFailed(err) => err.into(),
+            err @ service::ExplainPermissionError::Sqlx(_) => {
+                Self::internal("explain permission", &err)
+            }
+        }
+    }
+}
+
+impl From<service::CheckPermissionError> for ApiError {
+    fn from(err: service::CheckPermissionError) -> Self {
+        match err {
+            service::CheckPermissionError::InvalidInput(err) => err.into(),
+            service::CheckPermissionError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::CheckPermissionError::AccessCheckFailed(err) => err.into(),
+            err @ service::CheckPermissionError::Sqlx(_) => {
+                Self::internal("check permission", &err)
+            }
+        }
+    }
+}
+
+impl From<service::ListPermissionsError> for ApiError {
+    fn from(err: service::ListPermissionsError) -> Self {
+        match err {
+            service::ListPermissionsError::InvalidInput(err) => err.into(),
+            service::ListPermissionsError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::ListPermissionsError::AccessCheckFailed(err) => err.into(),
+            err @ service::ListPermissionsError::Sqlx(_) => {
+                Self::internal("list permissions", &err)
+            }
+        }
+    }
+}
+
+impl From<service::DenyPermissionError> for ApiError {
+    fn from(err: service::DenyPermissionError) -> Self {
+        match err {
+            service::DenyPermissionError::InvalidInput(err) => err.into(),
+            service::DenyPermissionError::Unauthorized => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::DenyPermissionError::NotFound => Self::new(Status::NotFound).with_detail(err),
+            service::DenyPermissionError::AccessCheckFailed(err) => err.into(),
+            err @ service::DenyPermissionError::Sqlx(_) => Self::internal("change denial", &err),
+        }
+    }
+}
+
+impl From<service::AssignRoleError> for ApiError {
+    fn from(err: service::AssignRoleError) -> Self {
+        match err {
+            service::AssignRoleError::InvalidInput(err) => err.into(),
+            service::AssignRoleError::AlreadyAssigned => {
+                Self::new(Status::Conflict).with_detail(err)
+            }
+            service::AssignRoleError::GlobalRole => Self::new(Status::Forbidden).with_detail(err),
+            service::AssignRoleError::Find(err) => err.into(),
+            err @ service::AssignRoleError::Sqlx(_) => {
+                Self::internal("change role assignment", &err)
+            }
+        }
+    }
+}
+
+impl From<service::ChangeRoleError> for ApiError {
+    fn from(err: service::ChangeRoleError) -> Self {
+        match err {
+            service::ChangeRoleError::InvalidInput(err) => err.into(),
+            service::ChangeRoleError::AlreadyExists => Self::new(Status::Conflict).with_detail(err),
+            service::ChangeRoleError::GlobalRole => Self::new(Status::Forbidden).with_detail(err),
+            service::ChangeRoleError::Find(err) => err.into(),
+            err @ service::ChangeRoleError::Sqlx(_) => Self::internal("change role", &err),
+        }
+    }
+}
+
+impl From<service::FindRoleError> for ApiError {
+    fn from(err: service::FindRoleError) -> Self {
+        match err {
+            service::FindRoleError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindRoleError::NotFound(_) => Self::new(Status::NotFound).with_detail(err),
+            service::FindRoleError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindRoleError::Sqlx(_) => Self::internal("find role", &err),
+        }
     }
 }
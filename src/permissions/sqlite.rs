@@ -1,26 +1,43 @@
-use crate::permissions::types;
+use crate::permissions::{
+    types::{self, RoleId},
+    Condition,
+};
+use crate::tenants::{types::TenantId, TenantScope};
+use crate::types::pagination::Pagination;
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
 use sqlx::SqliteExecutor;
+use std::convert::TryFrom;
 
+/// Inserts a grant, replacing the restrictions and grantor of the same grant if the user already
+/// holds it.
 pub async fn insert<'e>(
     executor: impl SqliteExecutor<'e>,
     permission: &types::Permission,
 ) -> eyre::Result<types::Permission, sqlx::Error> {
-    let user_id: String = permission.user_id.to_string();
     let action: String = permission.action.to_string();
     let resource_id = permission.resource.id();
     let resource_kind = permission.resource.kind().to_string();
+    let condition = permission.condition.as_ref().map(ToString::to_string);
 
     sqlx::query!(
         "
-INSERT INTO permissions (user_id, action, resource_id, resource_kind)
-VALUES (?, ?, ?, ?)
+INSERT INTO permissions
+    (user_id, action, resource_id, resource_kind, condition, expires_at, delegable, granted_by)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT (user_id, action, resource_id, resource_kind)
+DO UPDATE SET condition = excluded.condition, expires_at = excluded.expires_at,
+    delegable = excluded.delegable, granted_by = excluded.granted_by
     ",
-        user_id,
+        permission.user_id,
         action,
         resource_id,
-        resource_kind
+        resource_kind,
+        condition,
+        permission.expires_at,
+        permission.delegable,
+        permission.granted_by
     )
     .execute(executor)
     .await?;
@@ -28,23 +45,80 @@ VALUES (?, ?, ?, ?)
     Ok(permission.clone())
 }
 
-pub async fn delete<'e>(
+/// Deletes a grant along with every grant delegated onward from it, following `granted_by`
+/// through each user who passed the same permission on, and returns them all.
+pub async fn delete_with_delegated<'e>(
     executor: impl SqliteExecutor<'e>,
     permission: &types::Permission,
-) -> eyre::Result<(), sqlx::Error> {
-    let user_id: String = permission.user_id.to_string();
+) -> eyre::Result<Vec<types::Permission>, sqlx::Error> {
     let action: String = permission.action.to_string();
     let resource_id = permission.resource.id();
     let resource_kind = permission.resource.kind().to_string();
 
+    sqlx::query_as!(
+        PermissionRecord,
+        "WITH RECURSIVE revoked (user_id) AS (
+            SELECT ?1
+            UNION
+            SELECT permissions.user_id
+                FROM permissions
+                JOIN revoked ON permissions.granted_by = revoked.user_id
+                WHERE permissions.action = ?2
+                AND permissions.resource_id = ?3
+                AND permissions.resource_kind = ?4
+        )
+        DELETE FROM permissions
+            WHERE action = ?2
+            AND resource_id = ?3
+            AND resource_kind = ?4
+            AND user_id IN (SELECT user_id FROM revoked)
+            RETURNING user_id AS \"user_id!: UserId\", action AS \"action!\",
+                resource_id AS \"resource_id!\", resource_kind AS \"resource_kind!\",
+                condition, expires_at, delegable AS \"delegable!: bool\",
+                granted_by AS \"granted_by: UserId\"",
+        permission.user_id,
+        action,
+        resource_id,
+        resource_kind
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Permission::try_from)
+    .collect()
+}
+
+/// Deletes every grant on a resource, used when the resource itself is deleted.
+pub async fn delete_by_resource<'e>(
+    executor: impl SqliteExecutor<'e>,
+    resource: &types::Resource,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource_id = resource.id();
+    let resource_kind = resource.kind().to_string();
+
     sqlx::query!(
-        "DELETE FROM permissions
-    WHERE user_id = ?
-    AND action = ?
-    AND resource_id = ?
-    AND resource_kind = ?",
+        "DELETE FROM permissions WHERE resource_id = ? AND resource_kind = ?",
+        resource_id,
+        resource_kind
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes every grant a user holds on a resource.
+pub async fn delete_by_user_and_resource<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+    resource: &types::Resource,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource_id = resource.id();
+    let resource_kind = resource.kind().to_string();
+
+    sqlx::query!(
+        "DELETE FROM permissions WHERE user_id = ? AND resource_id = ? AND resource_kind = ?",
         user_id,
-        action,
         resource_id,
         resource_kind
     )
@@ -54,30 +128,819 @@ pub async fn delete<'e>(
     Ok(())
 }
 
-pub async fn has_permission_to<'e>(
+struct PermissionRecord {
+    user_id: UserId,
+    action: String,
+    resource_id: String,
+    resource_kind: String,
+    condition: Option<String>,
+    expires_at: Option<chrono::NaiveDateTime>,
+    delegable: bool,
+    granted_by: Option<UserId>,
+}
+
+/// Finds every permission a user holds, including those derived from their role assignments.
+///
+/// Conditional grants are left out, as whether they apply depends on each request, and so are
+/// expired grants which haven't been swept yet.
+pub async fn find_effective_by_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<Vec<types::Permission>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let records = sqlx::query_as!(
+        PermissionRecord,
+        "SELECT user_id AS \"user_id!: UserId\", action AS \"action!\",
+                resource_id AS \"resource_id!\", resource_kind AS \"resource_kind!\",
+                condition, expires_at, delegable AS \"delegable!: bool\",
+                granted_by AS \"granted_by: UserId\"
+            FROM permissions
+            WHERE user_id = ?
+            AND condition IS NULL
+            AND (expires_at IS NULL OR expires_at > ?)
+        UNION
+        SELECT role_assignments.user_id, role_permissions.action,
+                role_assignments.resource_id, role_assignments.resource_kind, NULL, NULL, TRUE,
+                NULL
+            FROM role_assignments
+            JOIN role_permissions ON role_permissions.role_id = role_assignments.role_id
+            WHERE role_assignments.user_id = ?",
+        user_id,
+        now,
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    records
+        .into_iter()
+        .map(types::Permission::try_from)
+        .collect()
+}
+
+impl TryFrom<PermissionRecord> for types::Permission {
+    type Error = sqlx::Error;
+
+    fn try_from(record: PermissionRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: record.user_id,
+            action: types::Actionable::try_from(record.action)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            resource: types::Resource::try_from((
+                record.resource_id.as_str(),
+                record.resource_kind.as_str(),
+            ))
+            .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            condition: decode_condition(record.condition.as_deref())?,
+            expires_at: record.expires_at,
+            delegable: record.delegable,
+            granted_by: record.granted_by,
+        })
+    }
+}
+
+fn decode_condition(condition: Option<&str>) -> eyre::Result<Option<Condition>, sqlx::Error> {
+    condition
+        .map(Condition::try_from)
+        .transpose()
+        .map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+struct RuleRecord {
+    user_id: UserId,
+    effect: String,
+    action: String,
+    resource_id: String,
+    resource_kind: String,
+    role_id: Option<RoleId>,
+    role_name: Option<String>,
+    role_tenant_id: Option<TenantId>,
+    condition: Option<String>,
+    delegable: bool,
+}
+
+impl TryFrom<RuleRecord> for types::Rule {
+    type Error = sqlx::Error;
+
+    fn try_from(record: RuleRecord) -> eyre::Result<Self, Self::Error> {
+        let effect = match record.effect.as_str() {
+            "allow" => types::Effect::Allow,
+            "deny" => types::Effect::Deny,
+            effect => {
+                return Err(sqlx::Error::Decode(
+                    eyre::eyre!("invalid rule effect `{effect}`").into(),
+                ))
+            }
+        };
+        let source = match (record.role_id, record.role_name) {
+            (Some(id), Some(name)) => types::Source::Role {
+                id,
+                name,
+                tenant_id: record.role_tenant_id,
+            },
+            _ => types::Source::Direct,
+        };
+
+        Ok(Self {
+            effect,
+            action: types::Actionable::try_from(record.action)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            resource: types::Resource::try_from((
+                record.resource_id.as_str(),
+                record.resource_kind.as_str(),
+            ))
+            .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            source,
+            condition: decode_condition(record.condition.as_deref())?,
+            delegable: record.delegable,
+        })
+    }
+}
+
+/// Finds every rule applying to a user: their unexpired direct grants, their denials, and the
+/// grants of the roles they are assigned.
+pub async fn find_rules<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<Vec<types::Rule>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query_as!(
+        RuleRecord,
+        "SELECT user_id AS \"user_id!: UserId\", 'allow' AS \"effect!: String\",
+                action AS \"action!\", resource_id AS \"resource_id!\",
+                resource_kind AS \"resource_kind!\", NULL AS \"role_id: RoleId\",
+                NULL AS \"role_name: String\", NULL AS \"role_tenant_id: TenantId\", condition,
+                delegable AS \"delegable!: bool\"
+            FROM permissions
+            WHERE user_id = ?
+            AND (expires_at IS NULL OR expires_at > ?)
+        UNION ALL
+        SELECT user_id, 'deny', action, resource_id, resource_kind, NULL, NULL, NULL, NULL, TRUE
+            FROM permission_denials
+            WHERE user_id = ?
+        UNION ALL
+        SELECT role_assignments.user_id, 'allow', role_permissions.action,
+                role_assignments.resource_id, role_assignments.resource_kind, roles.id, roles.name,
+                roles.tenant_id, NULL, TRUE
+            FROM role_assignments
+            JOIN roles ON roles.id = role_assignments.role_id
+            JOIN role_permissions ON role_permissions.role_id = role_assignments.role_id
+            WHERE role_assignments.user_id = ?",
+        user_id,
+        now,
+        user_id,
+        user_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Rule::try_from)
+    .collect()
+}
+
+impl TryFrom<RuleRecord> for types::Access {
+    type Error = sqlx::Error;
+
+    fn try_from(record: RuleRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: record.user_id,
+            rule: types::Rule::try_from(record)?,
+        })
+    }
+}
+
+/// Finds the rules every user holds on a resource, either on the resource itself or on every
+/// resource of its kind, skipping expired grants.
+///
+/// Grants on a tenant which users inherit within it aren't included, so for a user resource
+/// these are only the rules naming it.
+pub async fn find_access_to_resource<'e>(
+    executor: impl SqliteExecutor<'e>,
+    resource: &types::Resource,
+) -> eyre::Result<Vec<types::Access>, sqlx::Error> {
+    let resource_id = resource.id();
+    let resource_kind = resource.kind().to_string();
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query_as!(
+        RuleRecord,
+        "SELECT user_id AS \"user_id!: UserId\", 'allow' AS \"effect!: String\",
+                action AS \"action!\", resource_id AS \"resource_id!\",
+                resource_kind AS \"resource_kind!\", NULL AS \"role_id: RoleId\",
+                NULL AS \"role_name: String\", NULL AS \"role_tenant_id: TenantId\", condition,
+                delegable AS \"delegable!: bool\"
+            FROM permissions
+            WHERE resource_kind = ?1 AND resource_id IN (?2, '*')
+            AND (expires_at IS NULL OR expires_at > ?3)
+        UNION ALL
+        SELECT user_id, 'deny', action, resource_id, resource_kind, NULL, NULL, NULL, NULL, TRUE
+            FROM permission_denials
+            WHERE resource_kind = ?1 AND resource_id IN (?2, '*')
+        UNION ALL
+        SELECT role_assignments.user_id, 'allow', role_permissions.action,
+                role_assignments.resource_id, role_assignments.resource_kind, roles.id, roles.name,
+                roles.tenant_id, NULL, TRUE
+            FROM role_assignments
+            JOIN roles ON roles.id = role_assignments.role_id
+            JOIN role_permissions ON role_permissions.role_id = role_assignments.role_id
+            WHERE role_assignments.resource_kind = ?1
+            AND role_assignments.resource_id IN (?2, '*')
+        ORDER BY 1, 3",
+        resource_kind,
+        resource_id,
+        now
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Access::try_from)
+    .collect()
+}
+
+/// Finds a page of the grants a user holds directly, including expired grants which haven't been
+/// swept yet.
+pub async fn find_page_by_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+    pagination: &Pagination,
+) -> eyre::Result<Vec<types::Permission>, sqlx::Error> {
+    let limit = pagination.limit();
+    let offset = pagination.offset();
+    sqlx::query_as!(
+        PermissionRecord,
+        "SELECT user_id AS \"user_id!: UserId\", action AS \"action!\",
+                resource_id AS \"resource_id!\", resource_kind AS \"resource_kind!\",
+                condition, expires_at, delegable AS \"delegable!: bool\",
+                granted_by AS \"granted_by: UserId\"
+            FROM permissions
+            WHERE user_id = ?
+            ORDER BY resource_kind, resource_id, action
+            LIMIT ? OFFSET ?",
+        user_id,
+        limit,
+        offset
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Permission::try_from)
+    .collect()
+}
+
+/// Finds the ids of every resource of a kind a user holds a grant or role on, including `*` for
+/// a wildcard, as they are stored: always as text, whichever way ids of entities are stored.
+pub async fn find_resource_ids_by_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+    kind: &types::Target,
+) -> eyre::Result<Vec<String>, sqlx::Error> {
+    let resource_kind = kind.to_string();
+    sqlx::query_scalar!(
+        "SELECT resource_id AS \"resource_id!\" FROM permissions
+            WHERE user_id = ?1 AND resource_kind = ?2
+        UNION
+        SELECT resource_id FROM role_assignments
+            WHERE user_id = ?1 AND resource_kind = ?2",
+        user_id,
+        resource_kind
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn count_by_user<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(1) FROM permissions WHERE user_id = ?",
+        user_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(count.into())
+}
+
+/// Deletes every grant which expired, returning them.
+pub async fn delete_expired<'e>(
+    executor: impl SqliteExecutor<'e>,
+) -> eyre::Result<Vec<types::Permission>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query_as!(
+        PermissionRecord,
+        "DELETE FROM permissions
+            WHERE expires_at <= ?
+            RETURNING user_id AS \"user_id!: UserId\", action AS \"action!\",
+                resource_id AS \"resource_id!\", resource_kind AS \"resource_kind!\",
+                condition, expires_at, delegable AS \"delegable!: bool\",
+                granted_by AS \"granted_by: UserId\"",
+        now
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Permission::try_from)
+    .collect()
+}
+
+pub async fn insert_denial<'e>(
+    executor: impl SqliteExecutor<'e>,
+    denial: &types::Denial,
+) -> eyre::Result<(), sqlx::Error> {
+    let action = denial.action.to_string();
+    let resource_id = denial.resource.id();
+    let resource_kind = denial.resource.kind().to_string();
+
+    sqlx::query!(
+        "
+INSERT INTO permission_denials (user_id, action, resource_id, resource_kind, reason, denied_by, created_at)
+VALUES (?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT (user_id, action, resource_id, resource_kind)
+DO UPDATE SET reason = excluded.reason, denied_by = excluded.denied_by
+    ",
+        denial.user_id,
+        action,
+        resource_id,
+        resource_kind,
+        denial.reason,
+        denial.denied_by,
+        denial.created_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a denial, returning whether it existed.
+pub async fn delete_denial<'e>(
     executor: impl SqliteExecutor<'e>,
     permission: &types::Permission,
 ) -> eyre::Result<bool, sqlx::Error> {
-    let user_id: String = permission.user_id.to_string();
-    let action: String = permission.action.to_string();
+    let action = permission.action.to_string();
     let resource_id = permission.resource.id();
     let resource_kind = permission.resource.kind().to_string();
 
-    let exists = sqlx::query!(
-        "SELECT COUNT(1) as count
-            FROM permissions
-            WHERE user_id = ?
-            AND action = ?
-            AND resource_id = ?
-            AND resource_kind = ?",
-        user_id,
+    let result = sqlx::query!(
+        "DELETE FROM permission_denials
+            WHERE user_id = ? AND action = ? AND resource_id = ? AND resource_kind = ?",
+        permission.user_id,
         action,
         resource_id,
         resource_kind
     )
-    .fetch_optional(executor)
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+struct RoleRecord {
+    id: RoleId,
+    tenant_id: Option<TenantId>,
+    name: String,
+    created_at: chrono::NaiveDateTime,
+    actions: Option<String>,
+}
+
+impl TryFrom<RoleRecord> for types::Role {
+    type Error = sqlx::Error;
+
+    fn try_from(record: RoleRecord) -> eyre::Result<Self, Self::Error> {
+        let actions = record
+            .actions
+            .as_deref()
+            .map(|actions| {
+                actions
+                    .split(',')
+                    .map(types::Actionable::try_from)
+                    .collect::<eyre::Result<Vec<_>>>()
+            })
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(e.into()))?
+            .unwrap_or_default();
+
+        Ok(Self {
+            id: record.id,
+            tenant_id: record.tenant_id,
+            name: record.name,
+            actions,
+            created_at: record.created_at,
+        })
+    }
+}
+
+/// Finds the roles usable within a tenant: its own roles followed by the global ones.
+pub async fn find_roles<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+) -> eyre::Result<Vec<types::Role>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query_as!(
+        RoleRecord,
+        "SELECT roles.id AS \"id: RoleId\", roles.tenant_id AS \"tenant_id: TenantId\",
+                roles.name, roles.created_at,
+                GROUP_CONCAT(role_permissions.action) AS \"actions: String\"
+            FROM roles
+            LEFT JOIN role_permissions ON role_permissions.role_id = roles.id
+            WHERE roles.tenant_id = ? OR roles.tenant_id IS NULL
+            GROUP BY roles.id
+            ORDER BY roles.tenant_id IS NULL, roles.name",
+        tenant_id
+    )
+    .fetch_all(executor)
     .await?
-    .is_some();
+    .into_iter()
+    .map(types::Role::try_from)
+    .collect()
+}
+
+pub async fn find_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &RoleId,
+) -> eyre::Result<Option<types::Role>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let role = match sqlx::query_as!(
+        RoleRecord,
+        "SELECT roles.id AS \"id: RoleId\", roles.tenant_id AS \"tenant_id: TenantId\",
+                roles.name, roles.created_at,
+                GROUP_CONCAT(role_permissions.action) AS \"actions: String\"
+            FROM roles
+            LEFT JOIN role_permissions ON role_permissions.role_id = roles.id
+            WHERE roles.id = ? AND (roles.tenant_id = ? OR roles.tenant_id IS NULL)
+            GROUP BY roles.id",
+        id,
+        tenant_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Role::try_from(role).map(Some)
+}
+
+/// Finds a global role by name, such as the built-in role of a tenant membership.
+pub async fn find_global_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    name: &str,
+) -> eyre::Result<Option<types::Role>, sqlx::Error> {
+    let role = match sqlx::query_as!(
+        RoleRecord,
+        "SELECT roles.id AS \"id: RoleId\", roles.tenant_id AS \"tenant_id: TenantId\",
+                roles.name, roles.created_at,
+                GROUP_CONCAT(role_permissions.action) AS \"actions: String\"
+            FROM roles
+            LEFT JOIN role_permissions ON role_permissions.role_id = roles.id
+            WHERE roles.name = ? AND roles.tenant_id IS NULL
+            GROUP BY roles.id",
+        name
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Role::try_from(role).map(Some)
+}
+
+/// Inserts a role of the scoped tenant; its actions are added with `insert_role_action`.
+pub async fn insert_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    role: &types::Role,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query!(
+        "
+INSERT INTO roles (id, tenant_id, name, created_at)
+VALUES (?, ?, ?, ?)
+    ",
+        role.id,
+        tenant_id,
+        role.name,
+        role.created_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_role_name<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &RoleId,
+    name: &str,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query!(
+        "UPDATE roles SET name = ? WHERE id = ? AND tenant_id = ?",
+        name,
+        id,
+        tenant_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn insert_role_action<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    role_id: &RoleId,
+    action: &types::Actionable,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let action = action.to_string();
+
+    sqlx::query!(
+        "
+INSERT INTO role_permissions (role_id, action)
+SELECT id, ? FROM roles WHERE id = ? AND tenant_id = ?
+    ",
+        action,
+        role_id,
+        tenant_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_role_actions<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    role_id: &RoleId,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query!(
+        "DELETE FROM role_permissions
+            WHERE role_id IN (SELECT id FROM roles WHERE id = ? AND tenant_id = ?)",
+        role_id,
+        tenant_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a role of the scoped tenant along with its actions and assignments; global roles are
+/// never deleted.
+pub async fn delete_role<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &RoleId,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query!(
+        "DELETE FROM roles WHERE id = ? AND tenant_id = ?",
+        id,
+        tenant_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+struct RoleAssignmentRecord {
+    role_id: RoleId,
+    user_id: UserId,
+    resource_id: String,
+    resource_kind: String,
+}
+
+impl TryFrom<RoleAssignmentRecord> for types::RoleAssignment {
+    type Error = sqlx::Error;
+
+    fn try_from(record: RoleAssignmentRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            role_id: record.role_id,
+            user_id: record.user_id,
+            resource: types::Resource::try_from((
+                record.resource_id.as_str(),
+                record.resource_kind.as_str(),
+            ))
+            .map_err(|e| sqlx::Error::Decode(e.into()))?,
+        })
+    }
+}
+
+pub async fn find_role_assignments<'e>(
+    executor: impl SqliteExecutor<'e>,
+    role_id: &RoleId,
+) -> eyre::Result<Vec<types::RoleAssignment>, sqlx::Error> {
+    sqlx::query_as!(
+        RoleAssignmentRecord,
+        "SELECT role_id AS \"role_id: RoleId\", user_id AS \"user_id: UserId\", resource_id,
+                resource_kind
+            FROM role_assignments
+            WHERE role_id = ?
+            ORDER BY user_id, resource_kind, resource_id",
+        role_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::RoleAssignment::try_from)
+    .collect()
+}
+
+pub async fn insert_role_assignment<'e>(
+    executor: impl SqliteExecutor<'e>,
+    assignment: &types::RoleAssignment,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource_id = assignment.resource.id();
+    let resource_kind = assignment.resource.kind().to_string();
+
+    sqlx::query!(
+        "
+INSERT INTO role_assignments (role_id, user_id, resource_id, resource_kind)
+VALUES (?, ?, ?, ?)
+    ",
+        assignment.role_id,
+        assignment.user_id,
+        resource_id,
+        resource_kind
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a role assignment, returning whether it existed.
+pub async fn delete_role_assignment<'e>(
+    executor: impl SqliteExecutor<'e>,
+    assignment: &types::RoleAssignment,
+) -> eyre::Result<bool, sqlx::Error> {
+    let resource_id = assignment.resource.id();
+    let resource_kind = assignment.resource.kind().to_string();
+
+    let result = sqlx::query!(
+        "DELETE FROM role_assignments
+            WHERE role_id = ? AND user_id = ? AND resource_id = ? AND resource_kind = ?",
+        assignment.role_id,
+        assignment.user_id,
+        resource_id,
+        resource_kind
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes the global roles a user is assigned on a resource, leaving tenant roles in place.
+pub async fn delete_global_role_assignments<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+    resource: &types::Resource,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource_id = resource.id();
+    let resource_kind = resource.kind().to_string();
+
+    sqlx::query!(
+        "DELETE FROM role_assignments
+            WHERE user_id = ? AND resource_id = ? AND resource_kind = ?
+            AND role_id IN (SELECT id FROM roles WHERE tenant_id IS NULL)",
+        user_id,
+        resource_id,
+        resource_kind
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes every role assignment of a user within a tenant: assignments of the tenant's own roles
+/// and any role assigned on the tenant itself.
+pub async fn delete_tenant_role_assignments<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    user_id: &UserId,
+) -> eyre::Result<(), sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    // resource ids are always text, while the tenant id binds as a blob when ids are stored as
+    // blobs
+    let resource_id = tenant_id.to_string();
+    sqlx::query!(
+        "DELETE FROM role_assignments
+            WHERE user_id = ?
+            AND (
+                role_id IN (SELECT id FROM roles WHERE tenant_id = ?)
+                OR (resource_kind = 'tenant' AND resource_id = ?)
+            )",
+        user_id,
+        tenant_id,
+        resource_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes every role assignment on a resource, used when the resource itself is deleted.
+pub async fn delete_role_assignments_by_resource<'e>(
+    executor: impl SqliteExecutor<'e>,
+    resource: &types::Resource,
+) -> eyre::Result<(), sqlx::Error> {
+    let resource_id = resource.id();
+    let resource_kind = resource.kind().to_string();
+
+    sqlx::query!(
+        "DELETE FROM role_assignments WHERE resource_id = ? AND resource_kind = ?",
+        resource_id,
+        resource_kind
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenants;
+    use crate::types::sqlite::test_pool;
+    use crate::users;
+
+    #[rocket::async_test]
+    async fn grants_and_roles_on_a_tenant_are_found_and_deleted_by_its_id() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|user")).await?;
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let scope = TenantScope::unchecked(&tenant.id);
+        let resource = types::Resource::Tenant(tenant.id.to_string());
+        let role = find_global_role(&pool, "member")
+            .await?
+            .ok_or_else(|| eyre::eyre!("no member role"))?;
+        insert_role_assignment(
+            &pool,
+            &types::RoleAssignment {
+                role_id: role.id,
+                user_id: user.id,
+                resource: resource.clone(),
+            },
+        )
+        .await?;
+        insert(
+            &pool,
+            &types::Permission {
+                user_id: user.id,
+                action: types::Actionable::try_from("read-permissions")?,
+                resource: resource.clone(),
+                condition: None,
+                expires_at: None,
+                delegable: true,
+                granted_by: None,
+            },
+        )
+        .await?;
+
+        assert_eq!(
+            find_resource_ids_by_user(&pool, &user.id, &tenants::types::Tenant::kind()).await?,
+            vec![tenant.id.to_string()]
+        );
+        let sources = find_access_to_resource(&pool, &resource)
+            .await?
+            .into_iter()
+            .map(|access| access.rule.source)
+            .collect::<Vec<_>>();
+        assert_eq!(sources.len(), 2);
+        assert!(sources.contains(&types::Source::Role {
+            id: role.id,
+            name: role.name.clone(),
+            tenant_id: None,
+        }));
+
+        delete_tenant_role_assignments(&pool, &scope, &user.id).await?;
+        assert!(find_role_assignments(&pool, &role.id).await?.is_empty());
+        delete_by_resource(&pool, &resource).await?;
+        assert!(
+            find_resource_ids_by_user(&pool, &user.id, &tenants::types::Tenant::kind())
+                .await?
+                .is_empty()
+        );
 
-    Ok(exists)
+        Ok(())
+    }
 }
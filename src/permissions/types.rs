@@ -1,18 +1,23 @@
-use crate::types::{uuid::Uuid, validation::FieldValidationError};
-use crate::permissions;
+use crate::types::{id::define_id, validation::FieldValidationError};
+use crate::permissions::{self, Condition};
+use crate::tenants::types::TenantId;
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Resource {
     User(String),
     Tenant(String),
 }
 
 impl Resource {
+    /// The id of a resource which stands for every resource of its kind.
+    pub const WILDCARD: &'static str = "*";
+
     pub fn id(&self) -> &str {
         match self {
             Resource::User(id) | Resource::Tenant(id) => id,
@@ -51,7 +56,7 @@ impl fmt::Display for Resource {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Target(pub String);
 
 impl Target {
@@ -90,13 +95,55 @@ impl fmt::Display for Target {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The targets of the actions a tenant owns, see [`Actionable::is_tenant_scoped`].
+const TENANT_TARGETS: &[&str] = &["tenant", "webhooks", "permissions"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Actionable {
     Read(Target),
     Write(Target),
     Execute(Target),
 }
 
+impl Actionable {
+    pub const fn target(&self) -> &Target {
+        match self {
+            Self::Read(target) | Self::Write(target) | Self::Execute(target) => target,
+        }
+    }
+
+    /// The capability needed to delegate this action to others, such as `execute-grant-user`
+    /// for any action on users. A grant capability is delegated through itself.
+    pub fn grant(&self) -> Self {
+        match self {
+            Self::Execute(target) if target.0.starts_with("grant-") => self.clone(),
+            _ => Self::Execute(Target(format!("grant-{}", self.target()))),
+        }
+    }
+
+    /// Whether the action is on something a tenant owns: the tenant itself, its webhooks or the
+    /// permissions within it, or granting any of those. Only these can be bundled into a tenant's
+    /// roles or held over its members through the tenant, unlike actions on global user accounts
+    /// or operator actions such as `write-events`.
+    pub fn is_tenant_scoped(&self) -> bool {
+        let target = match self {
+            Self::Execute(Target(target)) => target.strip_prefix("grant-").unwrap_or(target),
+            Self::Read(Target(target)) | Self::Write(Target(target)) => target,
+        };
+
+        TENANT_TARGETS.contains(&target)
+    }
+
+    /// Whether holding this action also allows `other`: every action allows itself, and writing a
+    /// target allows reading it.
+    pub fn implies(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Write(granted), Self::Read(requested)) => granted == requested,
+            (granted, requested) => granted == requested,
+        }
+    }
+}
+
 impl TryFrom<&str> for Actionable {
     type Error = eyre::Report;
 
@@ -138,27 +185,44 @@ impl fmt::Display for Actionable {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Permission {
-    pub user_id: Uuid,
+    pub user_id: UserId,
     pub action: Actionable,
     pub resource: Resource,
+    /// Restricts the grant to requests whose attributes satisfy it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    /// When the grant stops applying, after which it is swept away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    /// Whether the holder may pass the grant on to others.
+    pub delegable: bool,
+    /// The user who delegated the grant, whose revocation also revokes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub granted_by: Option<UserId>,
 }
 
 impl Permission {
+    /// The target of actions on permissions themselves, such as `read-permissions`.
+    pub fn kind() -> Target {
+        Target("permissions".to_string())
+    }
+
     pub fn new(
-        user_id: &str,
+        user_id: &UserId,
         action: &str,
         resource: &Resource,
     ) -> eyre::Result<Self, FieldValidationError> {
         Ok(Self {
-            user_id: Uuid::try_from(user_id).map_err(|_| FieldValidationError {
-                field: "user_id".to_string(),
-                message: "invalid uuid provided for field `user_id`".to_string(),
-            })?,
+            user_id: *user_id,
             action: Actionable::try_from(action.to_string()).map_err(|e| FieldValidationError {
                 field: "action".to_string(),
                 message: e.to_string(),
             })?,
             resource: resource.clone(),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
         })
     }
 }
@@ -191,9 +255,217 @@ impl TryFrom<&str> for Permission {
             .ok_or(eyre::eyre!("missing `resource_kind` in permission string"))?;
 
         Ok(Self {
-            user_id: Uuid::try_from(user_id)?,
+            user_id: UserId::try_from(user_id)?,
             action: Actionable::try_from(action)?,
             resource: Resource::try_from((resource_id, resource_kind))?,
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
         })
     }
 }
+
+define_id!(RoleId, "role");
+
+/// A named bundle of actions which can be assigned to users on a resource.
+///
+/// Roles with a `tenant_id` are defined by and only assignable within that tenant, while global
+/// roles (such as the built-in membership roles) are managed by the system.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    pub id: RoleId,
+    pub tenant_id: Option<TenantId>,
+    pub name: String,
+    pub actions: Vec<Actionable>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl Role {
+    pub fn new(tenant_id: Option<&TenantId>, name: &str, actions: Vec<Actionable>) -> Self {
+        Self {
+            id: RoleId::new(),
+            tenant_id: tenant_id.copied(),
+            name: name.to_string(),
+            actions,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    pub const fn is_global(&self) -> bool {
+        self.tenant_id.is_none()
+    }
+}
+
+/// Grants a user every action of a role on a resource.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleAssignment {
+    pub role_id: RoleId,
+    pub user_id: UserId,
+    pub resource: Resource,
+}
+
+/// A user blocked from taking an action on a resource regardless of the grants they hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Denial {
+    pub user_id: UserId,
+    pub action: Actionable,
+    pub resource: Resource,
+    pub reason: Option<String>,
+    pub denied_by: Option<UserId>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl Denial {
+    pub fn new(permission: &Permission, reason: Option<String>, denied_by: &UserId) -> Self {
+        Self {
+            user_id: permission.user_id,
+            action: permission.action.clone(),
+            resource: permission.resource.clone(),
+            reason,
+            denied_by: Some(*denied_by),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// Where a rule comes from: a grant or denial held by the user directly, or one of their roles.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Source {
+    Direct,
+    Role {
+        id: RoleId,
+        name: String,
+        tenant_id: Option<TenantId>,
+    },
+}
+
+/// How a rule matched the requested resource, ordered from most to least specific.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Match {
+    Exact,
+    Wildcard,
+    Inherited,
+}
+
+/// An action a user is allowed or denied on a resource.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub effect: Effect,
+    pub action: Actionable,
+    pub resource: Resource,
+    pub source: Source,
+    /// Only set on direct grants made with a condition; the rule applies only while it holds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    /// Whether the rule counts towards delegating its action, which only direct grants made
+    /// non-delegable don't.
+    pub delegable: bool,
+}
+
+/// A rule some user holds on a resource, as listed when looking into who has access to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Access {
+    pub user_id: UserId,
+    #[serde(flatten)]
+    pub rule: Rule,
+}
+
+impl Rule {
+    /// Matches this rule against `action` on `resource`, where `tenant_ids` are the tenants the
+    /// resource belongs to: the tenant itself, or the tenants a user is a member of.
+    ///
+    /// A rule matches a resource when it is on that exact resource, when it is a wildcard on
+    /// every resource of its kind, or when it is on a tenant and the resource is a user within
+    /// it. Wildcards coming from a tenant's role only match resources within that tenant.
+    ///
+    /// A rule on a tenant only reaches its members for actions scoped to the tenant, and only
+    /// through the tenant of the request, `request_tenant_id`, so that it never reaches the
+    /// global accounts of its members or what they do within their other tenants.
+    ///
+    /// An allow rule matches the actions it implies, so allowing writes also allows reads, while
+    /// a deny rule matches the actions implying it, so denying reads also denies writes. Allow
+    /// rules from a tenant's roles never match actions which aren't scoped to the tenant, which
+    /// its roles may only have bundled before that was enforced.
+    pub fn matches(
+        &self,
+        action: &Actionable,
+        resource: &Resource,
+        tenant_ids: &[String],
+        request_tenant_id: Option<&TenantId>,
+    ) -> Option<Match> {
+        let action_matches = match self.effect {
+            Effect::Allow if self.tenant_bound().is_some() && !self.action.is_tenant_scoped() => {
+                false
+            }
+            Effect::Allow => self.action.implies(action),
+            Effect::Deny => action.implies(&self.action),
+        };
+        if !action_matches {
+            return None;
+        }
+
+        match (&self.resource, resource) {
+            (ruled, requested) if ruled == requested => Some(Match::Exact),
+            (Resource::User(id), Resource::User(_))
+            | (Resource::Tenant(id), Resource::Tenant(_))
+                if id == Resource::WILDCARD =>
+            {
+                (self.tenant_bound().is_none()
+                    || tenant_ids.iter().any(|tenant_id| self.is_within(tenant_id)))
+                .then_some(Match::Wildcard)
+            }
+            (Resource::Tenant(id), Resource::User(_)) => {
+                let request_tenant_id = request_tenant_id?.to_string();
+                (self.action.is_tenant_scoped()
+                    && (id == Resource::WILDCARD || *id == request_tenant_id)
+                    && tenant_ids.contains(&request_tenant_id)
+                    && self.is_within(&request_tenant_id))
+                .then_some(Match::Inherited)
+            }
+            _ => None,
+        }
+    }
+
+    /// The tenant of the role the rule comes from, which bounds what its wildcards match.
+    pub const fn tenant_bound(&self) -> Option<&TenantId> {
+        match &self.source {
+            Source::Role {
+                tenant_id: Some(tenant_id),
+                ..
+            } => Some(tenant_id),
+            _ => None,
+        }
+    }
+
+    fn is_within(&self, tenant_id: &str) -> bool {
+        self.tenant_bound()
+            .is_none_or(|bound| bound.to_string() == tenant_id)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let effect = match self.effect {
+            Effect::Allow => "allow",
+            Effect::Deny => "deny",
+        };
+        match &self.source {
+            Source::Direct => write!(f, "{effect} {} on {}", self.action, self.resource),
+            Source::Role { name, .. } => write!(
+                f,
+                "{effect} {} on {} through role `{name}`",
+                self.action, self.resource
+            ),
+        }
+    }
+}
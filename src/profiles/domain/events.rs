@@ -1,12 +1,16 @@
 use crate::events;
 use crate::profiles::types::Profile;
+use crate::users::types::UserId;
 
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ProfileEvent {
     Created(Profile),
-    Deleted(String),
+    Deleted(UserId),
 }
 
 pub struct ProfilesEventHandler;
@@ -17,19 +21,27 @@ impl ProfilesEventHandler {
     }
 }
 
+#[rocket::async_trait]
 impl events::EventHandler for ProfilesEventHandler {
-    fn handle(
+    fn name(&self) -> &'static str {
+        "profiles"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["profile.*"]
+    }
+
+    async fn handle(
         &self,
-        rx: Arc<tokio::sync::Mutex<bus::BusReader<events::AppEvent>>>,
+        _pool: &SqlitePool,
+        envelope: &events::Envelope,
     ) -> color_eyre::eyre::Result<()> {
-        tokio::spawn(async move {
-            let mut rx = rx.lock().await;
-            loop {
-                if let Ok(event) = rx.recv() {
-                    tracing::debug!("recv: {:?}", event);
-                }
-            }
-        });
+        tracing::debug!(
+            "handling {} {}: {:?}",
+            envelope.kind,
+            envelope.id,
+            envelope.event
+        );
 
         Ok(())
     }
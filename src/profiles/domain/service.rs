@@ -1,4 +1,4 @@
-use crate::types::uuid::Uuid;
+use crate::users::types::UserId;
 use crate::{profiles, profiles::types};
 
 use color_eyre::eyre;
@@ -13,7 +13,7 @@ pub enum CreateProfileError {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CreateProfile {
-    pub user_id: Uuid,
+    pub user_id: UserId,
     pub email: types::Email,
 }
 
@@ -1,12 +1,11 @@
 use crate::profiles::{domain::service, types};
-use crate::types::uuid::Uuid;
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
 use sqlx::SqliteExecutor;
-use std::convert::TryFrom;
 
 struct ProfileRecord {
-    user_id: String,
+    user_id: UserId,
     email: String,
 }
 
@@ -16,7 +15,7 @@ pub async fn find_one<'e>(
 ) -> eyre::Result<Option<types::Profile>, sqlx::Error> {
     let profile = match sqlx::query_as!(
         ProfileRecord,
-        "SELECT user_id, email FROM profiles WHERE email = ?",
+        "SELECT user_id AS \"user_id: UserId\", email FROM profiles WHERE email = ?",
         email
     )
     .fetch_one(executor)
@@ -30,30 +29,49 @@ pub async fn find_one<'e>(
     };
 
     Ok(Some(types::Profile {
-        user_id: Uuid::try_from(profile.user_id).unwrap(),
-        email: types::Email::new(&profile.email).unwrap(),
+        user_id: profile.user_id,
+        email: types::Email::new(&profile.email).map_err(|e| sqlx::Error::Decode(e.into()))?,
     }))
 }
 
+pub async fn find_by_user_id<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &UserId,
+) -> eyre::Result<Option<types::Profile>, sqlx::Error> {
+    sqlx::query_as!(
+        ProfileRecord,
+        "SELECT user_id AS \"user_id: UserId\", email FROM profiles WHERE user_id = ?",
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(|profile| {
+        Ok(types::Profile {
+            user_id: profile.user_id,
+            email: types::Email::new(&profile.email).map_err(|e| sqlx::Error::Decode(e.into()))?,
+        })
+    })
+    .transpose()
+}
+
 pub async fn insert<'e>(
     executor: impl SqliteExecutor<'e>,
     profile: &service::CreateProfile,
 ) -> eyre::Result<types::Profile, sqlx::Error> {
-    let user_id: String = profile.user_id.to_string();
     let email: String = profile.email.to_string();
     sqlx::query!(
         "
 INSERT INTO profiles (user_id, email)
 VALUES (?, ?)
     ",
-        user_id,
+        profile.user_id,
         email
     )
     .execute(executor)
     .await?;
 
     Ok(types::Profile {
-        user_id: profile.user_id.clone(),
+        user_id: profile.user_id,
         email: profile.email.clone(),
     })
 }
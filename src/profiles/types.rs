@@ -1,4 +1,4 @@
-use crate::types::uuid::Uuid;
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
 use lazy_regex::regex;
@@ -6,6 +6,9 @@ use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt;
 
+/// The longest email address which can be delivered to, as limited by SMTP.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Email(String);
 
@@ -45,6 +48,6 @@ impl TryFrom<String> for Email {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Profile {
-    pub user_id: Uuid,
+    pub user_id: UserId,
     pub email: Email,
 }
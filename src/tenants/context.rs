@@ -0,0 +1,139 @@
+use crate::auth::{self, AuthenticatedUser};
+use crate::memberships;
+use crate::permissions;
+use crate::tenants::types::TenantId;
+use crate::types::id::InvalidId;
+use crate::users::types::UserId;
+
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use sqlx::SqlitePool;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+pub const TENANT_HEADER: &str = "X-Tenant-ID";
+
+/// The tenant whose data a query may touch.
+///
+/// Functions in the `*::sqlite` modules over tenant-owned tables take a scope rather than a bare
+/// tenant id and add it to every `WHERE` clause, so data can only be read or changed within the
+/// tenant the caller was granted access to. Scopes come from a `TenantContext`, which is only
+/// built once the requesting user's membership of the tenant has been verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantScope(TenantId);
+
+impl TenantScope {
+    /// Scopes queries to a tenant without verifying access to it.
+    ///
+    /// Only for system flows which establish access themselves, such as sign up recording a
+    /// user's first membership; never build one from request input.
+    pub const fn unchecked(tenant_id: &TenantId) -> Self {
+        Self(*tenant_id)
+    }
+
+    pub const fn tenant_id(&self) -> &TenantId {
+        &self.0
+    }
+}
+
+/// The tenant the current request acts within, along with the requesting user's role in it.
+///
+/// The tenant is taken from the `tid` claim of a JWT access token, or from the `X-Tenant-ID`
+/// header for session tokens which aren't bound to a tenant. Routes taking this guard respond
+/// with `400 Bad Request` when no tenant is given, and `403 Forbidden` when the header contradicts
+/// the token or the user isn't a member of the tenant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantContext {
+    pub user_id: UserId,
+    pub role: memberships::types::Role,
+    pub scope: TenantScope,
+    /// The attributes of the request, which conditions on grants are evaluated against.
+    pub attributes: permissions::Attributes,
+}
+
+#[derive(Error, Debug)]
+pub enum TenantContextError {
+    #[error("failed to authenticate user")]
+    Authentication(Option<auth::AuthenticationError>),
+
+    #[error("missing tenant")]
+    Missing,
+
+    #[error("invalid tenant id")]
+    Invalid(#[from] InvalidId),
+
+    #[error("requested tenant does not match the tenant of the access token")]
+    Mismatch,
+
+    #[error("user is not a member of the tenant")]
+    NotMember,
+
+    #[error("tenant state is not managed by rocket")]
+    Unconfigured,
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TenantContext {
+    type Error = TenantContextError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = match request.guard::<AuthenticatedUser>().await {
+            Outcome::Success(user) => user,
+            Outcome::Failure((status, err)) => {
+                return Outcome::Failure((status, TenantContextError::Authentication(Some(err))))
+            }
+            Outcome::Forward(()) => {
+                return Outcome::Failure((
+                    Status::Unauthorized,
+                    TenantContextError::Authentication(None),
+                ))
+            }
+        };
+        let Some(pool) = request.rocket().state::<SqlitePool>() else {
+            return Outcome::Failure((
+                Status::InternalServerError,
+                TenantContextError::Unconfigured,
+            ));
+        };
+
+        let requested = match request
+            .headers()
+            .get_one(TENANT_HEADER)
+            .map(TenantId::try_from)
+        {
+            Some(Ok(tenant_id)) => Some(tenant_id),
+            Some(Err(err)) => {
+                return Outcome::Failure((Status::BadRequest, TenantContextError::Invalid(err)))
+            }
+            None => None,
+        };
+        let tenant_id = match (user.tenant_id, requested) {
+            (Some(claimed), Some(requested)) if claimed != requested => {
+                return Outcome::Failure((Status::Forbidden, TenantContextError::Mismatch))
+            }
+            (Some(tenant_id), _) | (None, Some(tenant_id)) => tenant_id,
+            (None, None) => {
+                return Outcome::Failure((Status::BadRequest, TenantContextError::Missing))
+            }
+        };
+
+        match memberships::sqlite::find_for_user(pool, &user.user_id, &tenant_id).await {
+            Ok(Some(membership)) => Outcome::Success(Self {
+                user_id: user.user_id,
+                role: membership.role,
+                attributes: permissions::Attributes::new(request.client_ip(), Some(&tenant_id)),
+                scope: TenantScope(tenant_id),
+            }),
+            Ok(None) => Outcome::Failure((Status::Forbidden, TenantContextError::NotMember)),
+            Err(err) => {
+                Outcome::Failure((Status::InternalServerError, TenantContextError::Sqlx(err)))
+            }
+        }
+    }
+}
@@ -1,13 +1,31 @@
 use crate::events;
-use crate::tenants::types::Tenant;
+use crate::tenants::types::{Invitation, Tenant, TenantId};
 
 use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum TenantEvent {
     Created(Tenant),
+    Updated(Tenant),
     Deleted(Tenant),
+    InvitationCreated(Invitation),
+    InvitationRevoked(Invitation),
+    InvitationAccepted(Invitation),
+}
+
+impl TenantEvent {
+    pub const fn tenant_id(&self) -> TenantId {
+        match self {
+            Self::Created(tenant) | Self::Updated(tenant) | Self::Deleted(tenant) => tenant.id,
+            Self::InvitationCreated(invitation)
+            | Self::InvitationRevoked(invitation)
+            | Self::InvitationAccepted(invitation) => invitation.tenant_id,
+        }
+    }
 }
 
 pub struct TenantsEventHandler;
@@ -18,19 +36,23 @@ impl TenantsEventHandler {
     }
 }
 
+#[rocket::async_trait]
 impl events::EventHandler for TenantsEventHandler {
-    fn handle(
-        &self,
-        rx: Arc<tokio::sync::Mutex<bus::BusReader<events::AppEvent>>>,
-    ) -> eyre::Result<()> {
-        tokio::spawn(async move {
-            let mut rx = rx.lock().await;
-            loop {
-                if let Ok(event) = rx.recv() {
-                    tracing::debug!("recv: {:?}", event);
-                }
-            }
-        });
+    fn name(&self) -> &'static str {
+        "tenants"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["tenant.*"]
+    }
+
+    async fn handle(&self, _pool: &SqlitePool, envelope: &events::Envelope) -> eyre::Result<()> {
+        tracing::debug!(
+            "handling {} {}: {:?}",
+            envelope.kind,
+            envelope.id,
+            envelope.event
+        );
 
         Ok(())
     }
@@ -1 +1,643 @@
+use crate::auth::{self, SessionConfig};
+use crate::events::{outbox, AppEvent, Publisher};
+use crate::memberships;
+use crate::permissions;
+use crate::profiles;
+use crate::tenants::{
+    self,
+    domain::events,
+    types::{self, InvitationId, TenantId},
+    TenantContext,
+};
+use crate::types::validation::FieldValidationError;
+use crate::users::{self, types::UserId};
 
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+const MAX_TENANT_NAME_LENGTH: usize = 128;
+
+#[derive(Error, Debug)]
+pub enum FindTenantError {
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("id `{0}` does not exist")]
+    NotFound(String),
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(permissions::HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+pub async fn find_tenant(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: &TenantId,
+) -> eyre::Result<types::Tenant, FindTenantError> {
+    ensure_permission(pool, attributes, requesting_user_id, "read-tenant", id).await?;
+
+    match tenants::sqlite::find_one(pool, id).await {
+        Ok(Some(tenant)) => Ok(tenant),
+        Ok(None) => Err(FindTenantError::NotFound(id.to_string())),
+        Err(err) => Err(FindTenantError::Sqlx(err)),
+    }
+}
+
+/// Lists every tenant the requesting user is allowed to read.
+pub async fn list_tenants(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+) -> eyre::Result<Vec<types::Tenant>, FindTenantError> {
+    let tenants = find_granted_to_user(pool, requesting_user_id).await?;
+    let checks = tenants
+        .iter()
+        .map(|tenant| {
+            (
+                permissions::types::Actionable::Read(types::Tenant::kind()),
+                permissions::types::Resource::Tenant(tenant.id.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let allowed = permissions::has_permissions_to(pool, attributes, requesting_user_id, &checks)
+        .await
+        .map_err(FindTenantError::AccessCheckFailed)?;
+
+    Ok(tenants
+        .into_iter()
+        .zip(allowed)
+        .filter_map(|(tenant, allowed)| allowed.then_some(tenant))
+        .collect())
+}
+
+/// Finds every tenant a user holds a grant or role on, directly or through a wildcard.
+///
+/// These are only the candidates for what the user may see; whether they are allowed to is
+/// decided by evaluating their permissions on each.
+async fn find_granted_to_user(
+    pool: &SqlitePool,
+    user_id: &UserId,
+) -> eyre::Result<Vec<types::Tenant>, sqlx::Error> {
+    // resource ids are text while tenant ids may be stored as blobs, so tenants are looked up by
+    // their parsed id rather than compared with the resource ids in sqlite
+    let resource_ids =
+        permissions::sqlite::find_resource_ids_by_user(pool, user_id, &types::Tenant::kind())
+            .await?;
+    if resource_ids
+        .iter()
+        .any(|id| id == permissions::types::Resource::WILDCARD)
+    {
+        return tenants::sqlite::find_all(pool).await;
+    }
+
+    let mut tenants = Vec::new();
+    for id in resource_ids {
+        let Ok(id) = TenantId::try_from(id) else {
+            continue;
+        };
+        if let Some(tenant) = tenants::sqlite::find_one(pool, &id).await? {
+            tenants.push(tenant);
+        }
+    }
+    tenants.sort_by_key(|tenant| (tenant.created_at, tenant.id));
+
+    Ok(tenants)
+}
+
+#[derive(Error, Debug)]
+pub enum CreateTenantError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTenantRequest {
+    pub name: String,
+}
+
+/// Creates a tenant with the requesting user as its owner.
+pub async fn create_tenant(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    requesting_user_id: &UserId,
+    payload: CreateTenantRequest,
+) -> eyre::Result<types::Tenant, CreateTenantError> {
+    let name = validate_name(&payload.name).map_err(CreateTenantError::InvalidInput)?;
+    let tenant = types::Tenant::new(&name);
+
+    let mut tx = pool.begin().await.map_err(CreateTenantError::Sqlx)?;
+    tenants::sqlite::insert(&mut tx, &tenant)
+        .await
+        .map_err(CreateTenantError::Sqlx)?;
+    memberships::insert_membership(
+        &mut tx,
+        &tenants::TenantScope::unchecked(&tenant.id),
+        requesting_user_id,
+        memberships::types::Role::Owner,
+    )
+    .await
+    .map_err(CreateTenantError::Sqlx)?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::Created(
+                tenant.clone(),
+            )))
+            .with_actor(requesting_user_id),
+    )
+    .await
+    .map_err(CreateTenantError::Sqlx)?;
+    tx.commit().await.map_err(CreateTenantError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(tenant)
+}
+
+#[derive(Error, Debug)]
+pub enum UpdateTenantError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("failed to find tenant")]
+    Find(#[from] FindTenantError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTenantRequest {
+    pub name: String,
+}
+
+pub async fn update_tenant(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: &TenantId,
+    payload: UpdateTenantRequest,
+) -> eyre::Result<types::Tenant, UpdateTenantError> {
+    let name = validate_name(&payload.name).map_err(UpdateTenantError::InvalidInput)?;
+    ensure_permission(pool, attributes, requesting_user_id, "write-tenant", id).await?;
+
+    let mut tx = pool.begin().await.map_err(UpdateTenantError::Sqlx)?;
+    let Some(mut tenant) = tenants::sqlite::find_one(&mut tx, id).await? else {
+        return Err(UpdateTenantError::Find(FindTenantError::NotFound(
+            id.to_string(),
+        )));
+    };
+    tenant.name = name;
+    let tenant = tenants::sqlite::update(&mut tx, &tenant).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::Updated(
+                tenant.clone(),
+            )))
+            .with_actor(requesting_user_id),
+    )
+    .await
+    .map_err(UpdateTenantError::Sqlx)?;
+    tx.commit().await.map_err(UpdateTenantError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(tenant)
+}
+
+/// Deletes a tenant along with every permission granted and role assigned on it.
+pub async fn delete_tenant(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: &TenantId,
+) -> eyre::Result<(), FindTenantError> {
+    ensure_permission(pool, attributes, requesting_user_id, "write-tenant", id).await?;
+
+    let mut tx = pool.begin().await.map_err(FindTenantError::Sqlx)?;
+    let Some(tenant) = tenants::sqlite::find_one(&mut tx, id).await? else {
+        return Err(FindTenantError::NotFound(id.to_string()));
+    };
+    tenants::sqlite::delete(&mut tx, id).await?;
+    let resource = permissions::types::Resource::Tenant(id.to_string());
+    permissions::sqlite::delete_by_resource(&mut tx, &resource).await?;
+    permissions::sqlite::delete_role_assignments_by_resource(&mut tx, &resource).await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::Deleted(tenant)))
+            .with_actor(requesting_user_id),
+    )
+    .await
+    .map_err(FindTenantError::Sqlx)?;
+    tx.commit().await.map_err(FindTenantError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(())
+}
+
+async fn ensure_permission(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    action: &str,
+    id: &TenantId,
+) -> eyre::Result<(), FindTenantError> {
+    let can = permissions::has_permission_to(
+        pool,
+        attributes,
+        requesting_user_id,
+        action,
+        &id.to_string(),
+        &types::Tenant::kind().to_string(),
+    )
+    .await
+    .map_err(FindTenantError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(FindTenantError::PermissionDenied)
+    }
+}
+
+fn validate_name(name: &str) -> eyre::Result<String, FieldValidationError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TENANT_NAME_LENGTH {
+        return Err(FieldValidationError {
+            field: "name".to_string(),
+            message: format!("name must be between 1 and {MAX_TENANT_NAME_LENGTH} characters long"),
+        });
+    }
+
+    Ok(name.to_string())
+}
+
+/// How long invitations can be accepted for when no expiry is requested.
+const DEFAULT_INVITATION_TTL_HOURS: i64 = 7 * 24;
+const MAX_INVITATION_TTL_HOURS: i64 = 30 * 24;
+
+#[derive(Error, Debug)]
+pub enum InviteError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("user is already a member of the tenant")]
+    AlreadyMember,
+
+    #[error("email already has a pending invitation to the tenant")]
+    AlreadyInvited,
+
+    #[error("failed to find tenant")]
+    Find(#[from] FindTenantError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    pub role: memberships::types::Role,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedInvitation {
+    #[serde(flatten)]
+    pub invitation: types::Invitation,
+    /// The single-use token to deliver to the invitee; it cannot be retrieved again.
+    pub token: String,
+}
+
+/// Invites someone to join the current tenant by email.
+///
+/// Inviting requires `write-tenant`, and inviting an owner additionally requires
+/// `execute-tenant`, mirroring the rules for adding members directly.
+pub async fn create_invitation(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    config: &SessionConfig,
+    context: &TenantContext,
+    payload: CreateInvitationRequest,
+) -> eyre::Result<CreatedInvitation, InviteError> {
+    let email = profiles::types::Email::try_from(payload.email).map_err(|e: eyre::Report| {
+        InviteError::InvalidInput(FieldValidationError {
+            field: "email".to_string(),
+            message: e.to_string(),
+        })
+    })?;
+    let ttl_hours = payload
+        .expires_in_hours
+        .unwrap_or(DEFAULT_INVITATION_TTL_HOURS);
+    if !(1..=MAX_INVITATION_TTL_HOURS).contains(&ttl_hours) {
+        return Err(InviteError::InvalidInput(FieldValidationError {
+            field: "expires_in_hours".to_string(),
+            message: format!("expiry must be between 1 and {MAX_INVITATION_TTL_HOURS} hours"),
+        }));
+    }
+
+    let tenant_id = context.scope.tenant_id();
+    ensure_permission(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        "write-tenant",
+        tenant_id,
+    )
+    .await?;
+    if payload.role == memberships::types::Role::Owner {
+        ensure_permission(
+            pool,
+            &context.attributes,
+            &context.user_id,
+            "execute-tenant",
+            tenant_id,
+        )
+        .await?;
+    }
+
+    let mut tx = pool.begin().await.map_err(InviteError::Sqlx)?;
+    if let Some(profile) = profiles::sqlite::find_one(&mut tx, &email.to_string()).await? {
+        if memberships::sqlite::find_one(&mut tx, &context.scope, &profile.user_id)
+            .await?
+            .is_some()
+        {
+            return Err(InviteError::AlreadyMember);
+        }
+    }
+    if tenants::sqlite::has_pending_invitation(&mut tx, &context.scope, &email).await? {
+        return Err(InviteError::AlreadyInvited);
+    }
+
+    let token = auth::generate_token(&config.secret);
+    let invitation = tenants::sqlite::insert_invitation(
+        &mut tx,
+        &context.scope,
+        &types::Invitation::new(
+            context.scope.tenant_id(),
+            &email,
+            payload.role,
+            &auth::hash_token(&token),
+            &context.user_id,
+            chrono::Duration::hours(ttl_hours),
+        ),
+    )
+    .await?;
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::InvitationCreated(
+                invitation.clone(),
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await
+    .map_err(InviteError::Sqlx)?;
+    tx.commit().await.map_err(InviteError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(CreatedInvitation { invitation, token })
+}
+
+/// Lists the invitations to the current tenant which can still be accepted.
+pub async fn list_invitations(
+    pool: &SqlitePool,
+    context: &TenantContext,
+) -> eyre::Result<Vec<types::Invitation>, FindTenantError> {
+    ensure_permission(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        "write-tenant",
+        context.scope.tenant_id(),
+    )
+    .await?;
+
+    tenants::sqlite::find_pending_invitations(pool, &context.scope)
+        .await
+        .map_err(FindTenantError::Sqlx)
+}
+
+pub async fn revoke_invitation(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    context: &TenantContext,
+    id: &InvitationId,
+) -> eyre::Result<(), FindTenantError> {
+    ensure_permission(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        "write-tenant",
+        context.scope.tenant_id(),
+    )
+    .await?;
+
+    let mut tx = pool.begin().await.map_err(FindTenantError::Sqlx)?;
+    let Some(invitation) = tenants::sqlite::revoke_invitation(&mut tx, &context.scope, id).await?
+    else {
+        return Err(FindTenantError::NotFound(id.to_string()));
+    };
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::InvitationRevoked(
+                invitation,
+            )))
+            .with_actor(&context.user_id),
+    )
+    .await?;
+    tx.commit().await.map_err(FindTenantError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum AcceptInvitationError {
+    #[error("invitation token is invalid, expired, revoked or was already used")]
+    InvalidToken,
+
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("user is already a member of the tenant")]
+    AlreadyMember,
+
+    #[error("failed to create user")]
+    SignUp(#[from] auth::SignUpError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+    /// Password for the new account when the invited email doesn't belong to a user yet.
+    pub password: Option<String>,
+}
+
+/// Accepts an invitation, making the user with the invited email a member of the tenant.
+///
+/// When nobody has signed up with the email a user is created with the given password. The
+/// invitation is used up, the user created and the membership recorded in one transaction, so a
+/// failure part way leaves the invitation pending.
+pub async fn accept_invitation(
+    pool: &SqlitePool,
+    publisher: &Publisher,
+    config: &SessionConfig,
+    payload: AcceptInvitationRequest,
+) -> eyre::Result<memberships::types::Membership, AcceptInvitationError> {
+    let token_hash = auth::verify_token(&config.secret, &payload.token)
+        .ok_or(AcceptInvitationError::InvalidToken)?;
+    let password_hash = match &payload.password {
+        Some(password) => Some(auth::hash_new_password(password).await?),
+        None => None,
+    };
+
+    let mut tx = pool.begin().await.map_err(AcceptInvitationError::Sqlx)?;
+    let Some(invitation) = tenants::sqlite::accept_invitation(&mut tx, &token_hash).await? else {
+        return Err(AcceptInvitationError::InvalidToken);
+    };
+    // holding the token is what grants access to the tenant
+    let scope = tenants::TenantScope::unchecked(&invitation.tenant_id);
+
+    let email = invitation.email.to_string();
+    let (user, membership) =
+        if let Some(profile) = profiles::sqlite::find_one(&mut tx, &email).await? {
+            if memberships::sqlite::find_one(&mut tx, &scope, &profile.user_id)
+                .await?
+                .is_some()
+            {
+                return Err(AcceptInvitationError::AlreadyMember);
+            }
+            let membership =
+                memberships::insert_membership(&mut tx, &scope, &profile.user_id, invitation.role)
+                    .await?;
+            (None, membership)
+        } else {
+            let Some(password_hash) = password_hash else {
+                return Err(AcceptInvitationError::InvalidInput(FieldValidationError {
+                    field: "password".to_string(),
+                    message: "a password is required to create an account".to_string(),
+                }));
+            };
+            let user = auth::insert_password_user(
+                &mut tx,
+                &email,
+                &password_hash,
+                invitation.tenant_id,
+                Some(invitation.role),
+            )
+            .await?;
+            let membership = memberships::sqlite::find_one(&mut tx, &scope, &user.id)
+                .await?
+                .ok_or(sqlx::Error::RowNotFound)?;
+            (Some(user), membership)
+        };
+    if let Some(user) = user {
+        outbox::insert(
+            &mut tx,
+            &publisher
+                .envelope(AppEvent::User(users::events::UserEvent::Created(user)))
+                .with_actor(&membership.user_id)
+                .with_tenant(&membership.tenant_id),
+        )
+        .await?;
+    }
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::Tenant(events::TenantEvent::InvitationAccepted(
+                invitation,
+            )))
+            .with_actor(&membership.user_id),
+    )
+    .await?;
+    tx.commit().await.map_err(AcceptInvitationError::Sqlx)?;
+
+    publisher.notify();
+
+    Ok(membership)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::sqlite::test_pool;
+
+    fn grant(user_id: &UserId, resource_id: &str) -> eyre::Result<permissions::types::Permission> {
+        Ok(permissions::types::Permission {
+            user_id: *user_id,
+            action: permissions::types::Actionable::try_from("read-tenant")?,
+            resource: permissions::types::Resource::Tenant(resource_id.to_string()),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
+        })
+    }
+
+    #[rocket::async_test]
+    async fn lists_the_tenants_granted_directly_through_roles_or_a_wildcard() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let attributes = permissions::Attributes::new(None, None);
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|user")).await?;
+        let operator =
+            users::sqlite::insert(&pool, &users::types::User::new("test|operator")).await?;
+        let granted = tenants::sqlite::insert(&pool, &types::Tenant::new("granted")).await?;
+        let assigned = tenants::sqlite::insert(&pool, &types::Tenant::new("assigned")).await?;
+        let other = tenants::sqlite::insert(&pool, &types::Tenant::new("other")).await?;
+
+        permissions::sqlite::insert(&pool, &grant(&user.id, &granted.id.to_string())?).await?;
+        let scope = tenants::TenantScope::unchecked(&assigned.id);
+        let read_tenant = permissions::types::Actionable::try_from("read-tenant")?;
+        let role =
+            permissions::types::Role::new(Some(&assigned.id), "readers", vec![read_tenant.clone()]);
+        permissions::sqlite::insert_role(&pool, &scope, &role).await?;
+        permissions::sqlite::insert_role_action(&pool, &scope, &role.id, &read_tenant).await?;
+        permissions::sqlite::insert_role_assignment(
+            &pool,
+            &permissions::types::RoleAssignment {
+                role_id: role.id,
+                user_id: user.id,
+                resource: permissions::types::Resource::Tenant(assigned.id.to_string()),
+            },
+        )
+        .await?;
+        permissions::sqlite::insert(
+            &pool,
+            &grant(&operator.id, permissions::types::Resource::WILDCARD)?,
+        )
+        .await?;
+
+        assert_eq!(
+            list_tenants(&pool, &attributes, &user.id).await?,
+            vec![granted.clone(), assigned.clone()]
+        );
+        assert_eq!(
+            list_tenants(&pool, &attributes, &operator.id).await?,
+            vec![granted, assigned, other]
+        );
+
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
+mod context;
 mod domain;
 mod routes;
 pub mod sqlite;
 pub mod types;
 
+pub use context::{TenantContext, TenantScope};
 pub use domain::events;
-pub use routes::routes;
+pub use domain::service::{
+    create_invitation, CreateInvitationRequest, CreatedInvitation, InviteError,
+};
+pub use routes::{invitation_routes, routes};
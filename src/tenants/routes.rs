@@ -1,11 +1,237 @@
-use crate::events::AppEvent;
+use crate::auth::{AuthenticatedUser, SessionConfig};
+use crate::events::Publisher;
+use crate::memberships;
+use crate::permissions;
+use crate::tenants::{
+    domain::service,
+    types::{self, InvitationId, TenantId},
+    TenantContext,
+};
+use crate::types::{error::ApiError, id::InvalidId};
 
-use bus::Bus;
 use color_eyre::eyre;
-use rocket::{http::Status, route::Route, serde::json::Json};
+use rocket::{http::Status, response::status, route::Route, serde::json::Json};
 use sqlx::SqlitePool;
 
 #[allow(clippy::no_effect_underscore_binding)]
 pub fn routes() -> Vec<Route> {
-    routes![]
+    routes![
+        list_tenants_route,
+        create_tenant_route,
+        find_tenant_route,
+        update_tenant_route,
+        delete_tenant_route
+    ]
+}
+
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn invitation_routes() -> Vec<Route> {
+    routes![
+        list_invitations_route,
+        create_invitation_route,
+        revoke_invitation_route,
+        accept_invitation_route
+    ]
+}
+
+#[get("/")]
+async fn list_tenants_route(
+    pool: &rocket::State<SqlitePool>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<Vec<types::Tenant>>, ApiError> {
+    service::list_tenants(pool, &attributes, &requesting_user.user_id)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/", data = "<payload>")]
+async fn create_tenant_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    payload: Json<service::CreateTenantRequest>,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<status::Created<Json<types::Tenant>>, ApiError> {
+    let tenant = service::create_tenant(
+        pool,
+        &publisher,
+        &requesting_user.user_id,
+        payload.into_inner(),
+    )
+    .await?;
+
+    Ok(status::Created::new(format!("/api/tenants/{}", tenant.id)).body(Json(tenant)))
+}
+
+#[get("/<id>")]
+async fn find_tenant_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<TenantId, InvalidId>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<types::Tenant>, ApiError> {
+    service::find_tenant(pool, &attributes, &requesting_user.user_id, &id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[put("/<id>", data = "<payload>")]
+async fn update_tenant_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<TenantId, InvalidId>,
+    payload: Json<service::UpdateTenantRequest>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<types::Tenant>, ApiError> {
+    let tenant = service::update_tenant(
+        pool,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &id?,
+        payload.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(tenant))
+}
+
+#[delete("/<id>")]
+async fn delete_tenant_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<TenantId, InvalidId>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    service::delete_tenant(
+        pool,
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &id?,
+    )
+    .await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/")]
+async fn list_invitations_route(
+    pool: &rocket::State<SqlitePool>,
+    context: TenantContext,
+) -> eyre::Result<Json<Vec<types::Invitation>>, ApiError> {
+    service::list_invitations(pool, &context)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/", data = "<payload>")]
+async fn create_invitation_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    config: &rocket::State<SessionConfig>,
+    payload: Json<service::CreateInvitationRequest>,
+    context: TenantContext,
+) -> eyre::Result<status::Created<Json<service::CreatedInvitation>>, ApiError> {
+    let created =
+        service::create_invitation(pool, &publisher, config, &context, payload.into_inner())
+            .await?;
+
+    Ok(
+        status::Created::new(format!("/api/invitations/{}", created.invitation.id))
+            .body(Json(created)),
+    )
+}
+
+#[delete("/<id>")]
+async fn revoke_invitation_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    id: Result<InvitationId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Status, ApiError> {
+    service::revoke_invitation(pool, &publisher, &context, &id?).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[post("/accept", data = "<payload>")]
+async fn accept_invitation_route(
+    pool: &rocket::State<SqlitePool>,
+    publisher: Publisher,
+    config: &rocket::State<SessionConfig>,
+    payload: Json<service::AcceptInvitationRequest>,
+) -> eyre::Result<Json<memberships::types::Membership>, ApiError> {
+    let membership =
+        service::accept_invitation(pool, &publisher, config, payload.into_inner()).await?;
+
+    Ok(Json(membership))
+}
+
+impl From<service::CreateTenantError> for ApiError {
+    fn from(err: service::CreateTenantError) -> Self {
+        match err {
+            service::CreateTenantError::InvalidInput(err) => err.into(),
+            err @ service::CreateTenantError::Sqlx(_) => Self::internal("create tenant", &err),
+        }
+    }
+}
+
+impl From<service::UpdateTenantError> for ApiError {
+    fn from(err: service::UpdateTenantError) -> Self {
+        match err {
+            service::UpdateTenantError::InvalidInput(err) => err.into(),
+            service::UpdateTenantError::Find(err) => err.into(),
+            err @ service::UpdateTenantError::Sqlx(_) => Self::internal("update tenant", &err),
+        }
+    }
+}
+
+impl From<service::InviteError> for ApiError {
+    fn from(err: service::InviteError) -> Self {
+        match err {
+            service::InviteError::InvalidInput(err) => err.into(),
+            service::InviteError::AlreadyMember | service::InviteError::AlreadyInvited => {
+                Self::new(Status::Conflict).with_detail(err)
+            }
+            service::InviteError::Find(err) => err.into(),
+            err @ service::InviteError::Sqlx(_) => Self::internal("create invitation", &err),
+        }
+    }
+}
+
+impl From<service::AcceptInvitationError> for ApiError {
+    fn from(err: service::AcceptInvitationError) -> Self {
+        match err {
+            service::AcceptInvitationError::InvalidToken => {
+                Self::new(Status::NotFound).with_detail(err)
+            }
+            service::AcceptInvitationError::InvalidInput(err) => err.into(),
+            service::AcceptInvitationError::AlreadyMember => {
+                Self::new(Status::Conflict).with_detail(err)
+            }
+            service::AcceptInvitationError::SignUp(err) => err.into(),
+            err @ service::AcceptInvitationError::Sqlx(_) => {
+                Self::internal("accept invitation", &err)
+            }
+        }
+    }
+}
+
+impl From<service::FindTenantError> for ApiError {
+    fn from(err: service::FindTenantError) -> Self {
+        match err {
+            service::FindTenantError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindTenantError::NotFound(_) => Self::new(Status::NotFound).with_detail(err),
+            service::FindTenantError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindTenantError::Sqlx(_) => Self::internal("find tenant", &err),
+        }
+    }
 }
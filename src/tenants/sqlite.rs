@@ -1,23 +1,22 @@
-use crate::tenants::types;
-use crate::types::uuid::Uuid;
+use crate::memberships;
+use crate::profiles;
+use crate::tenants::{
+    types::{self, InvitationId, TenantId},
+    TenantScope,
+};
+use crate::users::types::UserId;
 
 use color_eyre::eyre;
 use sqlx::SqliteExecutor;
 use std::convert::TryFrom;
 
-struct TenantRecord {
-    id: String,
-    name: String,
-    created_at: chrono::NaiveDateTime,
-}
-
 pub async fn find_one<'e>(
     executor: impl SqliteExecutor<'e>,
-    id: &str,
+    id: &TenantId,
 ) -> eyre::Result<Option<types::Tenant>, sqlx::Error> {
     let tenant = match sqlx::query_as!(
-        TenantRecord,
-        "SELECT id, name, created_at FROM tenants WHERE id = ?",
+        types::Tenant,
+        "SELECT id AS \"id: TenantId\", name, created_at FROM tenants WHERE id = ?",
         id
     )
     .fetch_one(executor)
@@ -30,24 +29,30 @@ pub async fn find_one<'e>(
         },
     };
 
-    Ok(Some(types::Tenant {
-        id: Uuid::try_from(tenant.id).unwrap(),
-        name: tenant.name,
-        created_at: tenant.created_at,
-    }))
+    Ok(Some(tenant))
+}
+
+pub async fn find_all<'e>(
+    executor: impl SqliteExecutor<'e>,
+) -> eyre::Result<Vec<types::Tenant>, sqlx::Error> {
+    sqlx::query_as!(
+        types::Tenant,
+        "SELECT id AS \"id: TenantId\", name, created_at FROM tenants ORDER BY created_at, id"
+    )
+    .fetch_all(executor)
+    .await
 }
 
 pub async fn insert<'e>(
     executor: impl SqliteExecutor<'e>,
     tenant: &types::Tenant,
 ) -> eyre::Result<types::Tenant, sqlx::Error> {
-    let id: String = tenant.id.clone().to_string();
     sqlx::query!(
         "
 INSERT INTO tenants (id, name, created_at)
 VALUES (?, ?, ?)
     ",
-        id,
+        tenant.id,
         tenant.name,
         tenant.created_at
     )
@@ -57,9 +62,24 @@ VALUES (?, ?, ?)
     Ok(tenant.clone())
 }
 
+pub async fn update<'e>(
+    executor: impl SqliteExecutor<'e>,
+    tenant: &types::Tenant,
+) -> eyre::Result<types::Tenant, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE tenants SET name = ? WHERE id = ?",
+        tenant.name,
+        tenant.id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(tenant.clone())
+}
+
 pub async fn delete<'e>(
     executor: impl SqliteExecutor<'e>,
-    id: &str,
+    id: &TenantId,
 ) -> eyre::Result<(), sqlx::Error> {
     sqlx::query!("DELETE FROM tenants WHERE id = ?", id)
         .execute(executor)
@@ -67,3 +87,279 @@ pub async fn delete<'e>(
 
     Ok(())
 }
+
+struct InvitationRecord {
+    id: InvitationId,
+    tenant_id: TenantId,
+    email: String,
+    role: String,
+    token_hash: String,
+    invited_by: Option<UserId>,
+    created_at: chrono::NaiveDateTime,
+    expires_at: chrono::NaiveDateTime,
+    accepted_at: Option<chrono::NaiveDateTime>,
+    revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<InvitationRecord> for types::Invitation {
+    type Error = sqlx::Error;
+
+    fn try_from(record: InvitationRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            id: record.id,
+            tenant_id: record.tenant_id,
+            email: profiles::types::Email::try_from(record.email)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            role: memberships::types::Role::try_from(record.role)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            token_hash: record.token_hash,
+            invited_by: record.invited_by,
+            created_at: record.created_at,
+            expires_at: record.expires_at,
+            accepted_at: record.accepted_at,
+            revoked_at: record.revoked_at,
+        })
+    }
+}
+
+/// Finds the invitations of a tenant which can still be accepted.
+pub async fn find_pending_invitations<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+) -> eyre::Result<Vec<types::Invitation>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query_as!(
+        InvitationRecord,
+        "SELECT id AS \"id: InvitationId\", tenant_id AS \"tenant_id: TenantId\", email, role,
+                token_hash, invited_by AS \"invited_by: UserId\", created_at, expires_at,
+                accepted_at, revoked_at
+            FROM invitations
+            WHERE tenant_id = ?
+            AND accepted_at IS NULL
+            AND revoked_at IS NULL
+            AND expires_at > ?
+            ORDER BY created_at, id",
+        tenant_id,
+        now
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Invitation::try_from)
+    .collect()
+}
+
+pub async fn has_pending_invitation<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    email: &profiles::types::Email,
+) -> eyre::Result<bool, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let email = email.to_string();
+    let now = chrono::Utc::now().naive_utc();
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(1)
+            FROM invitations
+            WHERE tenant_id = ?
+            AND email = ?
+            AND accepted_at IS NULL
+            AND revoked_at IS NULL
+            AND expires_at > ?",
+        tenant_id,
+        email,
+        now
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(count > 0)
+}
+
+pub async fn insert_invitation<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    invitation: &types::Invitation,
+) -> eyre::Result<types::Invitation, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let email = invitation.email.to_string();
+    let role = invitation.role.to_string();
+
+    sqlx::query!(
+        "
+INSERT INTO invitations (id, tenant_id, email, role, token_hash, invited_by, created_at, expires_at)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    ",
+        invitation.id,
+        tenant_id,
+        email,
+        role,
+        invitation.token_hash,
+        invitation.invited_by,
+        invitation.created_at,
+        invitation.expires_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(invitation.clone())
+}
+
+/// Revokes a pending invitation, returning it if it existed and could still have been accepted.
+pub async fn revoke_invitation<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &InvitationId,
+) -> eyre::Result<Option<types::Invitation>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let now = chrono::Utc::now().naive_utc();
+    let invitation = match sqlx::query_as!(
+        InvitationRecord,
+        "UPDATE invitations
+            SET revoked_at = ?
+            WHERE id = ?
+            AND tenant_id = ?
+            AND accepted_at IS NULL
+            AND revoked_at IS NULL
+            AND expires_at > ?
+            RETURNING id AS \"id!: InvitationId\", tenant_id AS \"tenant_id!: TenantId\",
+                email AS \"email!\", role AS \"role!\", token_hash AS \"token_hash!\",
+                invited_by AS \"invited_by?: UserId\",
+                created_at AS \"created_at!\", expires_at AS \"expires_at!\",
+                accepted_at AS \"accepted_at?\", revoked_at AS \"revoked_at?\"",
+        now,
+        id,
+        tenant_id,
+        now
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(i) => i,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Invitation::try_from(invitation).map(Some)
+}
+
+/// Marks the pending invitation with a token hash as accepted, returning it if it could still be
+/// accepted; this happens at most once per invitation.
+///
+/// The token is what grants access to the tenant, so unlike the other invitation queries this
+/// isn't scoped by a `TenantScope`.
+pub async fn accept_invitation<'e>(
+    executor: impl SqliteExecutor<'e>,
+    token_hash: &str,
+) -> eyre::Result<Option<types::Invitation>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let invitation = match sqlx::query_as!(
+        InvitationRecord,
+        "UPDATE invitations
+            SET accepted_at = ?
+            WHERE token_hash = ?
+            AND accepted_at IS NULL
+            AND revoked_at IS NULL
+            AND expires_at > ?
+            RETURNING id AS \"id!: InvitationId\", tenant_id AS \"tenant_id!: TenantId\",
+                email AS \"email!\", role AS \"role!\", token_hash AS \"token_hash!\",
+                invited_by AS \"invited_by?: UserId\",
+                created_at AS \"created_at!\", expires_at AS \"expires_at!\",
+                accepted_at AS \"accepted_at?\", revoked_at AS \"revoked_at?\"",
+        now,
+        token_hash,
+        now
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(i) => i,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    types::Invitation::try_from(invitation).map(Some)
+}
+
+/// Accepts the pending invitation of an email to a tenant, for someone signing up with an email
+/// their identity provider verified rather than with the invitation's token.
+pub async fn accept_invitation_for_email<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    email: &profiles::types::Email,
+) -> eyre::Result<Option<types::Invitation>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let email = email.to_string();
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query_as!(
+        InvitationRecord,
+        "UPDATE invitations
+            SET accepted_at = ?
+            WHERE tenant_id = ?
+            AND email = ?
+            AND accepted_at IS NULL
+            AND revoked_at IS NULL
+            AND expires_at > ?
+            RETURNING id AS \"id!: InvitationId\", tenant_id AS \"tenant_id!: TenantId\",
+                email AS \"email!\", role AS \"role!\", token_hash AS \"token_hash!\",
+                invited_by AS \"invited_by?: UserId\",
+                created_at AS \"created_at!\", expires_at AS \"expires_at!\",
+                accepted_at AS \"accepted_at?\", revoked_at AS \"revoked_at?\"",
+        now,
+        tenant_id,
+        email,
+        now
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(types::Invitation::try_from)
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::sqlite::test_pool;
+    use crate::users;
+
+    #[rocket::async_test]
+    async fn invitations_of_another_tenant_cannot_be_read_or_revoked() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let inviter =
+            users::sqlite::insert(&pool, &users::types::User::new("test|inviter")).await?;
+        let tenant_a = insert(&pool, &types::Tenant::new("a")).await?;
+        let tenant_b = insert(&pool, &types::Tenant::new("b")).await?;
+        let scope_a = TenantScope::unchecked(&tenant_a.id);
+        let scope_b = TenantScope::unchecked(&tenant_b.id);
+        let invitation = insert_invitation(
+            &pool,
+            &scope_b,
+            &types::Invitation::new(
+                &tenant_b.id,
+                &profiles::types::Email::try_from("invitee@example.com")?,
+                memberships::types::Role::Member,
+                "token-hash",
+                &inviter.id,
+                chrono::Duration::days(1),
+            ),
+        )
+        .await?;
+
+        assert!(find_pending_invitations(&pool, &scope_a).await?.is_empty());
+        assert!(!has_pending_invitation(&pool, &scope_a, &invitation.email).await?);
+        assert_eq!(
+            revoke_invitation(&pool, &scope_a, &invitation.id).await?,
+            None
+        );
+        assert_eq!(
+            find_pending_invitations(&pool, &scope_b).await?,
+            vec![invitation]
+        );
+
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
-use crate::types::uuid::Uuid;
+use crate::memberships;
 use crate::permissions;
+use crate::profiles;
+use crate::types::id::define_id;
+use crate::users::types::UserId;
 
 use serde::{Deserialize, Serialize};
 
+define_id!(TenantId, "tenant");
+define_id!(InvitationId, "invitation");
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Tenant {
-    pub id: Uuid,
+    pub id: TenantId,
     pub name: String,
     pub created_at: chrono::NaiveDateTime,
 }
@@ -13,7 +19,7 @@ pub struct Tenant {
 impl Tenant {
     pub fn new(name: &str) -> Self {
         Self {
-            id: Uuid::new(),
+            id: TenantId::new(),
             name: name.to_string(),
             created_at: chrono::Utc::now().naive_utc(),
         }
@@ -23,3 +29,47 @@ impl Tenant {
         permissions::types::Target("tenant".to_string())
     }
 }
+
+/// An invitation for someone to join a tenant, accepted with a single-use token.
+///
+/// Only a hash of the token is stored; the token itself is handed to the inviter once so that it
+/// can be delivered to the invitee.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Invitation {
+    pub id: InvitationId,
+    pub tenant_id: TenantId,
+    pub email: profiles::types::Email,
+    pub role: memberships::types::Role,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub invited_by: Option<UserId>,
+    pub created_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    pub accepted_at: Option<chrono::NaiveDateTime>,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Invitation {
+    pub fn new(
+        tenant_id: &TenantId,
+        email: &profiles::types::Email,
+        role: memberships::types::Role,
+        token_hash: &str,
+        invited_by: &UserId,
+        ttl: chrono::Duration,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: InvitationId::new(),
+            tenant_id: *tenant_id,
+            email: email.clone(),
+            role,
+            token_hash: token_hash.to_string(),
+            invited_by: Some(*invited_by),
+            created_at: now,
+            expires_at: now + ttl,
+            accepted_at: None,
+            revoked_at: None,
+        }
+    }
+}
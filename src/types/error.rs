@@ -0,0 +1,128 @@
+use crate::fairings::REQUEST_ID_HEADER;
+use crate::types::{
+    id::InvalidId,
+    validation::{FieldValidationError, ValidationErrors},
+};
+
+use rocket::{
+    http::{ContentType, Status},
+    response::{self, Responder},
+    Request, Response,
+};
+use serde::Serialize;
+use std::fmt;
+use std::io::Cursor;
+
+/// An error returned from a route, rendered as an RFC 7807 `application/problem+json` body.
+///
+/// Each domain's routes convert their service errors into this with `From`, picking the status
+/// for every variant. Client errors carry the error's message as their detail, while internal
+/// errors are logged instead of being described to the client.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: Status,
+    pub detail: Option<String>,
+    /// Every invalid field of the request, for validation errors.
+    pub errors: Vec<FieldValidationError>,
+}
+
+impl ApiError {
+    pub const fn new(status: Status) -> Self {
+        Self {
+            status,
+            detail: None,
+            errors: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_detail(mut self, detail: impl fmt::Display) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// A 500 for an unexpected error, logged with what the request failed to do.
+    pub fn internal(action: &str, err: &impl fmt::Debug) -> Self {
+        tracing::error!("failed to {}: {:?}", action, err);
+        Self::new(Status::InternalServerError)
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        Self::new(status)
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut err = Self::new(Status::UnprocessableEntity).with_detail(&errors);
+        err.errors = errors.0;
+        err
+    }
+}
+
+impl From<InvalidId> for ApiError {
+    fn from(err: InvalidId) -> Self {
+        Self::new(Status::BadRequest).with_detail(err)
+    }
+}
+
+impl From<FieldValidationError> for ApiError {
+    fn from(err: FieldValidationError) -> Self {
+        ValidationErrors::from(err).into()
+    }
+}
+
+/// The body of an error response, with the id of the request it failed so that clients can
+/// correlate it with the server's logs.
+#[derive(Debug, Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    instance: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldValidationError>,
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let problem = Problem {
+            kind: "about:blank",
+            title: self.status.reason_lossy(),
+            status: self.status.code,
+            detail: self.detail,
+            instance: request.uri().path().to_string(),
+            request_id: request.headers().get_one(REQUEST_ID_HEADER),
+            errors: self.errors,
+        };
+        let body = serde_json::to_string(&problem).map_err(|e| {
+            tracing::error!("failed to serialize problem: {:?}", e);
+            Status::InternalServerError
+        })?;
+
+        Response::build()
+            .status(self.status)
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// Renders errors raised outside of routes, such as by failing guards or unmatched routes, as
+/// problems too.
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn catchers() -> Vec<rocket::Catcher> {
+    catchers![default_catcher]
+}
+
+#[catch(default)]
+const fn default_catcher(status: Status, _request: &Request<'_>) -> ApiError {
+    ApiError::new(status)
+}
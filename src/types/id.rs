@@ -0,0 +1,130 @@
+use thiserror::Error;
+
+/// An id which is not a UUID, naming the kind of entity it was meant to identify.
+#[derive(Debug, Clone, Error)]
+#[error("invalid {kind} id `{value}`")]
+pub struct InvalidId {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+/// Declares the id of a kind of entity as its own newtype over `Uuid`, so that passing the id of
+/// one kind of entity where another's is expected fails to compile.
+///
+/// Ids parse from and display as hyphenated UUIDs, serialize as them, and bind to and decode from
+/// sqlite columns with sqlx. They are also path parameters, which routes take as
+/// `Result<Id, InvalidId>` so that a malformed id is rejected with a 400 instead of forwarded.
+///
+/// Ids are generated, ordered and stored like the `Uuid` they wrap.
+macro_rules! define_id {
+    ($(#[$meta:meta])* $name:ident, $kind:literal) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            serde::Serialize,
+            serde::Deserialize,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+        )]
+        #[serde(transparent)]
+        pub struct $name($crate::types::uuid::Uuid);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self($crate::types::uuid::Uuid::new())
+            }
+
+            /// When the id was generated, for v7 ids.
+            pub fn timestamp(&self) -> Option<chrono::NaiveDateTime> {
+                self.0.timestamp()
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = $crate::types::id::InvalidId;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Self).map_err(|_| $crate::types::id::InvalidId {
+                    kind: $kind,
+                    value: s.to_string(),
+                })
+            }
+        }
+
+        impl std::convert::TryFrom<&str> for $name {
+            type Error = $crate::types::id::InvalidId;
+
+            fn try_from(s: &str) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+
+        impl std::convert::TryFrom<String> for $name {
+            type Error = $crate::types::id::InvalidId;
+
+            fn try_from(s: String) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> Self {
+                id.to_string()
+            }
+        }
+
+        impl sqlx::Type<sqlx::Sqlite> for $name {
+            fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+                <$crate::types::uuid::Uuid as sqlx::Type<sqlx::Sqlite>>::type_info()
+            }
+
+            fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+                <$crate::types::uuid::Uuid as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+            }
+        }
+
+        impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for $name {
+            fn encode_by_ref(
+                &self,
+                args: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+            ) -> sqlx::encode::IsNull {
+                self.0.encode_by_ref(args)
+            }
+        }
+
+        impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for $name {
+            fn decode(
+                value: sqlx::sqlite::SqliteValueRef<'r>,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                <$crate::types::uuid::Uuid as sqlx::Decode<sqlx::Sqlite>>::decode(value).map(Self)
+            }
+        }
+
+        impl<'a> rocket::request::FromParam<'a> for $name {
+            type Error = $crate::types::id::InvalidId;
+
+            fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+                param.parse()
+            }
+        }
+    };
+}
+
+pub(crate) use define_id;
@@ -1,3 +1,6 @@
+pub mod error;
+pub mod id;
+pub mod pagination;
 pub mod sqlite;
 pub mod uuid;
 pub mod validation;
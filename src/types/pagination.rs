@@ -0,0 +1,66 @@
+use crate::types::validation::FieldValidationError;
+
+use color_eyre::eyre;
+use serde::Serialize;
+
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 100;
+
+/// Which page of a list to return, with pages numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl Pagination {
+    pub fn new(
+        page: Option<u32>,
+        per_page: Option<u32>,
+    ) -> eyre::Result<Self, FieldValidationError> {
+        let page = page.unwrap_or(1);
+        if page == 0 {
+            return Err(FieldValidationError {
+                field: "page".to_string(),
+                message: "pages are numbered from 1".to_string(),
+            });
+        }
+        let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(FieldValidationError {
+                field: "per_page".to_string(),
+                message: format!("per_page must be between 1 and {MAX_PER_PAGE}"),
+            });
+        }
+
+        Ok(Self { page, per_page })
+    }
+
+    pub fn limit(self) -> i64 {
+        i64::from(self.per_page)
+    }
+
+    pub fn offset(self) -> i64 {
+        i64::from(self.page - 1) * i64::from(self.per_page)
+    }
+}
+
+/// A page of a list, along with how many items the whole list holds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: i64,
+}
+
+impl<T> Paginated<T> {
+    pub const fn new(items: Vec<T>, pagination: Pagination, total: i64) -> Self {
+        Self {
+            items,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total,
+        }
+    }
+}
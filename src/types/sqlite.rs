@@ -1,5 +1,8 @@
-use std::borrow::{Borrow, Cow};
+use crate::types::uuid::{IdStorage, Uuid};
 
+use color_eyre::eyre;
+use sqlx::SqlitePool;
+use std::borrow::{Borrow, Cow};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -14,8 +17,130 @@ pub enum ErrorCode {
 impl From<Cow<'_, str>> for ErrorCode {
     fn from(code: Cow<'_, str>) -> Self {
         match code.borrow() {
-            "2067" => Self::UniqueConstraintViolation,
+            // SQLITE_CONSTRAINT_UNIQUE and SQLITE_CONSTRAINT_PRIMARYKEY
+            "2067" | "1555" => Self::UniqueConstraintViolation,
             c => Self::Unknown(c.to_string()),
         }
     }
 }
+
+/// Every column holding the id of an entity, as `(table, column)`.
+///
+/// Columns holding the id of a resource, such as `permissions.resource_id`, aren't among them as
+/// they may also hold the `*` wildcard; they always hold text.
+const ID_COLUMNS: &[(&str, &str)] = &[
+    ("users", "id"),
+    ("profiles", "user_id"),
+    ("credentials", "user_id"),
+    ("sessions", "id"),
+    ("sessions", "user_id"),
+    ("refresh_tokens", "id"),
+    ("refresh_tokens", "family_id"),
+    ("refresh_tokens", "user_id"),
+    ("refresh_tokens", "tenant_id"),
+    ("tenants", "id"),
+    ("identity_authorizations", "tenant_id"),
+    ("memberships", "user_id"),
+    ("memberships", "tenant_id"),
+    ("invitations", "id"),
+    ("invitations", "tenant_id"),
+    ("invitations", "invited_by"),
+    ("permissions", "user_id"),
+    ("permissions", "granted_by"),
+    ("permission_denials", "user_id"),
+    ("permission_denials", "denied_by"),
+    ("roles", "id"),
+    ("roles", "tenant_id"),
+    ("role_permissions", "role_id"),
+    ("role_assignments", "role_id"),
+    ("role_assignments", "user_id"),
+    ("webhooks", "id"),
+    ("webhooks", "tenant_id"),
+    ("webhooks", "created_by"),
+    ("webhook_deliveries", "id"),
+    ("webhook_deliveries", "webhook_id"),
+    ("webhook_deliveries", "event_id"),
+];
+
+/// Converts every stored id which isn't in `storage` to it, returning how many rows were changed.
+///
+/// This is how a database moves between storing ids as text and as blobs: once ids are configured
+/// to be stored as blobs, the ids already stored as text are converted on startup, and the other
+/// way around. Ids already in `storage` are left alone, so this only does work after a switch or
+/// when ids have been inserted in the other storage, such as by `just create-tenant`.
+pub async fn convert_id_storage(
+    pool: &SqlitePool,
+    storage: IdStorage,
+) -> eyre::Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // references are dangling until the ids on both sides of them have been converted
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut tx)
+        .await?;
+
+    let mut converted = 0;
+    for (table, column) in ID_COLUMNS {
+        let update = format!("UPDATE {table} SET {column} = ? WHERE {column} = ?");
+        match storage {
+            IdStorage::Blob => {
+                let ids: Vec<String> = sqlx::query_scalar(&format!(
+                    "SELECT DISTINCT {column} FROM {table} WHERE typeof({column}) = 'text'"
+                ))
+                .fetch_all(&mut tx)
+                .await?;
+                for id in ids {
+                    let uuid: Uuid = id.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+                    converted += sqlx::query(&update)
+                        .bind(uuid.as_bytes().to_vec())
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await?
+                        .rows_affected();
+                }
+            }
+            IdStorage::Text => {
+                let ids: Vec<Vec<u8>> = sqlx::query_scalar(&format!(
+                    "SELECT DISTINCT {column} FROM {table} WHERE typeof({column}) = 'blob'"
+                ))
+                .fetch_all(&mut tx)
+                .await?;
+                for id in ids {
+                    let uuid =
+                        Uuid::from_slice(&id).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+                    converted += sqlx::query(&update)
+                        .bind(uuid.to_string())
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await?
+                        .rows_affected();
+                }
+            }
+        }
+    }
+    tx.commit().await?;
+
+    Ok(converted)
+}
+
+/// Connects to a new in-memory database with every migration applied, for tests.
+///
+/// Every connection to `sqlite::memory:` opens a database of its own, so the pool holds a single
+/// connection which is never closed.
+///
+/// Ids are configured from the environment and the ids inserted by migrations converted like on
+/// startup, so that `ID_STORAGE=blob cargo test` runs the tests against ids stored as blobs.
+#[cfg(test)]
+pub async fn test_pool() -> eyre::Result<SqlitePool> {
+    let ids = crate::types::uuid::IdConfig::from_env();
+    crate::types::uuid::configure(ids);
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await?;
+    sqlx::migrate!().run(&pool).await?;
+    convert_id_storage(&pool, ids.storage).await?;
+
+    Ok(pool)
+}
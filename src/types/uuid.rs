@@ -1,15 +1,149 @@
 use color_eyre::eyre;
+use rocket::request::FromParam;
 use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{Sqlite, SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Type, ValueRef,
+};
 use std::convert::TryFrom;
+use std::env;
 use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
 use uuid::Uuid as CrateUuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Uuid(String);
+/// How new ids are generated and how ids are stored, set once on startup with `configure`.
+///
+/// Read from `ID_VERSION` (`v4` or `v7`) and `ID_STORAGE` (`text` or `blob`), defaulting to
+/// random ids stored as text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdConfig {
+    pub version: IdVersion,
+    pub storage: IdStorage,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdVersion {
+    /// Entirely random.
+    #[default]
+    V4,
+    /// Prefixed with the millisecond they were generated at, so that newer ids sort after older
+    /// ones and are inserted at the end of an index instead of at random.
+    V7,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdStorage {
+    /// The hyphenated form, as ids are serialized.
+    #[default]
+    Text,
+    /// The 16 bytes of the id.
+    Blob,
+}
+
+impl IdConfig {
+    pub fn from_env() -> Self {
+        let version = match env::var("ID_VERSION").as_deref() {
+            Ok("v4") | Err(_) => IdVersion::V4,
+            Ok("v7") => IdVersion::V7,
+            Ok(version) => panic!("unsupported ID_VERSION `{version}`, expected v4 or v7"),
+        };
+        let storage = match env::var("ID_STORAGE").as_deref() {
+            Ok("text") | Err(_) => IdStorage::Text,
+            Ok("blob") => IdStorage::Blob,
+            Ok(storage) => panic!("unsupported ID_STORAGE `{storage}`, expected text or blob"),
+        };
+
+        Self { version, storage }
+    }
+}
+
+static CONFIG: OnceLock<IdConfig> = OnceLock::new();
+
+/// Sets how ids are generated and stored for the rest of the process, returning `false` when
+/// they were already configured.
+///
+/// This has to happen before any id is generated or stored, as every id of an entity is expected
+/// to be stored in the same way; `types::sqlite::convert_id_storage` converts existing ones.
+pub fn configure(config: IdConfig) -> bool {
+    CONFIG.set(config).is_ok()
+}
+
+pub fn config() -> IdConfig {
+    CONFIG.get().copied().unwrap_or_default()
+}
+
+/// A 16-byte UUID, serialized as its hyphenated form and stored as configured by `IdConfig`.
+///
+/// Ids of entities wrap this in their own type with `define_id!`, so that ids of different kinds
+/// of entities cannot be mixed up; this is left for ids which don't belong to an entity.
+///
+/// Ids order by their bytes, which for v7 ids is the order they were generated in, in both their
+/// text and binary form. Cursors over lists ordered by id can therefore use the id alone.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Uuid(CrateUuid);
 
 impl Uuid {
+    /// Generates an id of the configured `IdVersion`.
     pub fn new() -> Self {
-        Self(CrateUuid::new_v4().to_string())
+        match config().version {
+            IdVersion::V4 => Self::new_v4(),
+            IdVersion::V7 => Self::now_v7(),
+        }
+    }
+
+    pub fn new_v4() -> Self {
+        Self(CrateUuid::new_v4())
+    }
+
+    /// Generates a v7 id for the current time.
+    ///
+    /// Ids generated within the same millisecond are ordered at random.
+    pub fn now_v7() -> Self {
+        Self::new_v7(chrono::Utc::now().naive_utc())
+    }
+
+    /// Generates a v7 id for `timestamp`, which is truncated to the millisecond and must not be
+    /// before the unix epoch.
+    pub fn new_v7(timestamp: chrono::NaiveDateTime) -> Self {
+        let millis = u64::try_from(timestamp.timestamp_millis()).unwrap_or_default();
+        // a random v4 id already has the variant bits of a v7 one, leaving the timestamp and
+        // version to be written over it
+        let mut bytes = CrateUuid::new_v4().into_bytes();
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+
+        Self(CrateUuid::from_bytes(bytes))
+    }
+
+    /// When a v7 id was generated, to the millisecond; other versions don't embed a time.
+    pub fn timestamp(&self) -> Option<chrono::NaiveDateTime> {
+        if self.0.get_version_num() != 7 {
+            return None;
+        }
+        let mut millis = [0; 8];
+        millis[2..].copy_from_slice(&self.0.as_bytes()[..6]);
+        let millis = i64::from_be_bytes(millis);
+
+        chrono::NaiveDateTime::from_timestamp_opt(
+            millis.div_euclid(1000),
+            u32::try_from(millis.rem_euclid(1000) * 1_000_000).ok()?,
+        )
+    }
+
+    pub const fn from_u128(value: u128) -> Self {
+        Self(CrateUuid::from_u128(value))
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> eyre::Result<Self, uuid::Error> {
+        CrateUuid::from_slice(bytes).map(Self)
     }
 }
 
@@ -21,7 +155,15 @@ impl Default for Uuid {
 
 impl fmt::Display for Uuid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> eyre::Result<Self, Self::Err> {
+        CrateUuid::try_parse(s).map(Self)
     }
 }
 
@@ -29,9 +171,7 @@ impl TryFrom<&str> for Uuid {
     type Error = uuid::Error;
 
     fn try_from(s: &str) -> eyre::Result<Self, Self::Error> {
-        let parsed = CrateUuid::try_parse(s)?;
-
-        Ok(Self(parsed.to_string()))
+        s.parse()
     }
 }
 
@@ -39,14 +179,52 @@ impl TryFrom<String> for Uuid {
     type Error = uuid::Error;
 
     fn try_from(s: String) -> eyre::Result<Self, Self::Error> {
-        let parsed = CrateUuid::try_parse(&s)?;
-
-        Ok(Self(parsed.to_string()))
+        s.parse()
     }
 }
 
 impl From<Uuid> for String {
     fn from(u: Uuid) -> Self {
-        u.0
+        u.to_string()
+    }
+}
+
+impl Type<Sqlite> for Uuid {
+    fn type_info() -> SqliteTypeInfo {
+        <str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        // blobs are compatible with text too
+        <[u8] as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+/// Ids are bound in the configured `IdStorage`.
+impl<'q> Encode<'q, Sqlite> for Uuid {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match config().storage {
+            IdStorage::Text => Encode::<Sqlite>::encode(self.to_string(), args),
+            IdStorage::Blob => Encode::<Sqlite>::encode(self.as_bytes().to_vec(), args),
+        }
+    }
+}
+
+/// Ids decode from either storage, so that they can be read while being converted between them.
+impl<'r> Decode<'r, Sqlite> for Uuid {
+    fn decode(value: SqliteValueRef<'r>) -> eyre::Result<Self, BoxDynError> {
+        if <str as Type<Sqlite>>::compatible(&value.type_info()) {
+            Ok(<&str as Decode<Sqlite>>::decode(value)?.parse()?)
+        } else {
+            Ok(Self::from_slice(<&[u8] as Decode<Sqlite>>::decode(value)?)?)
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for Uuid {
+    type Error = uuid::Error;
+
+    fn from_param(param: &'a str) -> eyre::Result<Self, Self::Error> {
+        param.parse()
     }
 }
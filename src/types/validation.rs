@@ -1,8 +1,114 @@
+use serde::Serialize;
+use std::fmt;
 use thiserror::Error;
 
-#[derive(Debug, Clone, Error)]
+#[derive(Debug, Clone, Error, Serialize)]
 #[error("failed to validate {field} with error: {message}")]
 pub struct FieldValidationError {
     pub field: String,
     pub message: String,
 }
+
+/// Every invalid field of a request, so that clients can fix them all at once.
+#[derive(Debug, Clone, Default, Error, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(pub Vec<FieldValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self
+            .0
+            .iter()
+            .map(|err| err.field.as_str())
+            .collect::<Vec<_>>();
+        write!(f, "failed to validate {}", fields.join(", "))
+    }
+}
+
+impl From<FieldValidationError> for ValidationErrors {
+    fn from(err: FieldValidationError) -> Self {
+        Self(vec![err])
+    }
+}
+
+/// Collects the errors of every rule checked against a request instead of stopping at the first.
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldValidationError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `message` as the error of `field` unless `valid`.
+    pub fn check(&mut self, field: &str, valid: bool, message: impl fmt::Display) -> &mut Self {
+        if !valid {
+            self.errors.push(FieldValidationError {
+                field: field.to_string(),
+                message: message.to_string(),
+            });
+        }
+        self
+    }
+
+    /// Records the error of parsing `field`, such as with `Email::new` or `UserId::try_from`,
+    /// returning the parsed value when it is valid.
+    pub fn parse<T, E: fmt::Display>(&mut self, field: &str, parsed: Result<T, E>) -> Option<T> {
+        match parsed {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.check(field, false, err);
+                None
+            }
+        }
+    }
+
+    /// Requires `value` to be no shorter than `min` characters.
+    pub fn min_length(&mut self, field: &str, value: &str, min: usize) -> &mut Self {
+        self.check(
+            field,
+            value.chars().count() >= min,
+            format!("{field} must be at least {min} characters long"),
+        )
+    }
+
+    /// Requires `value` to be no longer than `max` characters.
+    pub fn max_length(&mut self, field: &str, value: &str, max: usize) -> &mut Self {
+        self.check(
+            field,
+            value.chars().count() <= max,
+            format!("{field} must be at most {max} characters long"),
+        )
+    }
+
+    /// Requires `value` to hold more than whitespace.
+    pub fn not_blank(&mut self, field: &str, value: &str) -> &mut Self {
+        self.check(
+            field,
+            !value.trim().is_empty(),
+            format!("{field} cannot be blank"),
+        )
+    }
+
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(self.errors))
+        }
+    }
+}
+
+/// A request payload declaring the rules its fields must satisfy.
+pub trait Validate {
+    fn rules(&self, validator: &mut Validator);
+
+    /// Checks every rule, failing with all the fields which broke one.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut validator = Validator::new();
+        self.rules(&mut validator);
+        validator.finish()
+    }
+}
@@ -1,13 +1,16 @@
 use crate::events;
-use crate::users::types::User;
+use crate::users::types::{User, UserId};
 
 use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum UserEvent {
     Created(User),
-    Deleted(String),
+    Deleted(UserId),
 }
 
 pub struct UsersEventHandler;
@@ -18,19 +21,23 @@ impl UsersEventHandler {
     }
 }
 
+#[rocket::async_trait]
 impl events::EventHandler for UsersEventHandler {
-    fn handle(
-        &self,
-        rx: Arc<tokio::sync::Mutex<bus::BusReader<events::AppEvent>>>,
-    ) -> eyre::Result<()> {
-        tokio::spawn(async move {
-            let mut rx = rx.lock().await;
-            loop {
-                if let Ok(event) = rx.recv() {
-                    tracing::debug!("recv: {:?}", event);
-                }
-            }
-        });
+    fn name(&self) -> &'static str {
+        "users"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["user.*"]
+    }
+
+    async fn handle(&self, _pool: &SqlitePool, envelope: &events::Envelope) -> eyre::Result<()> {
+        tracing::debug!(
+            "handling {} {}: {:?}",
+            envelope.kind,
+            envelope.id,
+            envelope.event
+        );
 
         Ok(())
     }
@@ -1,16 +1,20 @@
 use std::convert::TryInto;
 
+use crate::memberships;
 use crate::permissions;
 use crate::profiles;
-use crate::types::{uuid::Uuid, validation::FieldValidationError};
+use crate::tenants::{self, types::TenantId};
+use crate::types::validation::{FieldValidationError, Validate, ValidationErrors, Validator};
 use crate::users::domain::events;
-use crate::users::types;
-use crate::{events::AppEvent, users};
+use crate::users::types::{self, UserId};
+use crate::{
+    events::{outbox, AppEvent, Publisher},
+    users,
+};
 
-use bus::Bus;
 use color_eyre::eyre;
 use serde::Deserialize;
-use sqlx::SqlitePool;
+use sqlx::{Sqlite, SqlitePool, Transaction};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -21,11 +25,33 @@ pub enum FindUserError {
     #[error("id `{0}` does not exist")]
     NotFound(String),
 
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(permissions::HasPermissionError),
+
     #[error("unexpected error from sqlx")]
     Sqlx(#[from] sqlx::Error),
 }
 
-pub async fn find_user(pool: &SqlitePool, id: &str) -> eyre::Result<types::User, FindUserError> {
+pub async fn find_user(
+    pool: &SqlitePool,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: &UserId,
+) -> eyre::Result<types::User, FindUserError> {
+    let can = permissions::has_permission_to(
+        pool,
+        attributes,
+        requesting_user_id,
+        "read-user",
+        &id.to_string(),
+        &types::User::kind().to_string(),
+    )
+    .await
+    .map_err(FindUserError::AccessCheckFailed)?;
+    if !can {
+        return Err(FindUserError::PermissionDenied);
+    }
+
     let user = match users::sqlite::find_one(pool, id).await {
         Ok(user) => match user {
             Some(user) => user,
@@ -37,10 +63,20 @@ pub async fn find_user(pool: &SqlitePool, id: &str) -> eyre::Result<types::User,
     Ok(user)
 }
 
+/// Finds the user linked to an identity, as identified by `Identity::auth_id`.
+pub async fn find_user_by_auth_id(
+    pool: &SqlitePool,
+    auth_id: &str,
+) -> eyre::Result<Option<types::User>, FindUserError> {
+    users::sqlite::find_by_auth_id(pool, auth_id)
+        .await
+        .map_err(FindUserError::Sqlx)
+}
+
 #[derive(Error, Debug)]
 pub enum CreateUserError {
     #[error("invalid input")]
-    InvalidInput(FieldValidationError),
+    InvalidInput(ValidationErrors),
 
     #[error("unexpected error from sqlx")]
     Sqlx(#[from] sqlx::Error),
@@ -48,35 +84,66 @@ pub enum CreateUserError {
 
 #[derive(Debug, Deserialize)]
 pub struct CreateUserRequest {
+    pub auth_id: String,
     pub email: String,
-    pub tenant_id: Uuid,
+    pub tenant_id: TenantId,
+    /// Role in the tenant; defaults to owner for a tenant's first user and member otherwise.
+    #[serde(default)]
+    pub role: Option<memberships::types::Role>,
 }
 
-pub async fn create_user(
-    pool: &SqlitePool,
-    bus: &tokio::sync::Mutex<Bus<AppEvent>>,
+impl Validate for CreateUserRequest {
+    fn rules(&self, validator: &mut Validator) {
+        validator.not_blank("auth_id", &self.auth_id);
+        validator.parse("email", profiles::types::Email::new(&self.email));
+        validator.max_length("email", &self.email, profiles::types::MAX_EMAIL_LENGTH);
+    }
+}
+
+/// Inserts a user, their profile, their default grants and their membership of the requested
+/// tenant within an existing transaction.
+///
+/// Callers are responsible for writing `UserEvent::Created` to the outbox and committing the
+/// transaction, which lets other modules (such as `auth`) store their own rows atomically
+/// alongside the user.
+pub async fn insert_user(
+    tx: &mut Transaction<'_, Sqlite>,
     payload: CreateUserRequest,
 ) -> eyre::Result<types::User, CreateUserError> {
-    let mut tx = pool.begin().await.map_err(CreateUserError::Sqlx)?;
+    payload.validate().map_err(CreateUserError::InvalidInput)?;
+    let tenant_id = payload.tenant_id;
+    if tenants::sqlite::find_one(&mut *tx, &tenant_id)
+        .await
+        .map_err(CreateUserError::Sqlx)?
+        .is_none()
+    {
+        return Err(CreateUserError::InvalidInput(
+            FieldValidationError {
+                field: "tenant_id".to_string(),
+                message: format!("tenant `{tenant_id}` does not exist"),
+            }
+            .into(),
+        ));
+    }
 
-    // mock the auth id;
-    // in production this would be received from an external oauth service
-    let mock_auth_id = uuid::Uuid::new_v4().to_string();
-    let user = types::User::new(&mock_auth_id);
+    let user = types::User::new(&payload.auth_id);
 
-    if let Err(err) = users::sqlite::insert(&mut tx, &user).await {
+    if let Err(err) = users::sqlite::insert(&mut *tx, &user).await {
         return Err(CreateUserError::Sqlx(err));
     }
 
     profiles::sqlite::insert(
-        &mut tx,
+        &mut *tx,
         &profiles::CreateProfile {
-            user_id: user.id.clone(),
+            user_id: user.id,
             email: payload.email.try_into().map_err(|e: eyre::Report| {
-                CreateUserError::InvalidInput(FieldValidationError {
-                    field: "email".to_string(),
-                    message: e.to_string(),
-                })
+                CreateUserError::InvalidInput(
+                    FieldValidationError {
+                        field: "email".to_string(),
+                        message: e.to_string(),
+                    }
+                    .into(),
+                )
             })?,
         },
     )
@@ -85,50 +152,77 @@ pub async fn create_user(
 
     let grants = vec![
         permissions::types::Permission {
-            user_id: user.id.clone(),
-            action: permissions::types::Actionable::Read(
-                types::User::kind()
-            ),
+            user_id: user.id,
+            action: permissions::types::Actionable::Read(types::User::kind()),
+            resource: permissions::types::Resource::User(user.id.to_string()),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
+        },
+        permissions::types::Permission {
+            user_id: user.id,
+            action: permissions::types::Actionable::Write(types::User::kind()),
             resource: permissions::types::Resource::User(user.id.to_string()),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
         },
         permissions::types::Permission {
-            user_id: user.id.clone(),
-            action: permissions::types::Actionable::Write(
-                types::User::kind()
-            ),
+            user_id: user.id,
+            action: permissions::types::Actionable::Read(permissions::types::Permission::kind()),
             resource: permissions::types::Resource::User(user.id.to_string()),
+            condition: None,
+            expires_at: None,
+            delegable: true,
+            granted_by: None,
         },
     ];
     for grant in grants {
-        permissions::sqlite::insert(&mut tx, &grant)
+        permissions::sqlite::insert(&mut *tx, &grant)
             .await
             .map_err(CreateUserError::Sqlx)?;
     }
 
-    tx.commit().await.map_err(CreateUserError::Sqlx)?;
-
-    bus.lock()
+    // the first user to join a tenant without an owner, such as one created with
+    // `just create-tenant`, becomes its owner
+    let scope = tenants::TenantScope::unchecked(&payload.tenant_id);
+    let owners =
+        memberships::sqlite::count_by_role(&mut *tx, &scope, memberships::types::Role::Owner)
+            .await
+            .map_err(CreateUserError::Sqlx)?;
+    let role = payload.role.unwrap_or(if owners == 0 {
+        memberships::types::Role::Owner
+    } else {
+        memberships::types::Role::Member
+    });
+    memberships::insert_membership(tx, &scope, &user.id, role)
         .await
-        .broadcast(AppEvent::User(events::UserEvent::Created(user.clone())));
+        .map_err(CreateUserError::Sqlx)?;
 
     Ok(user)
 }
 
 pub async fn delete_user(
     pool: &SqlitePool,
-    bus: &tokio::sync::Mutex<Bus<AppEvent>>,
-    id: &str,
+    publisher: &Publisher,
+    attributes: &permissions::Attributes,
+    requesting_user_id: &UserId,
+    id: &UserId,
 ) -> eyre::Result<(), FindUserError> {
-    match permissions::has_permission_to(pool, id, "write-user", id, &types::User::kind().to_string()).await {
-        Ok(can) => {
-            if !can {
-                return Err(FindUserError::PermissionDenied);
-            }
-        }
-        Err(err) => match err {
-            permissions::HasPermissionError::Sqlx(err) => return Err(FindUserError::Sqlx(err)),
-            _ => todo!(),
-        },
+    let can = permissions::has_permission_to(
+        pool,
+        attributes,
+        requesting_user_id,
+        "write-user",
+        &id.to_string(),
+        &types::User::kind().to_string(),
+    )
+    .await
+    .map_err(FindUserError::AccessCheckFailed)?;
+    if !can {
+        return Err(FindUserError::PermissionDenied);
     }
 
     let mut tx = pool.begin().await.map_err(FindUserError::Sqlx)?;
@@ -136,11 +230,17 @@ pub async fn delete_user(
         .await
         .map_err(FindUserError::Sqlx)?;
 
+    outbox::insert(
+        &mut tx,
+        &publisher
+            .envelope(AppEvent::User(events::UserEvent::Deleted(*id)))
+            .with_actor(requesting_user_id),
+    )
+    .await
+    .map_err(FindUserError::Sqlx)?;
     tx.commit().await.map_err(FindUserError::Sqlx)?;
 
-    bus.lock()
-        .await
-        .broadcast(AppEvent::User(events::UserEvent::Deleted(id.to_string())));
+    publisher.notify();
 
     Ok(())
 }
@@ -6,4 +6,4 @@ pub mod types;
 pub use domain::events;
 pub use domain::service::*;
 
-pub use routes::routes;
\ No newline at end of file
+pub use routes::routes;
@@ -1,9 +1,14 @@
 use crate::{
-    events::AppEvent,
-    users::{domain::service, types},
+    auth::AuthenticatedUser,
+    events::Publisher,
+    permissions,
+    types::{error::ApiError, id::InvalidId},
+    users::{
+        domain::service,
+        types::{self, UserId},
+    },
 };
 
-use bus::Bus;
 use color_eyre::eyre;
 use rocket::{http::Status, route::Route, serde::json::Json};
 use sqlx::SqlitePool;
@@ -16,29 +21,53 @@ pub fn routes() -> Vec<Route> {
 #[get("/<id>")]
 async fn find_user_route(
     pool: &rocket::State<SqlitePool>,
-    id: &str,
-) -> eyre::Result<Json<types::User>, Status> {
-    match service::find_user(pool.inner(), id).await {
-        Ok(user) => Ok(Json(user)),
-        Err(err) => match err {
-            service::FindUserError::NotFound(_) => Err(Status::NotFound),
-            _ => todo!(),
-        },
-    }
+    id: Result<UserId, InvalidId>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Json<types::User>, ApiError> {
+    Ok(Json(
+        service::find_user(pool.inner(), &attributes, &requesting_user.user_id, &id?).await?,
+    ))
 }
 
 #[delete("/<id>")]
 async fn delete_user_route(
     pool: &rocket::State<SqlitePool>,
-    bus: &rocket::State<tokio::sync::Mutex<Bus<AppEvent>>>,
-    id: &str,
-) -> Status {
-    if service::delete_user(pool.inner(), bus.inner(), id)
-        .await
-        .is_err()
-    {
-        todo!();
+    publisher: Publisher,
+    id: Result<UserId, InvalidId>,
+    attributes: permissions::Attributes,
+    requesting_user: AuthenticatedUser,
+) -> eyre::Result<Status, ApiError> {
+    service::delete_user(
+        pool.inner(),
+        &publisher,
+        &attributes,
+        &requesting_user.user_id,
+        &id?,
+    )
+    .await?;
+
+    Ok(Status::Ok)
+}
+
+impl From<service::FindUserError> for ApiError {
+    fn from(err: service::FindUserError) -> Self {
+        match err {
+            service::FindUserError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindUserError::NotFound(_) => Self::new(Status::NotFound).with_detail(err),
+            service::FindUserError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindUserError::Sqlx(_) => Self::internal("find user", &err),
+        }
     }
+}
 
-    Status::Ok
+impl From<service::CreateUserError> for ApiError {
+    fn from(err: service::CreateUserError) -> Self {
+        match err {
+            service::CreateUserError::InvalidInput(err) => err.into(),
+            err @ service::CreateUserError::Sqlx(_) => Self::internal("create user", &err),
+        }
+    }
 }
@@ -1,23 +1,15 @@
-use crate::types::uuid::Uuid;
-use crate::users::types;
+use crate::users::types::{self, UserId};
 
 use color_eyre::eyre;
 use sqlx::SqliteExecutor;
-use std::convert::TryFrom;
-
-struct UserRecord {
-    id: String,
-    auth_id: String,
-    created_at: chrono::NaiveDateTime,
-}
 
 pub async fn find_one<'e>(
     executor: impl SqliteExecutor<'e>,
-    id: &str,
+    id: &UserId,
 ) -> eyre::Result<Option<types::User>, sqlx::Error> {
     let user = match sqlx::query_as!(
-        UserRecord,
-        "SELECT id, auth_id, created_at FROM users WHERE id = ?",
+        types::User,
+        "SELECT id AS \"id: UserId\", auth_id, created_at FROM users WHERE id = ?",
         id
     )
     .fetch_one(executor)
@@ -30,24 +22,41 @@ pub async fn find_one<'e>(
         },
     };
 
-    Ok(Some(types::User {
-        id: Uuid::try_from(user.id).unwrap(),
-        auth_id: user.auth_id,
-        created_at: user.created_at,
-    }))
+    Ok(Some(user))
+}
+
+pub async fn find_by_auth_id<'e>(
+    executor: impl SqliteExecutor<'e>,
+    auth_id: &str,
+) -> eyre::Result<Option<types::User>, sqlx::Error> {
+    let user = match sqlx::query_as!(
+        types::User,
+        "SELECT id AS \"id: UserId\", auth_id, created_at FROM users WHERE auth_id = ?",
+        auth_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(u) => u,
+        Err(e) => match e {
+            sqlx::Error::RowNotFound => return Ok(None),
+            e => return Err(e),
+        },
+    };
+
+    Ok(Some(user))
 }
 
 pub async fn insert<'e>(
     executor: impl SqliteExecutor<'e>,
     user: &types::User,
 ) -> eyre::Result<types::User, sqlx::Error> {
-    let id: String = user.id.clone().to_string();
     sqlx::query!(
         "
 INSERT INTO users (id, auth_id, created_at)
 VALUES (?, ?, ?)
     ",
-        id,
+        user.id,
         user.auth_id,
         user.created_at
     )
@@ -59,7 +68,7 @@ VALUES (?, ?, ?)
 
 pub async fn delete<'e>(
     executor: impl SqliteExecutor<'e>,
-    id: &str,
+    id: &UserId,
 ) -> eyre::Result<(), sqlx::Error> {
     sqlx::query!("DELETE FROM users WHERE id = ?", id)
         .execute(executor)
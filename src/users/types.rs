@@ -1,11 +1,15 @@
-use crate::types::uuid::Uuid;
 use crate::permissions;
+use crate::types::id::define_id;
 
 use serde::{Deserialize, Serialize};
 
+define_id!(UserId, "user");
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct User {
-    pub id: Uuid,
+    pub id: UserId,
+    /// Links the user to its identity; kept out of responses and event payloads.
+    #[serde(skip_serializing, default)]
     pub auth_id: String,
     pub created_at: chrono::NaiveDateTime,
 }
@@ -13,7 +17,7 @@ pub struct User {
 impl User {
     pub fn new(auth_id: &str) -> Self {
         Self {
-            id: Uuid::new(),
+            id: UserId::new(),
             auth_id: auth_id.to_string(),
             created_at: chrono::Utc::now().naive_utc(),
         }
@@ -0,0 +1,299 @@
+use crate::events::{types::EventId, Envelope};
+use crate::webhooks::types::{Delivery, DeliveryId, Webhook};
+
+use color_eyre::eyre;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect,
+};
+use sha2::Sha256;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a webhook has to respond before the delivery fails.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Posts events to webhooks as JSON, signed with their secret.
+///
+/// Deliveries carry the headers of the Standard Webhooks spec: `webhook-id` is the id of the
+/// event, which stays the same when it's redelivered, `webhook-timestamp` is when it was sent in
+/// seconds since the epoch, and `webhook-signature` is `v1,` followed by the base64 encoded
+/// HMAC-SHA256 of `<id>.<timestamp>.<body>` keyed with the webhook's secret.
+///
+/// Unless private targets are allowed, webhooks are only ever posted to public addresses, so
+/// that tenants can't reach the services next to the server through them.
+#[derive(Debug, Clone)]
+pub struct Sender {
+    http: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+impl Sender {
+    /// Builds a sender, which refuses loopback, private, link-local and unspecified addresses
+    /// unless `allow_private_targets` is set for local development.
+    ///
+    /// Redirects are never followed, since they could send a delivery to any of those addresses.
+    pub fn new(allow_private_targets: bool) -> Self {
+        let builder = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(redirect::Policy::none());
+        // resolving the host again when connecting keeps it from rebinding to a private address
+        let builder = if allow_private_targets {
+            builder
+        } else {
+            builder.dns_resolver(Arc::new(PublicResolver))
+        };
+
+        Self {
+            http: builder.build().unwrap_or_else(|e| panic!("{}", e)),
+            allow_private_targets,
+        }
+    }
+
+    /// Builds a sender allowing private targets when `WEBHOOK_ALLOW_PRIVATE_TARGETS` is true,
+    /// which it isn't by default.
+    pub fn from_env() -> Self {
+        Self::new(
+            env::var("WEBHOOK_ALLOW_PRIVATE_TARGETS")
+                .is_ok_and(|v| v.parse::<bool>().unwrap_or_else(|e| panic!("{}", e))),
+        )
+    }
+
+    /// Checks that the host of a url only resolves to addresses webhooks may be posted to.
+    pub async fn check_target(&self, url: &reqwest::Url) -> eyre::Result<(), String> {
+        if self.allow_private_targets {
+            return Ok(());
+        }
+        let Some(host) = url.host_str() else {
+            return Err("url must have a host".to_string());
+        };
+        // ipv6 hosts are bracketed in urls, but not when resolved
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap_or_default();
+
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| format!("url host `{host}` could not be resolved"))?
+            .collect::<Vec<_>>();
+        if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+            return Err(
+                "url must not resolve to a loopback, private, link-local or unspecified address"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Posts an event to a webhook, returning the delivery recording how it went; the webhook has
+    /// to respond with a 2xx status for it to succeed.
+    pub async fn deliver(
+        &self,
+        webhook: &Webhook,
+        envelope: &Envelope,
+        attempt: i64,
+    ) -> eyre::Result<Delivery, serde_json::Error> {
+        let payload = serde_json::to_string(envelope)?;
+
+        Ok(self
+            .post(webhook, &envelope.id, &envelope.kind, payload, attempt)
+            .await)
+    }
+
+    /// Posts the payload of an earlier delivery to its webhook again, freshly signed.
+    pub async fn redeliver(
+        &self,
+        webhook: &Webhook,
+        delivery: &Delivery,
+        attempt: i64,
+    ) -> Delivery {
+        self.post(
+            webhook,
+            &delivery.event_id,
+            &delivery.event_type,
+            delivery.payload.clone(),
+            attempt,
+        )
+        .await
+    }
+
+    async fn post(
+        &self,
+        webhook: &Webhook,
+        event_id: &EventId,
+        event_type: &str,
+        payload: String,
+        attempt: i64,
+    ) -> Delivery {
+        let attempted_at = chrono::Utc::now();
+        let timestamp = attempted_at.timestamp();
+        let signature = sign(
+            &webhook.secret,
+            &format!("{event_id}.{timestamp}.{payload}"),
+        );
+
+        let started = Instant::now();
+        let response = match self.check_url(&webhook.url).await {
+            Ok(()) => self
+                .http
+                .post(&webhook.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header("webhook-id", event_id.to_string())
+                .header("webhook-timestamp", timestamp.to_string())
+                .header("webhook-signature", format!("v1,{signature}"))
+                .body(payload.clone())
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        let duration_ms = i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX);
+
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("webhook responded with {}", response.status())),
+            ),
+            Err(e) => (None, Some(e)),
+        };
+
+        Delivery {
+            id: DeliveryId::new(),
+            webhook_id: webhook.id,
+            event_id: *event_id,
+            event_type: event_type.to_string(),
+            payload,
+            attempt,
+            status_code,
+            succeeded: error.is_none(),
+            error,
+            duration_ms,
+            attempted_at: attempted_at.naive_utc(),
+            next_attempt_at: None,
+        }
+    }
+
+    /// Checks the target of webhooks registered before their url was checked, or whose url is an
+    /// address, which isn't resolved when connecting.
+    async fn check_url(&self, url: &str) -> eyre::Result<(), String> {
+        let url = reqwest::Url::parse(url).map_err(|e| format!("url is invalid: {e}"))?;
+
+        self.check_target(&url).await
+    }
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+/// Resolves hosts to their public addresses only, failing when they have none.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("`{}` has no public address", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an address is neither loopback, private, link-local nor unspecified, including
+/// ipv4 addresses mapped to ipv6.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or_else(
+            || {
+                !(ip.is_loopback()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || ip.is_unspecified())
+            },
+            |ip| is_public(IpAddr::V4(ip)),
+        ),
+    }
+}
+
+fn sign(secret: &str, content: &str) -> String {
+    // HMAC accepts keys of any length, so this can't fail
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap_or_else(|e| panic!("{}", e));
+    mac.update(content.as_bytes());
+
+    base64::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses_are_targets() {
+        let cases = [
+            ("93.184.216.34", true),
+            ("2606:2800:220:1:248:1893:25c8:1946", true),
+            ("127.0.0.1", false),
+            ("10.0.0.1", false),
+            ("172.16.0.1", false),
+            ("192.168.1.1", false),
+            ("169.254.169.254", false),
+            ("0.0.0.0", false),
+            ("255.255.255.255", false),
+            ("::1", false),
+            ("::", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:169.254.169.254", false),
+        ];
+
+        for (ip, public) in cases {
+            let addr = ip.parse::<IpAddr>().unwrap_or_else(|e| panic!("{}", e));
+            assert_eq!(is_public(addr), public, "{ip}");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn refuses_urls_resolving_to_private_addresses() {
+        let sender = Sender::new(false);
+
+        for url in [
+            "http://127.0.0.1:8000/hook",
+            "http://localhost/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+        ] {
+            let url = reqwest::Url::parse(url).unwrap_or_else(|e| panic!("{}", e));
+            assert!(sender.check_target(&url).await.is_err(), "{url}");
+        }
+
+        let url =
+            reqwest::Url::parse("http://127.0.0.1:8000/hook").unwrap_or_else(|e| panic!("{}", e));
+        assert!(Sender::new(true).check_target(&url).await.is_ok());
+    }
+}
@@ -0,0 +1,33 @@
+use crate::events::{self, RetryPolicy};
+use crate::webhooks::{domain::service, Sender};
+
+use color_eyre::eyre;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+/// Delivers every event to the webhooks of its tenant subscribed to it.
+///
+/// Failed deliveries don't fail the event, they're scheduled to be retried with the given policy
+/// by the webhook retrier instead, so that one tenant's webhook being down doesn't hold up the
+/// events of the others.
+pub struct WebhooksEventHandler {
+    sender: Sender,
+    retry: RetryPolicy,
+}
+
+impl WebhooksEventHandler {
+    pub fn new_handler(sender: Sender, retry: RetryPolicy) -> Arc<dyn events::EventHandler> {
+        Arc::new(Self { sender, retry })
+    }
+}
+
+#[rocket::async_trait]
+impl events::EventHandler for WebhooksEventHandler {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, pool: &SqlitePool, envelope: &events::Envelope) -> eyre::Result<()> {
+        Ok(service::deliver_event(pool, &self.sender, &self.retry, envelope).await?)
+    }
+}
@@ -0,0 +1,624 @@
+use crate::events::{types::is_type_pattern, Envelope, RetryPolicy};
+use crate::permissions;
+use crate::tenants::{self, TenantContext, TenantScope};
+use crate::types::{
+    pagination::{Paginated, Pagination},
+    validation::{FieldValidationError, Validate, ValidationErrors, Validator},
+};
+use crate::webhooks::{
+    self,
+    types::{self, DeliveryId, WebhookId},
+    Sender,
+};
+
+use color_eyre::eyre;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+const SECRET_BYTES: usize = 32;
+const MAX_URL_LENGTH: usize = 2048;
+
+#[derive(Error, Debug)]
+pub enum FindWebhookError {
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("webhook `{0}` does not exist")]
+    NotFound(String),
+
+    #[error("failed to check permission of requesting user")]
+    AccessCheckFailed(permissions::HasPermissionError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists the webhooks of the current tenant, which requires `read-webhooks` on it.
+pub async fn list_webhooks(
+    pool: &SqlitePool,
+    context: &TenantContext,
+) -> eyre::Result<Vec<types::Webhook>, FindWebhookError> {
+    ensure_permission(pool, context, "read-webhooks").await?;
+
+    Ok(webhooks::sqlite::find_all(pool, &context.scope).await?)
+}
+
+pub async fn find_webhook(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    id: &WebhookId,
+) -> eyre::Result<types::Webhook, FindWebhookError> {
+    ensure_permission(pool, context, "read-webhooks").await?;
+
+    webhooks::sqlite::find_one(pool, &context.scope, id)
+        .await?
+        .ok_or_else(|| FindWebhookError::NotFound(id.to_string()))
+}
+
+#[derive(Error, Debug)]
+pub enum CreateWebhookError {
+    #[error("invalid input")]
+    InvalidInput(ValidationErrors),
+
+    #[error("failed to find webhook")]
+    Find(#[from] FindWebhookError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Patterns of the event types to deliver, such as `user.created`, `permission.*` or `*`.
+    pub event_types: Vec<String>,
+}
+
+impl Validate for CreateWebhookRequest {
+    fn rules(&self, validator: &mut Validator) {
+        validator.parse("url", validate_url(&self.url));
+        validator.max_length("url", &self.url, MAX_URL_LENGTH);
+        validator.check(
+            "event_types",
+            !self.event_types.is_empty(),
+            "event_types must name at least one event type",
+        );
+        for pattern in &self.event_types {
+            validator.check(
+                "event_types",
+                is_type_pattern(pattern),
+                format!("`{pattern}` is not an event type, such as `user.created` or `user.*`"),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: types::Webhook,
+    /// The secret deliveries are signed with; it cannot be retrieved again.
+    pub secret: String,
+}
+
+/// Registers a webhook for the current tenant, which requires `write-webhooks` on it.
+///
+/// The webhook is delivered the events of the tenant matching its event types which happen from
+/// now on. Its url has to resolve to public addresses only, unless the sender allows otherwise.
+pub async fn create_webhook(
+    pool: &SqlitePool,
+    sender: &Sender,
+    context: &TenantContext,
+    payload: CreateWebhookRequest,
+) -> eyre::Result<CreatedWebhook, CreateWebhookError> {
+    payload
+        .validate()
+        .map_err(CreateWebhookError::InvalidInput)?;
+    ensure_permission(pool, context, "write-webhooks").await?;
+    // only resolved once the user may create webhooks, so it can't be used to probe hosts
+    let url = validate_url(&payload.url).map_err(invalid_url)?;
+    sender.check_target(&url).await.map_err(invalid_url)?;
+
+    let secret = generate_secret();
+    let webhook = webhooks::sqlite::insert(
+        pool,
+        &context.scope,
+        &types::Webhook::new(
+            context.scope.tenant_id(),
+            &payload.url,
+            &secret,
+            payload.event_types,
+            &context.user_id,
+        ),
+    )
+    .await?;
+
+    Ok(CreatedWebhook { webhook, secret })
+}
+
+/// Deletes a webhook of the current tenant along with its delivery log.
+pub async fn delete_webhook(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    id: &WebhookId,
+) -> eyre::Result<(), FindWebhookError> {
+    ensure_permission(pool, context, "write-webhooks").await?;
+
+    if webhooks::sqlite::delete(pool, &context.scope, id).await? {
+        Ok(())
+    } else {
+        Err(FindWebhookError::NotFound(id.to_string()))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ListDeliveriesError {
+    #[error("invalid input")]
+    InvalidInput(FieldValidationError),
+
+    #[error("failed to find webhook")]
+    Find(#[from] FindWebhookError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Lists a page of the delivery log of a webhook, newest first, with every attempt at posting an
+/// event to it.
+pub async fn list_deliveries(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    webhook_id: &WebhookId,
+    page: Option<u32>,
+    per_page: Option<u32>,
+) -> eyre::Result<Paginated<types::Delivery>, ListDeliveriesError> {
+    let pagination = Pagination::new(page, per_page).map_err(ListDeliveriesError::InvalidInput)?;
+    let webhook = find_webhook(pool, context, webhook_id).await?;
+
+    let deliveries =
+        webhooks::sqlite::find_deliveries(pool, &context.scope, &webhook.id, &pagination).await?;
+    let total = webhooks::sqlite::count_deliveries(pool, &context.scope, &webhook.id).await?;
+
+    Ok(Paginated::new(deliveries, pagination, total))
+}
+
+#[derive(Error, Debug)]
+pub enum RedeliverError {
+    #[error("delivery `{0}` does not exist")]
+    NotFound(String),
+
+    #[error("failed to find webhook")]
+    Find(#[from] FindWebhookError),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Posts the event of an earlier delivery to its webhook again, which requires `write-webhooks`,
+/// returning the new delivery whether or not it succeeded.
+///
+/// This is how a tenant gets an event which failed every automatic attempt, or one it failed to
+/// process on its side, delivered again.
+pub async fn redeliver(
+    pool: &SqlitePool,
+    sender: &Sender,
+    context: &TenantContext,
+    webhook_id: &WebhookId,
+    id: &DeliveryId,
+) -> eyre::Result<types::Delivery, RedeliverError> {
+    ensure_permission(pool, context, "write-webhooks").await?;
+
+    let webhook = webhooks::sqlite::find_one(pool, &context.scope, webhook_id)
+        .await?
+        .ok_or_else(|| FindWebhookError::NotFound(webhook_id.to_string()))?;
+    let delivery = webhooks::sqlite::find_delivery(pool, &context.scope, webhook_id, id)
+        .await?
+        .ok_or_else(|| RedeliverError::NotFound(id.to_string()))?;
+    let (attempts, _) =
+        webhooks::sqlite::count_attempts(pool, &webhook.id, &delivery.event_id).await?;
+
+    let redelivery = sender.redeliver(&webhook, &delivery, attempts + 1).await;
+
+    Ok(webhooks::sqlite::insert_delivery(pool, &redelivery).await?)
+}
+
+#[derive(Error, Debug)]
+pub enum DeliverEventError {
+    #[error("failed to serialize event")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("unexpected error from sqlx")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Posts an event once to every webhook of its tenant subscribed to its type, recording each
+/// attempt in their delivery logs.
+///
+/// Failed deliveries aren't retried here but scheduled to be retried by [`retry_deliveries`],
+/// so that a webhook which is down doesn't hold up the events of every other tenant. Webhooks
+/// the event was already posted to are skipped, so that handling it again doesn't post it twice.
+/// Only events which happened after a webhook was created are delivered to it.
+pub async fn deliver_event(
+    pool: &SqlitePool,
+    sender: &Sender,
+    retry: &RetryPolicy,
+    envelope: &Envelope,
+) -> eyre::Result<(), DeliverEventError> {
+    let Some(tenant_id) = envelope.tenant_id else {
+        return Ok(());
+    };
+
+    let subscribed = webhooks::sqlite::find_all(pool, &TenantScope::unchecked(&tenant_id))
+        .await?
+        .into_iter()
+        .filter(|webhook| {
+            webhook.is_subscribed_to(&envelope.kind) && envelope.occurred_at >= webhook.created_at
+        });
+    for webhook in subscribed {
+        let (attempts, _) =
+            webhooks::sqlite::count_attempts(pool, &webhook.id, &envelope.id).await?;
+        if attempts > 0 {
+            continue;
+        }
+
+        let delivery = sender.deliver(&webhook, envelope, 1).await?;
+        record_attempt(pool, retry, delivery).await?;
+    }
+
+    Ok(())
+}
+
+/// Retries the failed deliveries of every webhook which are due, each in a task of its own so
+/// that a slow webhook doesn't hold up the others, returning how many were retried.
+pub async fn retry_deliveries(
+    pool: &SqlitePool,
+    sender: &Sender,
+    retry: &RetryPolicy,
+) -> eyre::Result<usize, sqlx::Error> {
+    let due = webhooks::sqlite::find_due_deliveries(pool, &chrono::Utc::now().naive_utc()).await?;
+
+    let mut retried = 0;
+    for delivery in due {
+        // claimed first, so that a retry still running on the next tick isn't made again
+        if !webhooks::sqlite::claim_retry(pool, &delivery.id).await? {
+            continue;
+        }
+
+        let (pool, sender, retry) = (pool.clone(), sender.clone(), *retry);
+        tokio::spawn(async move {
+            if let Err(e) = retry_delivery(&pool, &sender, &retry, &delivery).await {
+                tracing::error!("failed to retry webhook delivery {}: {:?}", delivery.id, e);
+            }
+        });
+        retried += 1;
+    }
+
+    Ok(retried)
+}
+
+async fn retry_delivery(
+    pool: &SqlitePool,
+    sender: &Sender,
+    retry: &RetryPolicy,
+    delivery: &types::Delivery,
+) -> eyre::Result<(), sqlx::Error> {
+    let Some(webhook) = webhooks::sqlite::find_unscoped(pool, &delivery.webhook_id).await? else {
+        return Ok(());
+    };
+    // the event may have been redelivered by hand in the meantime
+    let (attempts, succeeded) =
+        webhooks::sqlite::count_attempts(pool, &webhook.id, &delivery.event_id).await?;
+    if succeeded > 0 {
+        return Ok(());
+    }
+
+    let redelivery = sender.redeliver(&webhook, delivery, attempts + 1).await;
+    record_attempt(pool, retry, redelivery).await?;
+
+    Ok(())
+}
+
+/// Records an automatic attempt at a delivery, scheduling its retry when it failed with attempts
+/// left.
+async fn record_attempt(
+    pool: &SqlitePool,
+    retry: &RetryPolicy,
+    mut delivery: types::Delivery,
+) -> eyre::Result<types::Delivery, sqlx::Error> {
+    if !delivery.succeeded {
+        let attempt = u32::try_from(delivery.attempt).unwrap_or(u32::MAX);
+        if attempt < retry.max_attempts {
+            delivery.next_attempt_at = chrono::Duration::from_std(retry.backoff(attempt))
+                .ok()
+                .and_then(|backoff| delivery.attempted_at.checked_add_signed(backoff));
+        }
+        tracing::warn!(
+            "failed to deliver event {} to webhook {} on attempt {}, {}: {}",
+            delivery.event_id,
+            delivery.webhook_id,
+            delivery.attempt,
+            delivery
+                .next_attempt_at
+                .map_or_else(|| "giving up".to_string(), |at| format!("retrying at {at}")),
+            delivery.error.as_deref().unwrap_or_default()
+        );
+    }
+
+    webhooks::sqlite::insert_delivery(pool, &delivery).await
+}
+
+async fn ensure_permission(
+    pool: &SqlitePool,
+    context: &TenantContext,
+    action: &str,
+) -> eyre::Result<(), FindWebhookError> {
+    let can = permissions::has_permission_to(
+        pool,
+        &context.attributes,
+        &context.user_id,
+        action,
+        &context.scope.tenant_id().to_string(),
+        &tenants::types::Tenant::kind().to_string(),
+    )
+    .await
+    .map_err(FindWebhookError::AccessCheckFailed)?;
+
+    if can {
+        Ok(())
+    } else {
+        Err(FindWebhookError::PermissionDenied)
+    }
+}
+
+fn validate_url(url: &str) -> eyre::Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("url is invalid: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("url must be an http or https url".to_string());
+    }
+
+    Ok(url)
+}
+
+fn invalid_url(message: String) -> CreateWebhookError {
+    CreateWebhookError::InvalidInput(
+        FieldValidationError {
+            field: "url".to_string(),
+            message,
+        }
+        .into(),
+    )
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0_u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    format!(
+        "whsec_{}",
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::AppEvent;
+    use crate::types::{pagination::Pagination, sqlite::test_pool};
+    use crate::users;
+
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// A request received by a [`Listener`], with its header names lowercased.
+    #[derive(Clone)]
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// An http endpoint on the loopback interface, answering every request with its status.
+    struct Listener {
+        url: String,
+        status: Arc<AtomicU16>,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl Listener {
+        async fn start(status: u16) -> eyre::Result<Self> {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let url = format!("http://{}/hook", listener.local_addr()?);
+            let status = Arc::new(AtomicU16::new(status));
+            let received = Arc::new(Mutex::new(Vec::new()));
+
+            let (responds_with, records) = (status.clone(), received.clone());
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let Ok(request) = read_request(&mut stream).await else {
+                        continue;
+                    };
+                    records
+                        .lock()
+                        .unwrap_or_else(|e| panic!("{}", e))
+                        .push(request);
+                    let response = format!(
+                        "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        responds_with.load(Ordering::SeqCst)
+                    );
+                    stream.write_all(response.as_bytes()).await.ok();
+                }
+            });
+
+            Ok(Self {
+                url,
+                status,
+                received,
+            })
+        }
+
+        fn received(&self) -> usize {
+            self.received
+                .lock()
+                .unwrap_or_else(|e| panic!("{}", e))
+                .len()
+        }
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> eyre::Result<Received> {
+        let mut buffer = Vec::new();
+        let head_end = loop {
+            let mut chunk = [0_u8; 1024];
+            let read = stream.read(&mut chunk).await?;
+            eyre::ensure!(read > 0, "connection closed before the request was read");
+            buffer.extend_from_slice(&chunk[..read]);
+            if let Some(at) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break at + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+        let headers = head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect::<HashMap<_, _>>();
+        let length = headers
+            .get("content-length")
+            .map_or(Ok(0), |length| length.parse::<usize>())?;
+        while buffer.len() < head_end + length {
+            let mut chunk = [0_u8; 1024];
+            let read = stream.read(&mut chunk).await?;
+            eyre::ensure!(read > 0, "connection closed before the body was read");
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        Ok(Received {
+            headers,
+            body: String::from_utf8_lossy(&buffer[head_end..head_end + length]).to_string(),
+        })
+    }
+
+    /// Waits for the retries running in the background to record their attempts.
+    async fn wait_for_attempts(
+        pool: &SqlitePool,
+        webhook_id: &WebhookId,
+        envelope: &Envelope,
+        expected: i64,
+    ) -> eyre::Result<()> {
+        for _ in 0..200 {
+            let (attempts, _) =
+                webhooks::sqlite::count_attempts(pool, webhook_id, &envelope.id).await?;
+            if attempts >= expected {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        eyre::bail!("webhook {webhook_id} wasn't attempted {expected} times")
+    }
+
+    #[rocket::async_test]
+    async fn delivers_signed_events_and_only_retries_failed_webhooks() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|owner")).await?;
+        let tenant = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let scope = TenantScope::unchecked(&tenant.id);
+        let healthy = Listener::start(204).await?;
+        let failing = Listener::start(500).await?;
+        let mut webhooks = Vec::new();
+        for listener in [&healthy, &failing] {
+            let webhook = types::Webhook::new(
+                &tenant.id,
+                &listener.url,
+                &generate_secret(),
+                vec!["user.*".to_string()],
+                &user.id,
+            );
+            webhooks.push(webhooks::sqlite::insert(&pool, &scope, &webhook).await?);
+        }
+        let (healthy_webhook, failing_webhook) = (&webhooks[0], &webhooks[1]);
+        let sender = Sender::new(true);
+        let retry = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+        let envelope = Envelope::new(AppEvent::User(users::events::UserEvent::Created(
+            user.clone(),
+        )))
+        .with_tenant(&tenant.id);
+
+        deliver_event(&pool, &sender, &retry, &envelope).await?;
+
+        // the delivery is signed over its id, timestamp and body with the webhook's secret
+        assert_eq!(healthy.received(), 1);
+        let request = healthy.received.lock().unwrap_or_else(|e| panic!("{}", e))[0].clone();
+        assert_eq!(request.headers["webhook-id"], envelope.id.to_string());
+        assert_eq!(request.body, serde_json::to_string(&envelope)?);
+        let signature = request.headers["webhook-signature"]
+            .strip_prefix("v1,")
+            .ok_or_else(|| eyre::eyre!("signature isn't versioned"))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(healthy_webhook.secret.as_bytes())?;
+        mac.update(
+            format!(
+                "{}.{}.{}",
+                envelope.id, request.headers["webhook-timestamp"], request.body
+            )
+            .as_bytes(),
+        );
+        mac.verify_slice(&base64::decode(signature)?)?;
+
+        // every attempt is logged, and only failed ones are scheduled to be retried
+        let log = webhooks::sqlite::find_deliveries(
+            &pool,
+            &scope,
+            &healthy_webhook.id,
+            &Pagination::new(None, None)?,
+        )
+        .await?;
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].event_id, envelope.id);
+        assert_eq!(log[0].event_type, "user.created");
+        assert_eq!(log[0].attempt, 1);
+        assert_eq!(log[0].status_code, Some(204));
+        assert!(log[0].succeeded);
+        assert_eq!(log[0].error, None);
+        assert_eq!(log[0].next_attempt_at, None);
+        let log = webhooks::sqlite::find_deliveries(
+            &pool,
+            &scope,
+            &failing_webhook.id,
+            &Pagination::new(None, None)?,
+        )
+        .await?;
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status_code, Some(500));
+        assert!(!log[0].succeeded);
+        assert!(log[0].next_attempt_at.is_some());
+
+        // handling the event again posts it to neither webhook
+        deliver_event(&pool, &sender, &retry, &envelope).await?;
+        assert_eq!((healthy.received(), failing.received()), (1, 1));
+
+        // retries only go to the webhook which failed, until it succeeds
+        assert_eq!(retry_deliveries(&pool, &sender, &retry).await?, 1);
+        wait_for_attempts(&pool, &failing_webhook.id, &envelope, 2).await?;
+        failing.status.store(204, Ordering::SeqCst);
+        assert_eq!(retry_deliveries(&pool, &sender, &retry).await?, 1);
+        wait_for_attempts(&pool, &failing_webhook.id, &envelope, 3).await?;
+        assert_eq!(retry_deliveries(&pool, &sender, &retry).await?, 0);
+        assert_eq!((healthy.received(), failing.received()), (1, 3));
+        let (attempts, succeeded) =
+            webhooks::sqlite::count_attempts(&pool, &failing_webhook.id, &envelope.id).await?;
+        assert_eq!((attempts, succeeded), (3, 1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+mod delivery;
+mod domain;
+mod routes;
+
+pub mod sqlite;
+pub mod types;
+pub use delivery::Sender;
+pub use domain::events;
+pub use domain::service::retry_deliveries;
+pub use routes::routes;
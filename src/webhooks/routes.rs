@@ -0,0 +1,142 @@
+use crate::tenants::TenantContext;
+use crate::types::{error::ApiError, id::InvalidId, pagination::Paginated};
+use crate::webhooks::{
+    domain::service,
+    types::{self, DeliveryId, WebhookId},
+    Sender,
+};
+
+use color_eyre::eyre;
+use rocket::{http::Status, response::status, route::Route, serde::json::Json};
+use sqlx::SqlitePool;
+
+#[allow(clippy::no_effect_underscore_binding)]
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_webhooks_route,
+        create_webhook_route,
+        find_webhook_route,
+        delete_webhook_route,
+        list_deliveries_route,
+        redeliver_route
+    ]
+}
+
+#[get("/")]
+async fn list_webhooks_route(
+    pool: &rocket::State<SqlitePool>,
+    context: TenantContext,
+) -> eyre::Result<Json<Vec<types::Webhook>>, ApiError> {
+    service::list_webhooks(pool, &context)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/", data = "<payload>")]
+async fn create_webhook_route(
+    pool: &rocket::State<SqlitePool>,
+    sender: &rocket::State<Sender>,
+    payload: Json<service::CreateWebhookRequest>,
+    context: TenantContext,
+) -> eyre::Result<status::Created<Json<service::CreatedWebhook>>, ApiError> {
+    let created = service::create_webhook(pool, sender, &context, payload.into_inner()).await?;
+
+    Ok(status::Created::new(format!("/api/webhooks/{}", created.webhook.id)).body(Json(created)))
+}
+
+#[get("/<id>")]
+async fn find_webhook_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<WebhookId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Json<types::Webhook>, ApiError> {
+    service::find_webhook(pool, &context, &id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[delete("/<id>")]
+async fn delete_webhook_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<WebhookId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Status, ApiError> {
+    service::delete_webhook(pool, &context, &id?).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[get("/<id>/deliveries?<page>&<per_page>")]
+async fn list_deliveries_route(
+    pool: &rocket::State<SqlitePool>,
+    id: Result<WebhookId, InvalidId>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    context: TenantContext,
+) -> eyre::Result<Json<Paginated<types::Delivery>>, ApiError> {
+    service::list_deliveries(pool, &context, &id?, page, per_page)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[post("/<id>/deliveries/<delivery_id>/redeliver")]
+async fn redeliver_route(
+    pool: &rocket::State<SqlitePool>,
+    sender: &rocket::State<Sender>,
+    id: Result<WebhookId, InvalidId>,
+    delivery_id: Result<DeliveryId, InvalidId>,
+    context: TenantContext,
+) -> eyre::Result<Json<types::Delivery>, ApiError> {
+    service::redeliver(pool, sender, &context, &id?, &delivery_id?)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+impl From<service::FindWebhookError> for ApiError {
+    fn from(err: service::FindWebhookError) -> Self {
+        match err {
+            service::FindWebhookError::PermissionDenied => {
+                Self::new(Status::Forbidden).with_detail(err)
+            }
+            service::FindWebhookError::NotFound(_) => Self::new(Status::NotFound).with_detail(err),
+            service::FindWebhookError::AccessCheckFailed(err) => err.into(),
+            err @ service::FindWebhookError::Sqlx(_) => Self::internal("find webhook", &err),
+        }
+    }
+}
+
+impl From<service::CreateWebhookError> for ApiError {
+    fn from(err: service::CreateWebhookError) -> Self {
+        match err {
+            service::CreateWebhookError::InvalidInput(err) => err.into(),
+            service::CreateWebhookError::Find(err) => err.into(),
+            err @ service::CreateWebhookError::Sqlx(_) => Self::internal("create webhook", &err),
+        }
+    }
+}
+
+impl From<service::ListDeliveriesError> for ApiError {
+    fn from(err: service::ListDeliveriesError) -> Self {
+        match err {
+            service::ListDeliveriesError::InvalidInput(err) => err.into(),
+            service::ListDeliveriesError::Find(err) => err.into(),
+            err @ service::ListDeliveriesError::Sqlx(_) => {
+                Self::internal("list webhook deliveries", &err)
+            }
+        }
+    }
+}
+
+impl From<service::RedeliverError> for ApiError {
+    fn from(err: service::RedeliverError) -> Self {
+        match err {
+            service::RedeliverError::NotFound(_) => Self::new(Status::NotFound).with_detail(err),
+            service::RedeliverError::Find(err) => err.into(),
+            err @ service::RedeliverError::Sqlx(_) => Self::internal("redeliver webhook", &err),
+        }
+    }
+}
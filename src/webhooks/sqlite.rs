@@ -0,0 +1,397 @@
+use crate::events::types::EventId;
+use crate::tenants::{types::TenantId, TenantScope};
+use crate::types::pagination::Pagination;
+use crate::users::types::UserId;
+use crate::webhooks::types::{self, DeliveryId, WebhookId};
+
+use color_eyre::eyre;
+use sqlx::SqliteExecutor;
+use std::convert::TryFrom;
+
+struct WebhookRecord {
+    id: WebhookId,
+    tenant_id: TenantId,
+    url: String,
+    secret: String,
+    event_types: String,
+    created_by: Option<UserId>,
+    created_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<WebhookRecord> for types::Webhook {
+    type Error = sqlx::Error;
+
+    fn try_from(record: WebhookRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            id: record.id,
+            tenant_id: record.tenant_id,
+            url: record.url,
+            secret: record.secret,
+            event_types: serde_json::from_str(&record.event_types)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            created_by: record.created_by,
+            created_at: record.created_at,
+        })
+    }
+}
+
+pub async fn insert<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    webhook: &types::Webhook,
+) -> eyre::Result<types::Webhook, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let event_types = serde_json::to_string(&webhook.event_types)
+        .map_err(|e| sqlx::Error::Protocol(format!("failed to serialize event types: {e}")))?;
+
+    sqlx::query!(
+        "
+INSERT INTO webhooks (id, tenant_id, url, secret, event_types, created_by, created_at)
+VALUES (?, ?, ?, ?, ?, ?, ?)
+        ",
+        webhook.id,
+        tenant_id,
+        webhook.url,
+        webhook.secret,
+        event_types,
+        webhook.created_by,
+        webhook.created_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(webhook.clone())
+}
+
+pub async fn find_all<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+) -> eyre::Result<Vec<types::Webhook>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query_as!(
+        WebhookRecord,
+        "SELECT id AS \"id: WebhookId\", tenant_id AS \"tenant_id: TenantId\", url, secret,
+                event_types, created_by AS \"created_by: UserId\", created_at
+            FROM webhooks
+            WHERE tenant_id = ?
+            ORDER BY created_at, id",
+        tenant_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Webhook::try_from)
+    .collect()
+}
+
+pub async fn find_one<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &WebhookId,
+) -> eyre::Result<Option<types::Webhook>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query_as!(
+        WebhookRecord,
+        "SELECT id AS \"id: WebhookId\", tenant_id AS \"tenant_id: TenantId\", url, secret,
+                event_types, created_by AS \"created_by: UserId\", created_at
+            FROM webhooks
+            WHERE tenant_id = ? AND id = ?",
+        tenant_id,
+        id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(types::Webhook::try_from)
+    .transpose()
+}
+
+/// Finds a webhook of any tenant, for retrying its deliveries outside of a request.
+pub async fn find_unscoped<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: &WebhookId,
+) -> eyre::Result<Option<types::Webhook>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookRecord,
+        "SELECT id AS \"id: WebhookId\", tenant_id AS \"tenant_id: TenantId\", url, secret,
+                event_types, created_by AS \"created_by: UserId\", created_at
+            FROM webhooks
+            WHERE id = ?",
+        id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(types::Webhook::try_from)
+    .transpose()
+}
+
+/// Deletes a webhook along with its delivery log, returning whether it existed.
+pub async fn delete<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    id: &WebhookId,
+) -> eyre::Result<bool, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let deleted = sqlx::query!(
+        "DELETE FROM webhooks WHERE tenant_id = ? AND id = ?",
+        tenant_id,
+        id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+struct DeliveryRecord {
+    id: DeliveryId,
+    webhook_id: WebhookId,
+    event_id: EventId,
+    event_type: String,
+    payload: String,
+    attempt: i64,
+    status_code: Option<i64>,
+    error: Option<String>,
+    succeeded: bool,
+    duration_ms: i64,
+    attempted_at: chrono::NaiveDateTime,
+    next_attempt_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<DeliveryRecord> for types::Delivery {
+    type Error = sqlx::Error;
+
+    fn try_from(record: DeliveryRecord) -> eyre::Result<Self, Self::Error> {
+        Ok(Self {
+            id: record.id,
+            webhook_id: record.webhook_id,
+            event_id: record.event_id,
+            event_type: record.event_type,
+            payload: record.payload,
+            attempt: record.attempt,
+            status_code: record
+                .status_code
+                .map(u16::try_from)
+                .transpose()
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            error: record.error,
+            succeeded: record.succeeded,
+            duration_ms: record.duration_ms,
+            attempted_at: record.attempted_at,
+            next_attempt_at: record.next_attempt_at,
+        })
+    }
+}
+
+pub async fn insert_delivery<'e>(
+    executor: impl SqliteExecutor<'e>,
+    delivery: &types::Delivery,
+) -> eyre::Result<types::Delivery, sqlx::Error> {
+    sqlx::query!(
+        "
+INSERT INTO webhook_deliveries (
+    id, webhook_id, event_id, event_type, payload, attempt, status_code, error, succeeded,
+    duration_ms, attempted_at, next_attempt_at
+)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ",
+        delivery.id,
+        delivery.webhook_id,
+        delivery.event_id,
+        delivery.event_type,
+        delivery.payload,
+        delivery.attempt,
+        delivery.status_code,
+        delivery.error,
+        delivery.succeeded,
+        delivery.duration_ms,
+        delivery.attempted_at,
+        delivery.next_attempt_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(delivery.clone())
+}
+
+/// Counts how often an event has been posted to a webhook, and how often successfully.
+///
+/// Deliveries are unscoped as they're made by the webhook handler rather than for a request.
+pub async fn count_attempts<'e>(
+    executor: impl SqliteExecutor<'e>,
+    webhook_id: &WebhookId,
+    event_id: &EventId,
+) -> eyre::Result<(i64, i64), sqlx::Error> {
+    let counts = sqlx::query!(
+        "SELECT COUNT(1) AS attempts, COALESCE(SUM(succeeded), 0) AS \"succeeded!: i64\"
+            FROM webhook_deliveries
+            WHERE webhook_id = ? AND event_id = ?",
+        webhook_id,
+        event_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok((counts.attempts.into(), counts.succeeded))
+}
+
+/// Finds the failed deliveries of every webhook whose retry is due, the longest due first.
+pub async fn find_due_deliveries<'e>(
+    executor: impl SqliteExecutor<'e>,
+    now: &chrono::NaiveDateTime,
+) -> eyre::Result<Vec<types::Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryRecord,
+        "SELECT id AS \"id: DeliveryId\", webhook_id AS \"webhook_id: WebhookId\",
+                event_id AS \"event_id: EventId\", event_type, payload, attempt, status_code,
+                error, succeeded AS \"succeeded: bool\", duration_ms, attempted_at,
+                next_attempt_at
+            FROM webhook_deliveries
+            WHERE next_attempt_at <= ?
+            ORDER BY next_attempt_at, id",
+        now
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Delivery::try_from)
+    .collect()
+}
+
+/// Claims the retry of a failed delivery by clearing when it's due, returning whether it was
+/// still due, so that it's only ever retried once.
+pub async fn claim_retry<'e>(
+    executor: impl SqliteExecutor<'e>,
+    id: &DeliveryId,
+) -> eyre::Result<bool, sqlx::Error> {
+    let claimed = sqlx::query!(
+        "UPDATE webhook_deliveries SET next_attempt_at = NULL
+            WHERE id = ? AND next_attempt_at IS NOT NULL",
+        id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(claimed > 0)
+}
+
+/// Finds a page of the delivery log of a webhook, newest first.
+pub async fn find_deliveries<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    webhook_id: &WebhookId,
+    pagination: &Pagination,
+) -> eyre::Result<Vec<types::Delivery>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let limit = pagination.limit();
+    let offset = pagination.offset();
+    sqlx::query_as!(
+        DeliveryRecord,
+        "SELECT webhook_deliveries.id AS \"id!: DeliveryId\",
+                webhook_id AS \"webhook_id!: WebhookId\", event_id AS \"event_id!: EventId\",
+                event_type AS \"event_type!\", payload AS \"payload!\", attempt AS \"attempt!\",
+                status_code, error, succeeded AS \"succeeded!: bool\",
+                duration_ms AS \"duration_ms!\", attempted_at AS \"attempted_at!\",
+                next_attempt_at
+            FROM webhook_deliveries
+            JOIN webhooks ON webhooks.id = webhook_deliveries.webhook_id
+            WHERE webhooks.tenant_id = ? AND webhook_id = ?
+            ORDER BY attempted_at DESC, webhook_deliveries.id
+            LIMIT ? OFFSET ?",
+        tenant_id,
+        webhook_id,
+        limit,
+        offset
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(types::Delivery::try_from)
+    .collect()
+}
+
+pub async fn count_deliveries<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    webhook_id: &WebhookId,
+) -> eyre::Result<i64, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(1)
+            FROM webhook_deliveries
+            JOIN webhooks ON webhooks.id = webhook_deliveries.webhook_id
+            WHERE webhooks.tenant_id = ? AND webhook_id = ?",
+        tenant_id,
+        webhook_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(count.into())
+}
+
+pub async fn find_delivery<'e>(
+    executor: impl SqliteExecutor<'e>,
+    scope: &TenantScope,
+    webhook_id: &WebhookId,
+    id: &DeliveryId,
+) -> eyre::Result<Option<types::Delivery>, sqlx::Error> {
+    let tenant_id = scope.tenant_id();
+    sqlx::query_as!(
+        DeliveryRecord,
+        "SELECT webhook_deliveries.id AS \"id!: DeliveryId\",
+                webhook_id AS \"webhook_id!: WebhookId\", event_id AS \"event_id!: EventId\",
+                event_type AS \"event_type!\", payload AS \"payload!\", attempt AS \"attempt!\",
+                status_code, error, succeeded AS \"succeeded!: bool\",
+                duration_ms AS \"duration_ms!\", attempted_at AS \"attempted_at!\",
+                next_attempt_at
+            FROM webhook_deliveries
+            JOIN webhooks ON webhooks.id = webhook_deliveries.webhook_id
+            WHERE webhooks.tenant_id = ? AND webhook_id = ? AND webhook_deliveries.id = ?",
+        tenant_id,
+        webhook_id,
+        id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(types::Delivery::try_from)
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenants;
+    use crate::types::sqlite::test_pool;
+    use crate::users;
+
+    #[rocket::async_test]
+    async fn webhooks_of_another_tenant_cannot_be_read_or_deleted() -> eyre::Result<()> {
+        let pool = test_pool().await?;
+        let user = users::sqlite::insert(&pool, &users::types::User::new("test|owner")).await?;
+        let tenant_a = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("a")).await?;
+        let tenant_b = tenants::sqlite::insert(&pool, &tenants::types::Tenant::new("b")).await?;
+        let scope_a = TenantScope::unchecked(&tenant_a.id);
+        let scope_b = TenantScope::unchecked(&tenant_b.id);
+        let webhook = insert(
+            &pool,
+            &scope_b,
+            &types::Webhook::new(
+                &tenant_b.id,
+                "https://example.com/hook",
+                "whsec_test",
+                vec!["*".to_string()],
+                &user.id,
+            ),
+        )
+        .await?;
+
+        assert!(find_all(&pool, &scope_a).await?.is_empty());
+        assert_eq!(find_one(&pool, &scope_a, &webhook.id).await?, None);
+        assert!(!delete(&pool, &scope_a, &webhook.id).await?);
+        assert_eq!(find_one(&pool, &scope_b, &webhook.id).await?, Some(webhook));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use crate::events::types::{type_matches, EventId};
+use crate::tenants::types::TenantId;
+use crate::types::id::define_id;
+use crate::users::types::UserId;
+
+use serde::{Deserialize, Serialize};
+
+define_id!(WebhookId, "webhook");
+define_id!(DeliveryId, "delivery");
+
+/// An endpoint of a tenant which the events of the tenant are posted to, signed with its secret.
+///
+/// The secret is handed to whoever created the webhook once so that they can verify deliveries;
+/// unlike tokens it's stored as is, since signing deliveries needs it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub tenant_id: TenantId,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    /// Patterns of the event types delivered to the webhook, such as `user.created` or `user.*`.
+    pub event_types: Vec<String>,
+    pub created_by: Option<UserId>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl Webhook {
+    pub fn new(
+        tenant_id: &TenantId,
+        url: &str,
+        secret: &str,
+        event_types: Vec<String>,
+        created_by: &UserId,
+    ) -> Self {
+        Self {
+            id: WebhookId::new(),
+            tenant_id: *tenant_id,
+            url: url.to_string(),
+            secret: secret.to_string(),
+            event_types,
+            created_by: Some(*created_by),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    /// Whether the webhook is subscribed to events of the type.
+    pub fn is_subscribed_to(&self, kind: &str) -> bool {
+        self.event_types
+            .iter()
+            .any(|pattern| type_matches(pattern, kind))
+    }
+}
+
+/// One attempt at posting an event to a webhook, kept as the webhook's delivery log.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Delivery {
+    pub id: DeliveryId,
+    pub webhook_id: WebhookId,
+    pub event_id: EventId,
+    pub event_type: String,
+    /// The body posted to the webhook, which is posted again when the delivery is redelivered.
+    #[serde(skip)]
+    pub payload: String,
+    /// How many times the event had been posted to the webhook with this attempt, counting manual
+    /// redeliveries.
+    pub attempt: i64,
+    /// The status the webhook responded with, if it responded at all.
+    pub status_code: Option<u16>,
+    /// Why the delivery failed, when it did.
+    pub error: Option<String>,
+    pub succeeded: bool,
+    pub duration_ms: i64,
+    pub attempted_at: chrono::NaiveDateTime,
+    /// When a failed delivery is retried, while it has attempts left; cleared once the retry is
+    /// made, which is recorded as a delivery of its own.
+    pub next_attempt_at: Option<chrono::NaiveDateTime>,
+}